@@ -0,0 +1,552 @@
+// 計測値の異常の検出
+// 予測した出力との差の外れ値・出力の急な低下・同じ値が続く状態・物理的にありえない値を
+// 時間帯ごとにまとめて重大度を付ける
+use std::fmt;
+use std::ops::Range;
+
+use chrono::{DateTime, Local};
+
+use crate::config::Site;
+use crate::error::Result;
+use crate::es::Field;
+use crate::model;
+use crate::timeseries::{self, TimeSeries};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyKind {
+    // 予測した出力との差が周りの時刻から外れている
+    Residual,
+    // 日射量は変わらないのに出力が急に下がった
+    Drop,
+    // 同じ値が続いている
+    Stuck,
+    // 物理的にありえない値
+    Impossible,
+}
+
+impl AnomalyKind {
+    // CSVなどで使う名前
+    pub fn name(self) -> &'static str {
+        match self {
+            AnomalyKind::Residual => "residual",
+            AnomalyKind::Drop => "drop",
+            AnomalyKind::Stuck => "stuck",
+            AnomalyKind::Impossible => "impossible",
+        }
+    }
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            AnomalyKind::Residual => "予測との差の外れ値",
+            AnomalyKind::Drop => "出力の急な低下",
+            AnomalyKind::Stuck => "値の固着",
+            AnomalyKind::Impossible => "ありえない値",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!(
+                "不明な重大度 \"{}\" です (指定可能な値: warning, critical)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Severity::Warning => "注意",
+            Severity::Critical => "重大",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct AnomalyParams {
+    // 外れ値を判定するときに中央値と中央絶対偏差を求める範囲(分)
+    pub window_minutes: usize,
+    // 頑健なzスコアの絶対値がこれ以上なら注意・重大
+    pub z_warning: f64,
+    pub z_critical: f64,
+    // 中央絶対偏差の下限(定格出力に対する割合)。予測がよく合うときに外れ値が出すぎないようにする
+    pub min_mad: f64,
+    // 1分間の出力の低下が定格出力のこの割合以上なら急な低下とする
+    pub drop_fraction: f64,
+    // 同じ値がこの時間以上続いたら注意、stuck_critical_minutes以上なら重大(分)
+    pub stuck_minutes: i64,
+    pub stuck_critical_minutes: i64,
+    // 大気外日射量をこの割合以上超えた日射量はありえない値とする
+    pub extraterrestrial_margin: f64,
+    // 負の値をありえない値とするときに計測の誤差として許す値(kwやkw/m^2)
+    pub negative_tolerance: f64,
+    // 太陽高度がこれより低い時刻は日射量のありえない値を判定しない(度)
+    // 地平線近くでは日射計の角度特性の誤差が大きく、大気外日射量も0に近いため
+    pub min_elevation: f64,
+}
+
+impl Default for AnomalyParams {
+    fn default() -> Self {
+        AnomalyParams {
+            window_minutes: 60,
+            z_warning: 4.0,
+            z_critical: 6.0,
+            min_mad: 0.005,
+            drop_fraction: 0.3,
+            stuck_minutes: 10,
+            stuck_critical_minutes: 60,
+            extraterrestrial_margin: 0.05,
+            negative_tolerance: 0.01,
+            min_elevation: 5.0,
+        }
+    }
+}
+
+// 異常と判定した時間帯
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub field: Field,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub severity: Severity,
+    // 種類ごとの大きさ(zスコアの絶対値、定格出力に対する低下の割合、続いた分数、最も外れた値)
+    pub magnitude: f64,
+}
+
+// 日射量を計測している日中とみなす大気外日射量の下限(kw/m^2)
+const MIN_DAYTIME_EXTRATERRESTRIAL: f64 = 0.1;
+
+// 同じ値が続くかを調べる項目
+const STUCK_FIELDS: [Field; 3] = [Field::SolarIrradiance, Field::AcPw, Field::DcPw];
+
+// 交流出力がパワーコンディショナの定格のこの割合以内で一定のときは頭打ちなので固着とみなさない
+const CLIPPING_TOLERANCE: f64 = 0.02;
+
+// seriesにはac_pw, dc_pw, solar_irradiance, air_temperatureが必要。欠損を補完した時刻は使わない
+// 品質管理で除く値もここで検出したい異常なので、品質管理で除かずに読み込んだ系列を渡す
+// 定格出力(rated_kw)が無ければ予測との差と出力の低下は調べず、固着とありえない値だけを調べる
+// 結果は開始時刻の順に並べる
+pub fn detect_anomalies(
+    site: &Site,
+    series: &TimeSeries,
+    params: &AnomalyParams,
+) -> Result<Vec<Anomaly>> {
+    // パワーコンディショナの定格が分からなければ頭打ちを固着から除けない
+    let inverter_kw = site.require_inverter_kw().ok();
+    let dts = series.dts();
+    let extraterrestrial = model::extraterrestrial_kw_series(site, dts);
+    let positions = model::solar_positions(site, dts);
+
+    let mut anomalies = Vec::new();
+    if let Some(rated_kw) = site.rated_kw {
+        detect_power_anomalies(
+            &mut anomalies,
+            site,
+            series,
+            rated_kw,
+            &extraterrestrial,
+            params,
+        )?;
+    }
+
+    // 日中に0以外の同じ値が続いている時間帯
+    for field in STUCK_FIELDS {
+        let values = series.valid(field)?;
+        for range in equal_runs(&values) {
+            let Some(value) = values[range.start] else {
+                continue;
+            };
+            let (start, end) = (dts[range.start], dts[range.end - 1]);
+            let minutes = ((end - start).num_seconds() + 1) / 60;
+            let clipping = field == Field::AcPw
+                && inverter_kw.is_some_and(|inverter_kw| {
+                    (value - inverter_kw).abs() <= inverter_kw * CLIPPING_TOLERANCE
+                });
+            if value == 0.0
+                || clipping
+                || minutes < params.stuck_minutes
+                || mean(&extraterrestrial[range.clone()]) < MIN_DAYTIME_EXTRATERRESTRIAL
+            {
+                continue;
+            }
+            let severity = if minutes >= params.stuck_critical_minutes {
+                Severity::Critical
+            } else {
+                Severity::Warning
+            };
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::Stuck,
+                field,
+                start,
+                end,
+                severity,
+                magnitude: minutes as f64,
+            });
+        }
+    }
+
+    // 負の日射量・出力と、大気外日射量を超える日射量
+    // 日射量は太陽高度がmin_elevationより低い時刻を判定しない
+    for field in [Field::SolarIrradiance, Field::AcPw, Field::DcPw] {
+        let valid = series.valid(field)?;
+        let values = series.require(field)?;
+        let impossible = |i: usize| {
+            if field == Field::SolarIrradiance && positions[i].elevation < params.min_elevation {
+                return false;
+            }
+            valid[i].is_some_and(|value| {
+                value < -params.negative_tolerance
+                    || (field == Field::SolarIrradiance
+                        && value
+                            > extraterrestrial[i] * (1.0 + params.extraterrestrial_margin)
+                                + params.negative_tolerance)
+            })
+        };
+        let mut i = 0;
+        while i < values.len() {
+            if !impossible(i) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            let mut worst = values[i];
+            while i < values.len() && impossible(i) {
+                if values[i].abs() > worst.abs() {
+                    worst = values[i];
+                }
+                i += 1;
+            }
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::Impossible,
+                field,
+                start: dts[start],
+                end: dts[i - 1],
+                severity: Severity::Critical,
+                magnitude: worst,
+            });
+        }
+    }
+
+    anomalies.sort_by_key(|anomaly| anomaly.start);
+    Ok(anomalies)
+}
+
+// 予測との差の外れ値と出力の急な低下
+fn detect_power_anomalies(
+    anomalies: &mut Vec<Anomaly>,
+    site: &Site,
+    series: &TimeSeries,
+    rated_kw: f64,
+    extraterrestrial: &[f64],
+    params: &AnomalyParams,
+) -> Result<()> {
+    let dts = series.dts();
+    let ac = series.valid(Field::AcPw)?;
+    let expected = model::expected_power(site, series)?
+        .iter()
+        .map(|estimate| estimate.map(|estimate| estimate.ac))
+        .collect::<Vec<Option<f64>>>();
+
+    // 実測と予測の両方がある時刻の1分平均値のうち、日中の分だけを使う
+    let mut minutes = Vec::new();
+    let mut ac_minutes = Vec::new();
+    let mut expected_minutes = Vec::new();
+    for range in timeseries::minute_ranges(dts) {
+        let pairs = range
+            .clone()
+            .filter_map(|i| Some((ac[i]?, expected[i]?)))
+            .collect::<Vec<(f64, f64)>>();
+        if pairs.is_empty() || mean(&extraterrestrial[range.clone()]) < MIN_DAYTIME_EXTRATERRESTRIAL
+        {
+            continue;
+        }
+        let n = pairs.len() as f64;
+        ac_minutes.push(pairs.iter().map(|(ac, _)| ac).sum::<f64>() / n / rated_kw);
+        expected_minutes.push(pairs.iter().map(|(_, e)| e).sum::<f64>() / n / rated_kw);
+        minutes.push(range);
+    }
+
+    // 予測との差の頑健なzスコア
+    let residuals = ac_minutes
+        .iter()
+        .zip(expected_minutes.iter())
+        .map(|(ac, expected)| ac - expected)
+        .collect::<Vec<f64>>();
+    let half = params.window_minutes / 2;
+    let flagged = (0..residuals.len())
+        .map(|m| {
+            let window = &residuals[m.saturating_sub(half)..(m + half + 1).min(residuals.len())];
+            let median = median(window);
+            let deviations = window
+                .iter()
+                .map(|r| (r - median).abs())
+                .collect::<Vec<f64>>();
+            let mad = (1.4826 * self::median(&deviations)).max(params.min_mad);
+            let z = ((residuals[m] - median) / mad).abs();
+            if z >= params.z_critical {
+                Some((Severity::Critical, z))
+            } else if z >= params.z_warning {
+                Some((Severity::Warning, z))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    merge_minutes(
+        anomalies,
+        dts,
+        &minutes,
+        &flagged,
+        AnomalyKind::Residual,
+        Field::AcPw,
+    );
+
+    // 前の分からの出力の低下のうち、予測した出力の低下では説明できないもの
+    let flagged = (0..minutes.len())
+        .map(|m| {
+            // 日中の分が連続していないときは比べない
+            if m == 0 || minutes[m - 1].end != minutes[m].start {
+                return None;
+            }
+            let drop = ac_minutes[m - 1] - ac_minutes[m];
+            let unexplained = drop - (expected_minutes[m - 1] - expected_minutes[m]);
+            if drop < params.drop_fraction || unexplained < params.drop_fraction {
+                return None;
+            }
+            let severity = if drop >= params.drop_fraction * 2.0 {
+                Severity::Critical
+            } else {
+                Severity::Warning
+            };
+            Some((severity, drop))
+        })
+        .collect::<Vec<_>>();
+    merge_minutes(
+        anomalies,
+        dts,
+        &minutes,
+        &flagged,
+        AnomalyKind::Drop,
+        Field::AcPw,
+    );
+    Ok(())
+}
+
+// 判定した分のうち連続するものを1つの時間帯にまとめる
+fn merge_minutes(
+    anomalies: &mut Vec<Anomaly>,
+    dts: &[DateTime<Local>],
+    minutes: &[Range<usize>],
+    flagged: &[Option<(Severity, f64)>],
+    kind: AnomalyKind,
+    field: Field,
+) {
+    let mut current: Option<Anomaly> = None;
+    for (m, flag) in flagged.iter().enumerate() {
+        let contiguous = m > 0 && minutes[m - 1].end == minutes[m].start;
+        match (flag, current.as_mut()) {
+            (Some((severity, magnitude)), Some(anomaly)) if contiguous => {
+                anomaly.end = dts[minutes[m].end - 1];
+                anomaly.severity = anomaly.severity.max(*severity);
+                anomaly.magnitude = anomaly.magnitude.max(*magnitude);
+            }
+            (Some((severity, magnitude)), _) => {
+                anomalies.extend(current.take());
+                current = Some(Anomaly {
+                    kind,
+                    field,
+                    start: dts[minutes[m].start],
+                    end: dts[minutes[m].end - 1],
+                    severity: *severity,
+                    magnitude: *magnitude,
+                });
+            }
+            (None, _) => anomalies.extend(current.take()),
+        }
+    }
+    anomalies.extend(current);
+}
+
+// 同じ値が続く範囲。値の無い時刻はNoneが続く範囲になる
+fn equal_runs(values: &[Option<f64>]) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for (i, value) in values.iter().enumerate() {
+        match runs.last_mut() {
+            Some(run) if values[run.start] == *value => run.end = i + 1,
+            _ => runs.push(i..i + 1),
+        }
+    }
+    runs
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len();
+    if n.is_multiple_of(2) {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    } else {
+        sorted[n / 2]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    // 10時から2時間の1秒ごとの系列。日射量は少しずつ変わり、直流出力は日射量に比例する
+    // 交流出力は予測した出力をacで書き換えた値
+    fn series(site: &Site, ac: impl Fn(usize, f64) -> f64) -> TimeSeries {
+        let start = Local.with_ymd_and_hms(2022, 10, 9, 10, 0, 0).unwrap();
+        let len = 7200;
+        let dts = (0..len)
+            .map(|i| start + Duration::seconds(i as i64))
+            .collect::<Vec<_>>();
+        let irradiance = (0..len)
+            .map(|i| 0.6 + 0.1 * (i as f64 / 600.0).sin())
+            .collect::<Vec<f64>>();
+        let dc = irradiance.iter().map(|v| v * 5.0).collect::<Vec<f64>>();
+        let series = TimeSeries::new(dts)
+            .with_column(Field::SolarIrradiance, irradiance)
+            .unwrap()
+            .with_column(Field::AirTemperature, vec![20.0; len])
+            .unwrap()
+            .with_column(Field::DcPw, dc)
+            .unwrap();
+        let rated = Site {
+            rated_kw: Some(5.0),
+            ..site.clone()
+        };
+        let expected = model::expected_power(&rated, &series).unwrap();
+        let values = (0..len)
+            .map(|i| ac(i, expected[i].unwrap().ac))
+            .collect::<Vec<f64>>();
+        series.with_column(Field::AcPw, values).unwrap()
+    }
+
+    fn site() -> Site {
+        let mut site = Site {
+            rated_kw: Some(5.0),
+            ..Site::default()
+        };
+        site.pv.inverter_kw = Some(4.0);
+        site
+    }
+
+    fn of_kind(anomalies: &[Anomaly], kind: AnomalyKind) -> Vec<Anomaly> {
+        anomalies
+            .iter()
+            .filter(|anomaly| anomaly.kind == kind)
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn stuck_value_but_not_clipping() {
+        // 20分から20分間は3kWのまま、60分から20分間はパワーコンディショナの定格の4kWで頭打ち
+        let site = site();
+        let series = series(&site, |i, expected| match i / 60 {
+            20..=39 => 3.0,
+            60..=79 => 4.0,
+            _ => expected,
+        });
+        let anomalies = detect_anomalies(&site, &series, &AnomalyParams::default()).unwrap();
+        let stuck = of_kind(&anomalies, AnomalyKind::Stuck);
+        assert_eq!(stuck.len(), 1);
+        assert_eq!(stuck[0].field, Field::AcPw);
+        assert_eq!(stuck[0].start, series.dts()[20 * 60]);
+        assert_eq!(stuck[0].end, series.dts()[40 * 60 - 1]);
+        assert_eq!(stuck[0].severity, Severity::Warning);
+        assert_eq!(stuck[0].magnitude, 20.0);
+    }
+
+    #[test]
+    fn drop_and_residuals() {
+        // 60分から5分間だけ出力が0になる。予測との差の外れ値は5分をまとめた1つの時間帯になる
+        let site = site();
+        let series = series(&site, |i, expected| {
+            if (60..65).contains(&(i / 60)) {
+                0.0
+            } else {
+                expected
+            }
+        });
+        let anomalies = detect_anomalies(&site, &series, &AnomalyParams::default()).unwrap();
+
+        let drops = of_kind(&anomalies, AnomalyKind::Drop);
+        assert_eq!(drops.len(), 1);
+        assert_eq!(drops[0].start, series.dts()[60 * 60]);
+        assert_eq!(drops[0].end, series.dts()[61 * 60 - 1]);
+
+        let residuals = of_kind(&anomalies, AnomalyKind::Residual);
+        assert_eq!(residuals.len(), 1);
+        assert_eq!(residuals[0].start, series.dts()[60 * 60]);
+        assert_eq!(residuals[0].end, series.dts()[65 * 60 - 1]);
+        assert_eq!(residuals[0].severity, Severity::Critical);
+    }
+
+    #[test]
+    fn impossible_values_without_rated_kw() {
+        // 定格出力が無くても固着とありえない値は調べる
+        let site = Site::default();
+        let mut series = series(&site, |i, expected| {
+            if (30 * 60..30 * 60 + 10).contains(&i) {
+                -1.0
+            } else {
+                expected
+            }
+        });
+        let mut irradiance = series.require(Field::SolarIrradiance).unwrap().to_vec();
+        irradiance[100..105].fill(2.0);
+        series
+            .insert_column(Field::SolarIrradiance, irradiance)
+            .unwrap();
+
+        let anomalies = detect_anomalies(&site, &series, &AnomalyParams::default()).unwrap();
+        assert!(of_kind(&anomalies, AnomalyKind::Residual).is_empty());
+        let impossible = of_kind(&anomalies, AnomalyKind::Impossible);
+        assert_eq!(impossible.len(), 2);
+        assert_eq!(impossible[0].field, Field::SolarIrradiance);
+        assert_eq!(impossible[0].start, series.dts()[100]);
+        assert_eq!(impossible[0].end, series.dts()[104]);
+        assert_eq!(impossible[0].magnitude, 2.0);
+        assert_eq!(impossible[1].field, Field::AcPw);
+        assert_eq!(impossible[1].start, series.dts()[30 * 60]);
+        assert_eq!(impossible[1].end, series.dts()[30 * 60 + 9]);
+        assert_eq!(impossible[1].magnitude, -1.0);
+    }
+}
@@ -0,0 +1,349 @@
+// 蓄電池の残量(%)の分析
+// 日ごとの充電量・放電量と等価サイクル数、満充電・空の時間、放電の深さの分布、発電量との相関を求める
+// 欠損を補完した時刻と品質管理で除いた値は使わない
+use std::ops::Range;
+
+use chrono::{DateTime, Local, NaiveDate, Timelike};
+
+use crate::analysis::{correlation, energy};
+use crate::error::Result;
+use crate::es::Field;
+use crate::timeseries::{self, TimeSeries};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct BatteryParams {
+    // 残量がこれ以上なら満充電、これ以下なら空とみなす(%)
+    pub full_soc: f64,
+    pub empty_soc: f64,
+    // 充電から放電、放電から充電に変わったとみなす残量の変化(%)。これより小さい揺れは無視する
+    pub min_swing: f64,
+    // 発電量の積算で、これより間隔が空いたところは積算しない(秒)
+    pub max_gap_seconds: i64,
+}
+
+impl Default for BatteryParams {
+    fn default() -> Self {
+        BatteryParams {
+            full_soc: 95.0,
+            empty_soc: 10.0,
+            min_swing: 2.0,
+            max_gap_seconds: 60,
+        }
+    }
+}
+
+// 残量が減り続けた期間
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct DischargeCycle {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub from_soc: f64,
+    pub to_soc: f64,
+}
+
+impl DischargeCycle {
+    // 放電の深さ(%)
+    pub fn depth(&self) -> f64 {
+        self.from_soc - self.to_soc
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct DayBattery {
+    pub date: NaiveDate,
+    // 残量の値が無い日はNone
+    pub min_soc: Option<f64>,
+    pub max_soc: Option<f64>,
+    // 残量の増加・減少の合計(%)。min_swingより小さい揺れは数えない
+    pub charged: f64,
+    pub discharged: f64,
+    pub full_seconds: i64,
+    pub empty_seconds: i64,
+    // 交流出力の積算値(kWh)
+    pub pv_kwh: f64,
+}
+
+impl DayBattery {
+    // 放電量を容量で割った等価サイクル数
+    pub fn equivalent_full_cycles(&self) -> f64 {
+        self.discharged / 100.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[non_exhaustive]
+pub struct BatteryAnalysis {
+    pub days: Vec<DayBattery>,
+    // 開始時刻の順。期間の終わりで放電の途中のものは含めない
+    pub cycles: Vec<DischargeCycle>,
+    // 日ごとの発電量と充電量の相関係数
+    pub daily_correlation: Option<f64>,
+    // 1時間ごとの平均出力と残量の変化の相関係数
+    pub hourly_correlation: Option<f64>,
+}
+
+impl BatteryAnalysis {
+    pub fn equivalent_full_cycles(&self) -> f64 {
+        self.days
+            .iter()
+            .fold(0.0, |sum, day| sum + day.equivalent_full_cycles())
+    }
+
+    // 放電の深さを幅width(%)で区切った件数。(区分の下端, 件数) を0%から100%まで返す
+    // widthが正でなければ空
+    pub fn depth_histogram(&self, width: f64) -> Vec<(f64, usize)> {
+        if width.is_nan() || width <= 0.0 {
+            return Vec::new();
+        }
+        let bins = (100.0 / width).ceil() as usize;
+        let mut counts = vec![0; bins];
+        for cycle in self.cycles.iter() {
+            let bin = ((cycle.depth() / width) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (i as f64 * width, count))
+            .collect()
+    }
+}
+
+// seriesにはremaining_storage_battery_capacityとac_pwが必要
+pub fn analyze_battery(series: &TimeSeries, params: &BatteryParams) -> Result<BatteryAnalysis> {
+    let soc = series.valid(Field::RemainingStorageBatteryCapacity)?;
+    let ac = series.valid(Field::AcPw)?;
+    let dts = series.dts();
+
+    let days = series
+        .day_ranges()
+        .into_iter()
+        .map(|(date, range)| analyze_day(dts, &soc, &ac, date, range, params))
+        .collect::<Vec<_>>();
+
+    let (pv, charged): (Vec<f64>, Vec<f64>) = days
+        .iter()
+        .filter(|day| day.max_soc.is_some())
+        .map(|day| (day.pv_kwh, day.charged))
+        .unzip();
+    let daily_correlation = correlation(&pv, &charged);
+
+    // 残量は1%刻みで1分では変化が見えにくいので1時間ごとにまとめる。残量の値が2つ以上ある時間だけを使う
+    let (power, change): (Vec<f64>, Vec<f64>) = hour_ranges(dts)
+        .into_iter()
+        .filter_map(|range| {
+            let measured = soc[range.clone()].iter().flatten().collect::<Vec<_>>();
+            let power = ac[range].iter().flatten().collect::<Vec<_>>();
+            if measured.len() < 2 || power.is_empty() {
+                return None;
+            }
+            let mean = power.iter().cloned().sum::<f64>() / power.len() as f64;
+            Some((mean, measured[measured.len() - 1] - measured[0]))
+        })
+        .unzip();
+    let hourly_correlation = correlation(&power, &change);
+
+    Ok(BatteryAnalysis {
+        days,
+        cycles: discharge_cycles(dts, &soc, params.min_swing),
+        daily_correlation,
+        hourly_correlation,
+    })
+}
+
+// 同じ日の同じ時の分をまとめた範囲
+fn hour_ranges(dts: &[DateTime<Local>]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for range in timeseries::minute_ranges(dts) {
+        match ranges.last_mut() {
+            Some(last)
+                if dts[last.start].date_naive() == dts[range.start].date_naive()
+                    && dts[last.start].hour() == dts[range.start].hour() =>
+            {
+                last.end = range.end
+            }
+            _ => ranges.push(range),
+        }
+    }
+    ranges
+}
+
+fn analyze_day(
+    dts: &[DateTime<Local>],
+    soc: &[Option<f64>],
+    ac: &[Option<f64>],
+    date: NaiveDate,
+    range: Range<usize>,
+    params: &BatteryParams,
+) -> DayBattery {
+    let measured = soc[range.clone()]
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<f64>>();
+    let (min_soc, max_soc) = measured.iter().fold((None, None), |(m, n), v| {
+        (
+            Some(m.map_or(*v, |m: f64| m.min(*v))),
+            Some(n.map_or(*v, |n: f64| n.max(*v))),
+        )
+    });
+    // 向きが変わった点の間の変化を足す。最後の点は向きが変わる前の最も進んだ値
+    let (points, last) = turning_points(&soc[range.clone()], params.min_swing);
+    let (mut charged, mut discharged) = (0.0, 0.0);
+    for pair in points
+        .iter()
+        .chain(last.iter())
+        .collect::<Vec<_>>()
+        .windows(2)
+    {
+        let diff = pair[1].1 - pair[0].1;
+        if diff > 0.0 {
+            charged += diff;
+        } else {
+            discharged -= diff;
+        }
+    }
+    // 条件を満たす値の時刻と直前の値の時刻の間の秒数を足す。間隔が空いたところは数えない
+    let seconds = |pred: &dyn Fn(f64) -> bool| {
+        range
+            .clone()
+            .filter(|i| *i > range.start)
+            .filter_map(|i| {
+                let (_, v) = (soc[i - 1]?, soc[i]?);
+                let gap = (dts[i] - dts[i - 1]).num_seconds();
+                (pred(v) && gap <= params.max_gap_seconds).then_some(gap)
+            })
+            .sum::<i64>()
+    };
+
+    DayBattery {
+        date,
+        min_soc,
+        max_soc,
+        charged,
+        discharged,
+        full_seconds: seconds(&|v| v >= params.full_soc),
+        empty_seconds: seconds(&|v| v <= params.empty_soc),
+        pv_kwh: energy::integrate(dts, ac, range, params.max_gap_seconds).0,
+    }
+}
+
+// 残量の山から谷までを放電の1回とする
+fn discharge_cycles(
+    dts: &[DateTime<Local>],
+    soc: &[Option<f64>],
+    min_swing: f64,
+) -> Vec<DischargeCycle> {
+    turning_points(soc, min_swing)
+        .0
+        .windows(2)
+        .filter(|pair| pair[0].1 > pair[1].1)
+        .map(|pair| DischargeCycle {
+            start: dts[pair[0].0],
+            end: dts[pair[1].0],
+            from_soc: pair[0].1,
+            to_soc: pair[1].1,
+        })
+        .collect()
+}
+
+// 系列の番号と残量
+type SocPoint = (usize, f64);
+
+// 残量の向きが変わった点の一覧と、最後の向きで最も進んだ点を返す
+// min_swingより小さい揺れでは向きが変わったとみなさない。最初の点は最初の値
+fn turning_points(soc: &[Option<f64>], min_swing: f64) -> (Vec<SocPoint>, Option<SocPoint>) {
+    // 今の向きで最も進んだ値
+    let mut extreme: Option<SocPoint> = None;
+    // Some(true)なら充電中、Some(false)なら放電中
+    let mut rising: Option<bool> = None;
+    let mut points = Vec::new();
+
+    for (i, v) in soc
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| v.as_ref().map(|v| (i, *v)))
+    {
+        let Some((_, extreme_v)) = extreme else {
+            extreme = Some((i, v));
+            continue;
+        };
+        match rising {
+            Some(true) if v > extreme_v => extreme = Some((i, v)),
+            Some(false) if v < extreme_v => extreme = Some((i, v)),
+            Some(true) | None if v <= extreme_v - min_swing => {
+                points.extend(extreme);
+                rising = Some(false);
+                extreme = Some((i, v));
+            }
+            Some(false) | None if v >= extreme_v + min_swing => {
+                points.extend(extreme);
+                rising = Some(true);
+                extreme = Some((i, v));
+            }
+            _ => {}
+        }
+    }
+    (points, extreme.filter(|_| rising.is_some()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    // 10秒ごとの残量の系列。発電はしていない
+    fn series(soc: &[f64]) -> TimeSeries {
+        let start = Local.with_ymd_and_hms(2022, 10, 9, 0, 0, 0).unwrap();
+        let dts = (0..soc.len())
+            .map(|i| start + Duration::seconds(10 * i as i64))
+            .collect::<Vec<_>>();
+        TimeSeries::new(dts)
+            .with_column(Field::RemainingStorageBatteryCapacity, soc.to_vec())
+            .unwrap()
+            .with_column(Field::AcPw, vec![0.0; soc.len()])
+            .unwrap()
+    }
+
+    #[test]
+    fn noise_is_not_counted() {
+        // 1%の揺れは充電・放電とみなさない
+        let soc = (0..100)
+            .map(|i| if i % 2 == 0 { 50.0 } else { 51.0 })
+            .collect::<Vec<f64>>();
+        let analysis = analyze_battery(&series(&soc), &BatteryParams::default()).unwrap();
+        let day = analysis.days[0];
+        assert_eq!((day.charged, day.discharged), (0.0, 0.0));
+        assert_eq!(analysis.equivalent_full_cycles(), 0.0);
+        assert!(analysis.cycles.is_empty());
+    }
+
+    #[test]
+    fn cycles_and_durations() {
+        // 50%から満充電の100%まで上がり、その後40%まで下がってから60%に戻る。途中に1%の揺れがある
+        let mut soc = Vec::new();
+        soc.extend((50..=100).map(f64::from));
+        soc.extend([99.0, 100.0, 99.0, 100.0]);
+        soc.extend((40..100).rev().map(f64::from));
+        soc.extend([41.0, 40.0]);
+        soc.extend((41..=60).map(f64::from));
+        let analysis = analyze_battery(&series(&soc), &BatteryParams::default()).unwrap();
+        let day = analysis.days[0];
+        assert_eq!(day.charged, 70.0);
+        assert_eq!(day.discharged, 60.0);
+        assert!((analysis.equivalent_full_cycles() - 0.6).abs() < 1e-9);
+        assert_eq!(analysis.cycles.len(), 1);
+        assert_eq!(analysis.cycles[0].depth(), 60.0);
+        // 95%以上の値は100%の前後に11個と揺れの4個で15個、10秒ごとなので150秒
+        assert_eq!(day.full_seconds, 150);
+
+        let histogram = analysis.depth_histogram(20.0);
+        assert_eq!(histogram.len(), 5);
+        assert_eq!(histogram[3], (60.0, 1));
+        assert!(analysis.depth_histogram(0.0).is_empty());
+        assert!(analysis.depth_histogram(-10.0).is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+// 晴天の日の南中時刻付近の実測/晴天日射量の比から、日射計の感度の経時変化(汚れ・劣化)を調べる
+use chrono::{DateTime, Local, NaiveDate};
+
+use super::irradiance::IrradianceComparison;
+use super::sky::{DaySky, SkyCondition};
+use crate::config::Site;
+use crate::model;
+use crate::timeseries;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct DriftParams {
+    // 南中時刻の前後何分の値を使うか
+    pub noon_window_minutes: i64,
+    // 変化点の前後それぞれに必要な晴天の日数
+    pub min_segment_days: usize,
+    // 変化点とみなす比の平均値の差の下限
+    pub min_shift: f64,
+}
+
+impl Default for DriftParams {
+    fn default() -> Self {
+        DriftParams {
+            noon_window_minutes: 30,
+            min_segment_days: 5,
+            min_shift: 0.03,
+        }
+    }
+}
+
+// 晴天の日1日分の比
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct NoonRatio {
+    pub date: NaiveDate,
+    pub solar_noon: DateTime<Local>,
+    // 南中時刻の前後の積算値の比(実測/晴天)
+    pub ratio: f64,
+}
+
+// 比の一次の傾向
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct DriftTrend {
+    // 最初の晴天の日の比
+    pub intercept: f64,
+    // 1年あたりの比の変化
+    pub slope_per_year: f64,
+}
+
+impl DriftTrend {
+    // 最初の晴天の日からdays日後の比
+    pub fn at(&self, days: f64) -> f64 {
+        self.intercept + self.slope_per_year * days / 365.25
+    }
+}
+
+// 比の平均値が変わった日
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ChangePoint {
+    // 変化した後の最初の晴天の日
+    pub date: NaiveDate,
+    pub before: f64,
+    pub after: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct CalibrationDrift {
+    pub ratios: Vec<NoonRatio>,
+    // 晴天の日が2日未満のときはNone
+    pub trend: Option<DriftTrend>,
+    pub change_points: Vec<ChangePoint>,
+}
+
+pub fn analyze_drift(
+    site: &Site,
+    comparison: &IrradianceComparison,
+    days: &[DaySky],
+    params: &DriftParams,
+) -> CalibrationDrift {
+    let window = params.noon_window_minutes * 60;
+    let ratios = timeseries::day_ranges(&comparison.dts)
+        .into_iter()
+        .filter(|(date, _)| {
+            days.iter()
+                .any(|day| day.date == *date && day.condition == SkyCondition::Clear)
+        })
+        .filter_map(|(date, range)| {
+            let solar_noon = model::sun_times(site, date).solar_noon;
+            let (measured, clear_sky) = range
+                .filter(|i| (comparison.dts[*i] - solar_noon).num_seconds().abs() <= window)
+                .fold((0.0, 0.0), |(m, c), i| {
+                    (m + comparison.measured[i], c + comparison.clear_sky[i])
+                });
+            if clear_sky <= 0.0 {
+                return None;
+            }
+            Some(NoonRatio {
+                date,
+                solar_noon,
+                ratio: measured / clear_sky,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let trend = fit_trend(&ratios);
+    let values = ratios.iter().map(|ratio| ratio.ratio).collect::<Vec<f64>>();
+    let mut splits = Vec::new();
+    find_change_points(&values, 0, params, &mut splits);
+    splits.sort_unstable();
+    // 隣り合う変化点の間の平均値を前後の値にする
+    let bounds = std::iter::once(0)
+        .chain(splits.iter().cloned())
+        .chain(std::iter::once(values.len()))
+        .collect::<Vec<usize>>();
+    let change_points = splits
+        .iter()
+        .enumerate()
+        .map(|(k, split)| ChangePoint {
+            date: ratios[*split].date,
+            before: mean(&values[bounds[k]..bounds[k + 1]]),
+            after: mean(&values[bounds[k + 1]..bounds[k + 2]]),
+        })
+        .collect();
+
+    CalibrationDrift {
+        ratios,
+        trend,
+        change_points,
+    }
+}
+
+// 最小二乗法で日数に対する一次式を当てはめる
+fn fit_trend(ratios: &[NoonRatio]) -> Option<DriftTrend> {
+    let first = ratios.first()?.date;
+    if ratios.len() < 2 {
+        return None;
+    }
+    let xs = ratios
+        .iter()
+        .map(|ratio| (ratio.date - first).num_days() as f64)
+        .collect::<Vec<f64>>();
+    let ys = ratios.iter().map(|ratio| ratio.ratio).collect::<Vec<f64>>();
+    let (mean_x, mean_y) = (mean(&xs), mean(&ys));
+    let (sxy, sxx) = xs
+        .iter()
+        .zip(ys.iter())
+        .fold((0.0, 0.0), |(sxy, sxx), (x, y)| {
+            (
+                sxy + (x - mean_x) * (y - mean_y),
+                sxx + (x - mean_x).powi(2),
+            )
+        });
+    if sxx <= 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some(DriftTrend {
+        intercept: mean_y - slope * mean_x,
+        slope_per_year: slope * 365.25,
+    })
+}
+
+// 二分割法。二乗誤差が最も小さくなる位置で分け、前後の平均値の差が十分大きければ
+// それぞれをさらに分ける。見つけた位置(valuesの先頭からの番号)をsplitsに追加する
+fn find_change_points(
+    values: &[f64],
+    offset: usize,
+    params: &DriftParams,
+    splits: &mut Vec<usize>,
+) {
+    let min_len = params.min_segment_days.max(1);
+    if values.len() < min_len * 2 {
+        return;
+    }
+    let best = (min_len..=values.len() - min_len)
+        .map(|k| (k, sse(&values[..k]) + sse(&values[k..])))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    let Some((k, _)) = best else {
+        return;
+    };
+    if (mean(&values[..k]) - mean(&values[k..])).abs() < params.min_shift {
+        return;
+    }
+    splits.push(offset + k);
+    find_change_points(&values[..k], offset, params, splits);
+    find_change_points(&values[k..], offset + k, params, splits);
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// 平均値からの偏差の二乗和
+fn sse(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    values.iter().map(|v| (v - mean).powi(2)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_step_change() {
+        // 11日目から比が1.00から0.95に下がった(日射計が汚れた)場合
+        let values = (0..20)
+            .map(|i| if i < 10 { 1.0 } else { 0.95 } + if i % 2 == 0 { 0.002 } else { -0.002 })
+            .collect::<Vec<f64>>();
+        let mut splits = Vec::new();
+        find_change_points(&values, 0, &DriftParams::default(), &mut splits);
+        assert_eq!(splits, vec![10]);
+        assert!((mean(&values[..10]) - 1.0).abs() < 1e-9);
+        assert!((mean(&values[10..]) - 0.95).abs() < 1e-9);
+
+        // 差がmin_shiftより小さければ変化点としない
+        let values = (0..20)
+            .map(|i| if i < 10 { 1.0 } else { 0.99 })
+            .collect::<Vec<f64>>();
+        let mut splits = Vec::new();
+        find_change_points(&values, 0, &DriftParams::default(), &mut splits);
+        assert!(splits.is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+// パワーコンディショナの定格で交流出力が頭打ちになった時間帯の検出と、失われた発電量の推定
+// 頭打ちにならなかった場合の出力は、実測の日射量と気温から予測した出力を
+// 頭打ちになっていない時刻の実測値に合わせて補正して求める
+use chrono::{DateTime, Local, NaiveDate};
+
+use crate::analysis::energy;
+use crate::config::Site;
+use crate::error::Result;
+use crate::es::Field;
+use crate::model;
+use crate::timeseries::TimeSeries;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ClippingParams {
+    // 交流出力が定格のこの割合以内なら頭打ちとみなす
+    pub tolerance: f64,
+    // 頭打ちにならなかった場合の出力が定格をこの割合以上超えた時刻だけを数える
+    pub min_excess: f64,
+    // これより短い時間帯は除く(秒)
+    pub min_duration_seconds: i64,
+    // 発電量の積算で、これより間隔が空いたところは積算しない(秒)
+    pub max_gap_seconds: i64,
+}
+
+impl Default for ClippingParams {
+    fn default() -> Self {
+        ClippingParams {
+            tolerance: 0.02,
+            min_excess: 0.02,
+            min_duration_seconds: 60,
+            max_gap_seconds: 60,
+        }
+    }
+}
+
+// 頭打ちになった時間帯
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ClippingPeriod {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    // 失われた発電量(kWh)
+    pub lost_kwh: f64,
+    // 頭打ちにならなかった場合の出力の最大値(kW)
+    pub peak_potential_kw: f64,
+}
+
+impl ClippingPeriod {
+    pub fn duration_seconds(&self) -> i64 {
+        (self.end - self.start).num_seconds() + 1
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct DayClipping {
+    pub date: NaiveDate,
+    // 交流出力の積算値(kWh)
+    pub ac_kwh: f64,
+    pub lost_kwh: f64,
+    // 予測した出力に掛けた補正の倍率
+    pub scale: f64,
+    pub periods: Vec<ClippingPeriod>,
+}
+
+impl DayClipping {
+    pub fn clipped_seconds(&self) -> i64 {
+        self.periods
+            .iter()
+            .map(|period| period.duration_seconds())
+            .sum()
+    }
+
+    // 発電できたはずの量に対する失われた量の割合
+    pub fn lost_fraction(&self) -> f64 {
+        self.lost_kwh / (self.ac_kwh + self.lost_kwh)
+    }
+}
+
+// seriesにはac_pw, solar_irradiance, air_temperatureが必要
+pub fn detect_clipping(
+    site: &Site,
+    series: &TimeSeries,
+    params: &ClippingParams,
+) -> Result<Vec<DayClipping>> {
+    let limit = site.require_inverter_kw()?;
+    let dts = series.dts();
+    let ac = series.valid(Field::AcPw)?;
+    // 頭打ちにならなかった場合の交流出力。予測できない時刻はNone
+    let potential = model::expected_power(site, series)?
+        .iter()
+        .map(|estimate| estimate.map(|estimate| estimate.dc * site.pv.inverter_efficiency))
+        .collect::<Vec<Option<f64>>>();
+    // 両方の値がある時刻の (交流出力, 頭打ちにならなかった場合の出力)
+    let sample = |i: usize| Some((ac[i]?, potential[i]?));
+    let at_limit = |ac: f64| (ac - limit).abs() <= limit * params.tolerance;
+
+    let days = series
+        .day_ranges()
+        .into_iter()
+        .map(|(date, range)| {
+            // 頭打ちになっていない日中の時刻で予測値を実測値に合わせる
+            let (actual, predicted) = range
+                .clone()
+                .filter_map(sample)
+                .filter(|(ac, potential)| !at_limit(*ac) && *ac > 0.0 && *potential > limit * 0.05)
+                .fold((0.0, 0.0), |(a, p), (ac, potential)| {
+                    (a + ac, p + potential)
+                });
+            let scale = if predicted > 0.0 {
+                actual / predicted
+            } else {
+                1.0
+            };
+
+            let clipped = |i: usize| {
+                sample(i).is_some_and(|(ac, potential)| {
+                    at_limit(ac) && potential * scale >= limit * (1.0 + params.min_excess)
+                })
+            };
+            // 失われた出力(kW)。時刻の間隔に合わせて積算する
+            let lost = range
+                .clone()
+                .map(|i| sample(i).map(|(ac, potential)| (potential * scale - ac).max(0.0)))
+                .collect::<Vec<Option<f64>>>();
+            let day_dts = &dts[range.clone()];
+            let mut periods = Vec::new();
+            let mut i = range.start;
+            while i < range.end {
+                if !clipped(i) {
+                    i += 1;
+                    continue;
+                }
+                let start = i;
+                while i < range.end && clipped(i) {
+                    i += 1;
+                }
+                let period = ClippingPeriod {
+                    start: dts[start],
+                    end: dts[i - 1],
+                    lost_kwh: energy::integrate(
+                        day_dts,
+                        &lost,
+                        start - range.start + 1..i - range.start,
+                        params.max_gap_seconds,
+                    )
+                    .0,
+                    peak_potential_kw: (start..i)
+                        .filter_map(sample)
+                        .map(|(_, potential)| potential * scale)
+                        .fold(f64::NAN, f64::max),
+                };
+                if period.duration_seconds() >= params.min_duration_seconds {
+                    periods.push(period);
+                }
+            }
+
+            DayClipping {
+                date,
+                ac_kwh: energy::integrate(dts, &ac, range, params.max_gap_seconds).0,
+                lost_kwh: periods
+                    .iter()
+                    .fold(0.0, |sum, period| sum + period.lost_kwh),
+                scale,
+                periods,
+            }
+        })
+        .collect();
+
+    Ok(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn flat_plateau_is_clipping() {
+        // 11時から13時まで日射量が0.3から0.9kW/m^2まで上がって下がる日
+        let start = Local.with_ymd_and_hms(2022, 10, 9, 11, 0, 0).unwrap();
+        let len = 7200;
+        let dts = (0..len)
+            .map(|i| start + Duration::seconds(i))
+            .collect::<Vec<_>>();
+        let irradiance = (0..len)
+            .map(|i| 0.9 - 0.6 * (i - len / 2).abs() as f64 / (len / 2) as f64)
+            .collect::<Vec<f64>>();
+        let series = TimeSeries::new(dts)
+            .with_column(Field::SolarIrradiance, irradiance)
+            .unwrap()
+            .with_column(Field::AirTemperature, vec![20.0; len as usize])
+            .unwrap();
+        let mut site = Site {
+            rated_kw: Some(10.0),
+            ..Site::default()
+        };
+        let potential = model::expected_power(&site, &series)
+            .unwrap()
+            .iter()
+            .map(|estimate| estimate.unwrap().dc * site.pv.inverter_efficiency)
+            .collect::<Vec<f64>>();
+
+        // 予測した出力の最大値の9割で頭打ちになるパワーコンディショナ
+        let limit = potential.iter().fold(0.0, |max: f64, p| max.max(*p)) * 0.9;
+        site.pv.inverter_kw = Some(limit);
+        let ac = potential.iter().map(|p| p.min(limit)).collect::<Vec<f64>>();
+        let series = series.with_column(Field::AcPw, ac).unwrap();
+
+        let days = detect_clipping(&site, &series, &ClippingParams::default()).unwrap();
+        assert_eq!(days.len(), 1);
+        let day = &days[0];
+        assert!((day.scale - 1.0).abs() < 1e-9);
+        assert_eq!(day.periods.len(), 1);
+
+        // 定格をmin_excess以上超えた時刻が頭打ちの時間帯で、その間の超えた分が失われた発電量
+        let clipped = (0..len as usize)
+            .filter(|i| potential[*i] >= limit * 1.02)
+            .collect::<Vec<usize>>();
+        let (first, last) = (clipped[0], clipped[clipped.len() - 1]);
+        let period = day.periods[0];
+        assert_eq!(period.start, series.dts()[first]);
+        assert_eq!(period.end, series.dts()[last]);
+        let lost_kwh = (first + 1..=last)
+            .map(|i| (potential[i - 1] + potential[i] - 2.0 * limit) / 2.0 / 3600.0)
+            .sum::<f64>();
+        assert!(lost_kwh > 0.0);
+        assert!((period.lost_kwh - lost_kwh).abs() < 1e-9);
+        assert!((day.lost_kwh - lost_kwh).abs() < 1e-9);
+        assert!((period.peak_potential_kw - limit / 0.9).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,243 @@
+// 実測の日射量と大気外日射量の相互相関から、ロガーの時計のずれを推定する
+use chrono::{DateTime, Duration, Local, NaiveDate, Timelike};
+
+use super::correlation;
+use super::irradiance::IrradianceComparison;
+use super::sky::{self, ClearSkyCriteria, SkyCondition};
+use crate::config::Site;
+use crate::error::{Error, Result};
+use crate::model;
+use crate::timeseries;
+
+// 1日分の推定結果
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ClockOffset {
+    pub date: NaiveDate,
+    // 記録された時刻に足すと正しい時刻になる秒数
+    pub offset_seconds: i64,
+    // ずらした後の相関係数
+    pub correlation: f64,
+}
+
+// 1秒刻みで細かく探す範囲(秒)。1分刻みで求めたずれの前後を探す
+const REFINE_SECONDS: i64 = 90;
+
+// 晴天の日ごとのずれ。晴天ではない日は雲で日射量が変わるので推定せず、結果に含めない
+// max_offset_seconds: 探すずれの最大値(秒)。負ならエラー
+pub fn estimate_offsets(
+    site: &Site,
+    comparison: &IrradianceComparison,
+    criteria: &ClearSkyCriteria,
+    max_offset_seconds: i64,
+) -> Result<Vec<ClockOffset>> {
+    check_max_offset(max_offset_seconds)?;
+    Ok(timeseries::day_ranges(&comparison.dts)
+        .into_iter()
+        .filter_map(|(date, range)| {
+            let dts = &comparison.dts[range.clone()];
+            let measured = &comparison.measured[range];
+            let search = LagSearch::new(site, dts, measured, max_offset_seconds)?;
+            // ずれたままでは晴天の日でも晴天日射量と合わないので、1分刻みで大まかに合わせてから判定する
+            let coarse_lag = search.coarse_lag()?;
+            let shifted = IrradianceComparison::new(
+                site,
+                dts.iter()
+                    .map(|dt| *dt + Duration::minutes(coarse_lag))
+                    .collect(),
+                measured.to_vec(),
+            );
+            let clear = sky::classify_days(&shifted, criteria)
+                .iter()
+                .any(|day| day.condition == SkyCondition::Clear);
+            if !clear {
+                return None;
+            }
+            let (offset_seconds, correlation) = search.refine(coarse_lag)?;
+            Some(ClockOffset {
+                date,
+                offset_seconds,
+                correlation,
+            })
+        })
+        .collect())
+}
+
+// 晴天の日の推定値の中央値
+pub fn median_offset(offsets: &[ClockOffset]) -> Option<i64> {
+    let mut offsets = offsets
+        .iter()
+        .map(|offset| offset.offset_seconds)
+        .collect::<Vec<i64>>();
+    if offsets.is_empty() {
+        return None;
+    }
+    offsets.sort_unstable();
+    Some(offsets[offsets.len() / 2])
+}
+
+// measuredはdtsの時刻の値。時刻は飛んでいてもよい。(ずれの秒数, 相関係数) を返す
+// 日射量が全て0の日など、相関が求まらないときはNone。max_offset_secondsが負ならエラー
+pub fn estimate_offset(
+    site: &Site,
+    dts: &[DateTime<Local>],
+    measured: &[f64],
+    max_offset_seconds: i64,
+) -> Result<Option<(i64, f64)>> {
+    check_max_offset(max_offset_seconds)?;
+    Ok(LagSearch::new(site, dts, measured, max_offset_seconds)
+        .and_then(|search| search.refine(search.coarse_lag()?)))
+}
+
+fn check_max_offset(max_offset_seconds: i64) -> Result<()> {
+    if max_offset_seconds < 0 {
+        return Err(Error::Config(format!(
+            "探すずれの最大値は0以上にしてください ({}秒が指定されました)",
+            max_offset_seconds
+        )));
+    }
+    Ok(())
+}
+
+// 1日分の実測値と、ずらした大気外日射量の相関を求める
+struct LagSearch<'a> {
+    dts: &'a [DateTime<Local>],
+    measured: &'a [f64],
+    max_offset_seconds: i64,
+    // 最初の時刻からの経過秒数
+    elapsed: Vec<i64>,
+    // 大気外日射量を最初の時刻の前後に余裕を持たせて1秒刻みで計算しておく。theory[margin + t]が最初の時刻のt秒後
+    theory: Vec<f64>,
+    margin: i64,
+}
+
+impl<'a> LagSearch<'a> {
+    // max_offset_secondsは0以上であること
+    fn new(
+        site: &Site,
+        dts: &'a [DateTime<Local>],
+        measured: &'a [f64],
+        max_offset_seconds: i64,
+    ) -> Option<Self> {
+        let begin = *dts.first()?;
+        let elapsed = dts
+            .iter()
+            .map(|dt| (*dt - begin).num_seconds())
+            .collect::<Vec<i64>>();
+        let margin = max_offset_seconds + REFINE_SECONDS + 60;
+        let theory = model::extraterrestrial_kw_series(
+            site,
+            &(-margin..=elapsed[elapsed.len() - 1] + margin)
+                .map(|t| begin + Duration::seconds(t))
+                .collect::<Vec<_>>(),
+        );
+        Some(LagSearch {
+            dts,
+            measured,
+            max_offset_seconds,
+            elapsed,
+            theory,
+            margin,
+        })
+    }
+
+    fn theory_at(&self, t: i64) -> f64 {
+        self.theory[(self.margin + t) as usize]
+    }
+
+    // 1分平均値で大まかに探したずれ(分)。理論値は各分の中央の時刻の値と比べる
+    fn coarse_lag(&self) -> Option<i64> {
+        let minute_ranges = timeseries::minute_ranges(self.dts);
+        let minutes = minute_ranges
+            .iter()
+            .map(|range| self.measured[range.clone()].iter().sum::<f64>() / range.len() as f64)
+            .collect::<Vec<f64>>();
+        let minute_centers = minute_ranges
+            .iter()
+            .map(|range| self.elapsed[range.start] - self.dts[range.start].second() as i64 + 30)
+            .collect::<Vec<i64>>();
+        let (lag, _) = best_lag(&minutes, self.max_offset_seconds / 60, |lag| {
+            minute_centers
+                .iter()
+                .map(|t| self.theory_at(t + lag * 60))
+                .collect()
+        })?;
+        Some(lag)
+    }
+
+    // coarse_lag分の前後を1秒刻みで細かく探す。(ずれの秒数, 相関係数) を返す
+    fn refine(&self, coarse_lag: i64) -> Option<(i64, f64)> {
+        let center = coarse_lag * 60;
+        let (fine_lag, correlation) = best_lag(self.measured, REFINE_SECONDS, |lag| {
+            self.elapsed
+                .iter()
+                .map(|t| self.theory_at(t + center + lag))
+                .collect()
+        })?;
+        Some((center + fine_lag, correlation))
+    }
+}
+
+// values[i]とshifted(lag)[i]の相関が最大になるlagと相関係数。lagは-max_lagからmax_lagまで探す
+fn best_lag(values: &[f64], max_lag: i64, shifted: impl Fn(i64) -> Vec<f64>) -> Option<(i64, f64)> {
+    (-max_lag..=max_lag)
+        .filter_map(|lag| correlation(values, &shifted(lag)).map(|r| (lag, r)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn recovers_known_offset_on_clear_day() {
+        // 記録された時刻に1234秒足すと正しい時刻になるロガーで、1日目は晴天、2日目は雲で日射量が変わる
+        let site = Site::default();
+        let offset = 1234;
+        let start = Local.with_ymd_and_hms(2022, 10, 9, 0, 0, 0).unwrap();
+        let dts = (0..2 * 86400)
+            .map(|i| start + Duration::seconds(i))
+            .collect::<Vec<_>>();
+        let clear_sky = IrradianceComparison::new(
+            &site,
+            dts.iter()
+                .map(|dt| *dt + Duration::seconds(offset))
+                .collect(),
+            vec![0.0; dts.len()],
+        )
+        .clear_sky;
+        let measured = clear_sky
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                if i < 86400 {
+                    *v
+                } else {
+                    v * (0.2 + 0.8 * (i as f64 / 300.0).sin().abs())
+                }
+            })
+            .collect::<Vec<f64>>();
+        let comparison = IrradianceComparison::new(&site, dts, measured);
+
+        let offsets =
+            estimate_offsets(&site, &comparison, &ClearSkyCriteria::default(), 3600).unwrap();
+        assert_eq!(offsets.len(), 1);
+        assert_eq!(offsets[0].date, start.date_naive());
+        assert_eq!(offsets[0].offset_seconds, offset);
+        assert!(offsets[0].correlation > 0.99);
+        assert_eq!(median_offset(&offsets), Some(offsets[0].offset_seconds));
+    }
+
+    #[test]
+    fn negative_max_offset_is_an_error() {
+        let site = Site::default();
+        let start = Local.with_ymd_and_hms(2022, 10, 9, 12, 0, 0).unwrap();
+        let dts = (0..600)
+            .map(|i| start + Duration::seconds(i))
+            .collect::<Vec<_>>();
+        assert!(estimate_offset(&site, &dts, &[0.5; 600], -60).is_err());
+        let comparison = IrradianceComparison::new(&site, dts, vec![0.5; 600]);
+        assert!(estimate_offsets(&site, &comparison, &ClearSkyCriteria::default(), -60).is_err());
+    }
+}
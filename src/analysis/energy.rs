@@ -0,0 +1,256 @@
+// 発電量の日・月・年ごとの集計
+// 瞬時の出力を台形則で積算した値と、積算電力量のカウンタの差分を比べる
+use std::ops::Range;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+
+use crate::error::Result;
+use crate::es::Field;
+use crate::timeseries::TimeSeries;
+
+// 集計する単位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YieldPeriod {
+    Day,
+    Month,
+    Year,
+}
+
+impl std::str::FromStr for YieldPeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(YieldPeriod::Day),
+            "month" => Ok(YieldPeriod::Month),
+            "year" => Ok(YieldPeriod::Year),
+            _ => Err(format!(
+                "不明な集計単位 \"{}\" です (指定可能な値: day, month, year)",
+                s
+            )),
+        }
+    }
+}
+
+impl YieldPeriod {
+    // 集計の単位の最初の日
+    pub fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            YieldPeriod::Day => date,
+            YieldPeriod::Month => date.with_day(1).unwrap(),
+            YieldPeriod::Year => date.with_ordinal(1).unwrap(),
+        }
+    }
+
+    // 表示やCSVで使う名前
+    pub fn label(self, start: NaiveDate) -> String {
+        match self {
+            YieldPeriod::Day => start.format("%Y-%m-%d").to_string(),
+            YieldPeriod::Month => start.format("%Y-%m").to_string(),
+            YieldPeriod::Year => start.format("%Y").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct EnergyParams {
+    // これより長く間隔が空いた区間は積算しない(秒)
+    pub max_gap_seconds: i64,
+    // 積算値とカウンタの差分の相対的な差の許容値
+    pub tolerance: f64,
+    // カウンタの分解能などによる差を無視する絶対値(kWh)
+    pub min_difference_kwh: f64,
+}
+
+impl Default for EnergyParams {
+    fn default() -> Self {
+        EnergyParams {
+            max_gap_seconds: 60,
+            tolerance: 0.05,
+            min_difference_kwh: 0.2,
+        }
+    }
+}
+
+// 1つの集計単位の発電量(kWh)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct EnergyYield {
+    pub start: NaiveDate,
+    // 交流・直流の出力の積算値
+    pub ac_kwh: f64,
+    pub dc_kwh: f64,
+    // 積算電力量のカウンタの増加量。カウンタの値が1件も無いときはNone
+    pub single_unit_counter_kwh: Option<f64>,
+    pub total_unit_counter_kwh: Option<f64>,
+    // 間隔が空いていて積算しなかった秒数
+    pub missing_seconds: i64,
+    // 交流の積算値と自機のカウンタの増加量が食い違っているか
+    pub discrepancy: bool,
+}
+
+impl EnergyYield {
+    fn check(mut self, params: &EnergyParams) -> Self {
+        self.discrepancy = match self.single_unit_counter_kwh {
+            Some(counter) => {
+                let difference = (self.ac_kwh - counter).abs();
+                difference > params.min_difference_kwh
+                    && difference > params.tolerance * self.ac_kwh.abs().max(counter.abs())
+            }
+            None => false,
+        };
+        self
+    }
+}
+
+// 日ごとの発電量。ac_pw, dc_pwと2つの積算電力量の項目が必要
+pub fn daily_yields(series: &TimeSeries, params: &EnergyParams) -> Result<Vec<EnergyYield>> {
+    let dts = series.dts();
+    let ac = series.valid(Field::AcPw)?;
+    let dc = series.valid(Field::DcPw)?;
+    let single_unit = series.valid(Field::SingleUnitIntegratedPowerGeneration)?;
+    let total_unit = series.valid(Field::TotalUnitIntegratedPowerGeneration)?;
+
+    let mut single_unit_counter = Counter::default();
+    let mut total_unit_counter = Counter::default();
+
+    let yields = series
+        .day_ranges()
+        .into_iter()
+        .map(|(date, range)| {
+            let (ac_kwh, missing_seconds) =
+                integrate(dts, &ac, range.clone(), params.max_gap_seconds);
+            let (dc_kwh, _) = integrate(dts, &dc, range.clone(), params.max_gap_seconds);
+            EnergyYield {
+                start: date,
+                ac_kwh,
+                dc_kwh,
+                single_unit_counter_kwh: single_unit_counter.increase(&single_unit[range.clone()]),
+                total_unit_counter_kwh: total_unit_counter.increase(&total_unit[range]),
+                missing_seconds,
+                discrepancy: false,
+            }
+            .check(params)
+        })
+        .collect();
+
+    Ok(yields)
+}
+
+// rangeの範囲の値を台形則で時間について積算する(kwならkwh)。(積算値, 積算しなかった秒数) を返す
+// 間隔がmax_gap_secondsより長い区間と、どちらかの端の値が無い(None)区間は積算しない
+// 前の日の最後の値との間も、rangeの最初の時刻の側に含める
+pub fn integrate(
+    dts: &[DateTime<Local>],
+    values: &[Option<f64>],
+    range: Range<usize>,
+    max_gap_seconds: i64,
+) -> (f64, i64) {
+    let (mut integrated, mut missing_seconds) = (0.0, 0);
+    for i in range.filter(|i| *i > 0) {
+        let gap = (dts[i] - dts[i - 1]).num_seconds();
+        match (values[i - 1], values[i]) {
+            (Some(a), Some(b)) if gap <= max_gap_seconds => {
+                integrated += (a + b) / 2.0 * gap as f64 / 3600.0;
+            }
+            _ => missing_seconds += gap,
+        }
+    }
+    (integrated, missing_seconds)
+}
+
+// 日ごとの発電量を月・年ごとにまとめる
+pub fn aggregate(
+    daily: &[EnergyYield],
+    period: YieldPeriod,
+    params: &EnergyParams,
+) -> Vec<EnergyYield> {
+    let mut yields: Vec<EnergyYield> = Vec::new();
+    for day in daily {
+        let start = period.start(day.start);
+        match yields.last_mut() {
+            Some(last) if last.start == start => {
+                last.ac_kwh += day.ac_kwh;
+                last.dc_kwh += day.dc_kwh;
+                last.single_unit_counter_kwh =
+                    add_counter(last.single_unit_counter_kwh, day.single_unit_counter_kwh);
+                last.total_unit_counter_kwh =
+                    add_counter(last.total_unit_counter_kwh, day.total_unit_counter_kwh);
+                last.missing_seconds += day.missing_seconds;
+            }
+            _ => yields.push(EnergyYield { start, ..*day }),
+        }
+    }
+    yields.into_iter().map(|y| y.check(params)).collect()
+}
+
+fn add_counter(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    }
+}
+
+// 積算電力量のカウンタの増加量を日をまたいで求める
+#[derive(Debug, Default)]
+struct Counter {
+    last: Option<f64>,
+}
+
+impl Counter {
+    // 値が無い時刻は除く。値が減ったときはカウンタが戻ったとみなして数えない
+    fn increase(&mut self, values: &[Option<f64>]) -> Option<f64> {
+        let mut increase = None;
+        for value in values.iter().flatten() {
+            if let Some(last) = self.last {
+                let diff = (value - last).max(0.0);
+                increase = Some(increase.unwrap_or(0.0) + diff);
+            } else {
+                increase = Some(0.0);
+            }
+            self.last = Some(*value);
+        }
+        increase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn dts(len: i64) -> Vec<DateTime<Local>> {
+        let start = Local.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap();
+        (0..len).map(|i| start + Duration::seconds(i)).collect()
+    }
+
+    #[test]
+    fn integrate_constant_power() {
+        // 3.6kWで1時間なら3.6kWh
+        let values = vec![Some(3.6); 3601];
+        let (kwh, missing) = integrate(&dts(3601), &values, 0..3601, 60);
+        assert!((kwh - 3.6).abs() < 1e-9);
+        assert_eq!(missing, 0);
+    }
+
+    #[test]
+    fn integrate_skips_missing_samples() {
+        // 補完した値(None)を挟む区間は積算せず欠損として数える
+        let mut values = vec![Some(3.6); 3601];
+        for value in values[1000..2000].iter_mut() {
+            *value = None;
+        }
+        let (kwh, missing) = integrate(&dts(3601), &values, 0..3601, 60);
+        assert_eq!(missing, 1001);
+        assert!((kwh - 3.6 * 2599.0 / 3600.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn counter_ignores_missing_and_resets() {
+        let mut counter = Counter::default();
+        let values = [None, Some(10.0), Some(10.5), None, Some(11.0), Some(0.5)];
+        assert_eq!(counter.increase(&values), Some(1.0));
+        assert_eq!(counter.increase(&[None, None]), None);
+    }
+}
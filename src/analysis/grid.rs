@@ -0,0 +1,311 @@
+// 系統の周波数と交流電圧の安定性
+// 基準値からのずれの分布と、許容範囲を外れた時間帯(周波数の逸脱・電圧のサグ/スウェル)を求める
+use std::fmt;
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::Deserialize;
+
+use crate::error::Result;
+use crate::es::Field;
+use crate::timeseries::TimeSeries;
+
+// 基準値と許容範囲。地域や受電方式によって違うので地点ごとに設定する
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct GridParams {
+    // 基準周波数(Hz)と、基準からのずれの許容値(Hz)
+    pub nominal_frequency: f64,
+    pub frequency_tolerance: f64,
+    // 基準電圧(V)と、基準からのずれの許容値(V)。既定値は単相3線式の202±20V
+    pub nominal_voltage: f64,
+    pub voltage_tolerance: f64,
+    // これより短い逸脱は数えない(秒)
+    pub min_event_seconds: i64,
+}
+
+impl Default for GridParams {
+    fn default() -> Self {
+        GridParams {
+            nominal_frequency: 60.0,
+            frequency_tolerance: 0.2,
+            nominal_voltage: 202.0,
+            voltage_tolerance: 20.0,
+            min_event_seconds: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridQuantity {
+    Frequency,
+    Voltage,
+}
+
+impl GridQuantity {
+    pub fn field(self) -> Field {
+        match self {
+            GridQuantity::Frequency => Field::Frequency,
+            GridQuantity::Voltage => Field::AcV,
+        }
+    }
+
+    // (基準値, 許容値)
+    fn limits(self, params: &GridParams) -> (f64, f64) {
+        match self {
+            GridQuantity::Frequency => (params.nominal_frequency, params.frequency_tolerance),
+            GridQuantity::Voltage => (params.nominal_voltage, params.voltage_tolerance),
+        }
+    }
+}
+
+// 許容範囲を外れた時間帯
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct GridEvent {
+    pub quantity: GridQuantity,
+    // 上限を超えたか(電圧ならスウェル)、下限を下回ったか(電圧ならサグ)
+    pub above: bool,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    // 最も基準から離れた値
+    pub extreme: f64,
+}
+
+impl GridEvent {
+    pub fn duration_seconds(&self) -> i64 {
+        (self.end - self.start).num_seconds() + 1
+    }
+
+    // CSVなどで使う名前
+    pub fn name(&self) -> &'static str {
+        match (self.quantity, self.above) {
+            (GridQuantity::Frequency, true) => "over_frequency",
+            (GridQuantity::Frequency, false) => "under_frequency",
+            (GridQuantity::Voltage, true) => "swell",
+            (GridQuantity::Voltage, false) => "sag",
+        }
+    }
+}
+
+impl fmt::Display for GridEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match (self.quantity, self.above) {
+            (GridQuantity::Frequency, true) => "周波数上昇",
+            (GridQuantity::Frequency, false) => "周波数低下",
+            (GridQuantity::Voltage, true) => "電圧上昇(スウェル)",
+            (GridQuantity::Voltage, false) => "電圧低下(サグ)",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+// 値の分布
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub p5: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+    // 基準からのずれの絶対値の平均
+    pub mean_abs_deviation: f64,
+    // 許容範囲内の値の割合
+    pub within_tolerance: f64,
+}
+
+impl Distribution {
+    // 値が無いときはNone
+    pub fn of(values: &[f64], nominal: f64, tolerance: f64) -> Option<Distribution> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / n;
+        let std_dev = (sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+        Some(Distribution {
+            count: sorted.len(),
+            mean,
+            std_dev,
+            min: sorted[0],
+            p5: percentile(0.05),
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            max: sorted[sorted.len() - 1],
+            mean_abs_deviation: sorted.iter().map(|v| (v - nominal).abs()).sum::<f64>() / n,
+            within_tolerance: sorted
+                .iter()
+                .filter(|v| (*v - nominal).abs() <= tolerance)
+                .count() as f64
+                / n,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct DayGrid {
+    pub date: NaiveDate,
+    pub frequency: Option<Distribution>,
+    pub voltage: Option<Distribution>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[non_exhaustive]
+pub struct GridStability {
+    pub frequency: Option<Distribution>,
+    pub voltage: Option<Distribution>,
+    pub days: Vec<DayGrid>,
+    // 開始時刻の順
+    pub events: Vec<GridEvent>,
+}
+
+// seriesにはfrequencyとac_vが必要。欠損を補完した時刻は除く
+// 品質管理の範囲外の値も実際のサグ・スウェルや周波数の逸脱なので、品質管理で除く値も使う
+pub fn analyze_grid(series: &TimeSeries, params: &GridParams) -> Result<GridStability> {
+    let frequency = series.recorded(Field::Frequency)?;
+    let voltage = series.recorded(Field::AcV)?;
+    let distribution = |values: &[Option<f64>], quantity: GridQuantity| {
+        let (nominal, tolerance) = quantity.limits(params);
+        let measured = values.iter().flatten().cloned().collect::<Vec<f64>>();
+        Distribution::of(&measured, nominal, tolerance)
+    };
+
+    let days = series
+        .day_ranges()
+        .into_iter()
+        .map(|(date, range)| DayGrid {
+            date,
+            frequency: distribution(&frequency[range.clone()], GridQuantity::Frequency),
+            voltage: distribution(&voltage[range], GridQuantity::Voltage),
+        })
+        .collect();
+
+    let mut events = find_events(series.dts(), &frequency, GridQuantity::Frequency, params);
+    events.extend(find_events(
+        series.dts(),
+        &voltage,
+        GridQuantity::Voltage,
+        params,
+    ));
+    events.sort_by_key(|event| event.start);
+
+    Ok(GridStability {
+        frequency: distribution(&frequency, GridQuantity::Frequency),
+        voltage: distribution(&voltage, GridQuantity::Voltage),
+        days,
+        events,
+    })
+}
+
+// 許容範囲の上限を超えた・下限を下回った値が続く時間帯。値の無い時刻で区切る
+fn find_events(
+    dts: &[DateTime<Local>],
+    values: &[Option<f64>],
+    quantity: GridQuantity,
+    params: &GridParams,
+) -> Vec<GridEvent> {
+    let (nominal, tolerance) = quantity.limits(params);
+    // 上限を超えたらSome(true)、下限を下回ったらSome(false)
+    let side = |value: Option<f64>| {
+        let value = value?;
+        if value > nominal + tolerance {
+            Some(true)
+        } else if value < nominal - tolerance {
+            Some(false)
+        } else {
+            None
+        }
+    };
+
+    let mut events: Vec<GridEvent> = Vec::new();
+    let mut i = 0;
+    while i < values.len() {
+        let Some(above) = side(values[i]) else {
+            i += 1;
+            continue;
+        };
+        let start = i;
+        let mut extreme = nominal;
+        while i < values.len() && side(values[i]) == Some(above) {
+            let value = values[i].unwrap_or(nominal);
+            if (value - nominal).abs() > (extreme - nominal).abs() {
+                extreme = value;
+            }
+            i += 1;
+        }
+        let event = GridEvent {
+            quantity,
+            above,
+            start: dts[start],
+            end: dts[i - 1],
+            extreme,
+        };
+        if event.duration_seconds() >= params.min_event_seconds {
+            events.push(event);
+        }
+    }
+    events
+}
+
+// 幅widthの区分ごとの件数。(区分の下端, 件数) を値の小さい順に返す。値の無い時刻は除く
+pub fn histogram(values: &[Option<f64>], width: f64) -> Vec<(f64, usize)> {
+    let mut bins: Vec<(i64, usize)> = Vec::new();
+    let mut keys = values
+        .iter()
+        .flatten()
+        .map(|v| (v / width).floor() as i64)
+        .collect::<Vec<i64>>();
+    keys.sort_unstable();
+    for key in keys {
+        match bins.last_mut() {
+            Some((last, count)) if *last == key => *count += 1,
+            _ => bins.push((key, 1)),
+        }
+    }
+    bins.into_iter()
+        .map(|(key, count)| (key as f64 * width, count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Site;
+    use crate::qc;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn sag_outside_qc_range_is_an_event() {
+        // 140Vは品質管理の範囲外だが、除く設定でも実際のサグとして検出する
+        let start = Local.with_ymd_and_hms(2022, 10, 9, 12, 0, 0).unwrap();
+        let dts = (0..60)
+            .map(|i| start + Duration::seconds(i))
+            .collect::<Vec<_>>();
+        let mut voltage = vec![202.0; 60];
+        voltage[10..15].fill(140.0);
+        let mut series = TimeSeries::new(dts)
+            .with_column(Field::Frequency, vec![60.0; 60])
+            .unwrap()
+            .with_column(Field::AcV, voltage)
+            .unwrap();
+        qc::apply_qc(&Site::default(), &mut series).unwrap();
+        assert!(series.exclude_flagged() > 0);
+
+        let result = analyze_grid(&series, &GridParams::default()).unwrap();
+        assert_eq!(result.events.len(), 1);
+        let event = result.events[0];
+        assert_eq!(event.name(), "sag");
+        assert_eq!(event.start, start + Duration::seconds(10));
+        assert_eq!(event.duration_seconds(), 5);
+        assert_eq!(event.extreme, 140.0);
+        assert_eq!(result.voltage.unwrap().min, 140.0);
+    }
+}
@@ -0,0 +1,252 @@
+// パワーコンディショナの変換効率(交流出力/直流出力)の負荷率ごとの特性と、効率が落ちた日の検出
+use chrono::NaiveDate;
+
+use crate::error::Result;
+use crate::es::Field;
+use crate::timeseries::TimeSeries;
+
+// ヨーロッパ効率とCEC効率の (負荷率, 重み)
+const EURO_WEIGHTS: [(f64, f64); 6] = [
+    (0.05, 0.03),
+    (0.1, 0.06),
+    (0.2, 0.13),
+    (0.3, 0.1),
+    (0.5, 0.48),
+    (1.0, 0.2),
+];
+const CEC_WEIGHTS: [(f64, f64); 6] = [
+    (0.1, 0.04),
+    (0.2, 0.05),
+    (0.3, 0.12),
+    (0.5, 0.21),
+    (0.75, 0.53),
+    (1.0, 0.05),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct InverterParams {
+    // 負荷率の区分の幅
+    pub bin_width: f64,
+    // 区分の効率を求めるのに必要な件数
+    pub min_bin_count: usize,
+    // これより負荷率が低い時刻は効率が不安定なので除く
+    pub min_load_fraction: f64,
+    // 効率がこれを超える時刻は計測の誤りとみなして除く
+    pub max_efficiency: f64,
+    // 日ごとの効率を求めるのに必要な件数
+    pub min_day_count: usize,
+    // 期間全体の特性から予想した効率をこの割合以上下回った日を効率が落ちた日とする
+    pub degraded_threshold: f64,
+}
+
+impl Default for InverterParams {
+    fn default() -> Self {
+        InverterParams {
+            bin_width: 0.05,
+            min_bin_count: 30,
+            min_load_fraction: 0.02,
+            max_efficiency: 1.05,
+            min_day_count: 600,
+            degraded_threshold: 0.02,
+        }
+    }
+}
+
+// 負荷率の区分ごとの効率
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct EfficiencyBin {
+    // 区分の中央の負荷率(交流出力/定格出力)
+    pub load_fraction: f64,
+    // 区分内の交流出力の合計/直流出力の合計
+    pub efficiency: f64,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[non_exhaustive]
+pub struct EfficiencyCurve {
+    pub bins: Vec<EfficiencyBin>,
+}
+
+impl EfficiencyCurve {
+    // 負荷率に対する効率を区分の間で線形補間する。範囲外のときは端の値
+    pub fn at(&self, load_fraction: f64) -> Option<f64> {
+        let first = self.bins.first()?;
+        let last = self.bins.last()?;
+        if load_fraction <= first.load_fraction {
+            return Some(first.efficiency);
+        }
+        if load_fraction >= last.load_fraction {
+            return Some(last.efficiency);
+        }
+        self.bins.windows(2).find_map(|w| {
+            let (a, b) = (w[0], w[1]);
+            (load_fraction <= b.load_fraction).then(|| {
+                let t = (load_fraction - a.load_fraction) / (b.load_fraction - a.load_fraction);
+                a.efficiency + (b.efficiency - a.efficiency) * t
+            })
+        })
+    }
+
+    // ヨーロッパ効率
+    pub fn euro_efficiency(&self) -> Option<f64> {
+        self.weighted(&EURO_WEIGHTS)
+    }
+
+    // CEC(カリフォルニア州エネルギー委員会)効率
+    pub fn cec_efficiency(&self) -> Option<f64> {
+        self.weighted(&CEC_WEIGHTS)
+    }
+
+    // 加重効率の負荷率(5%~100%)が全て区分の範囲内か。範囲外の負荷率は端の値で代用する
+    pub fn covers_weights(&self) -> bool {
+        match (self.bins.first(), self.bins.last()) {
+            (Some(first), Some(last)) => first.load_fraction <= 0.05 && last.load_fraction >= 1.0,
+            _ => false,
+        }
+    }
+
+    fn weighted(&self, weights: &[(f64, f64)]) -> Option<f64> {
+        weights
+            .iter()
+            .map(|(load_fraction, weight)| self.at(*load_fraction).map(|e| e * weight))
+            .sum()
+    }
+}
+
+// 1日の効率
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct DayEfficiency {
+    pub date: NaiveDate,
+    // 交流出力の合計/直流出力の合計
+    pub efficiency: f64,
+    // 期間全体の特性から予想した交流出力に対する実測の比
+    pub relative: f64,
+    pub count: usize,
+    pub degraded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[non_exhaustive]
+pub struct InverterEfficiency {
+    pub curve: EfficiencyCurve,
+    pub days: Vec<DayEfficiency>,
+}
+
+// seriesにはac_pwとdc_pwが必要。inverter_kwはパワーコンディショナの定格出力(kW)
+pub fn analyze_efficiency(
+    series: &TimeSeries,
+    inverter_kw: f64,
+    params: &InverterParams,
+) -> Result<InverterEfficiency> {
+    let ac = series.valid(Field::AcPw)?;
+    let dc = series.valid(Field::DcPw)?;
+    // 効率を求められる時刻の (交流, 直流)
+    let sample = |i: usize| match (ac[i], dc[i]) {
+        (Some(ac), Some(dc))
+            if dc > 0.0
+                && ac > 0.0
+                && ac / inverter_kw >= params.min_load_fraction
+                && ac / dc <= params.max_efficiency =>
+        {
+            Some((ac, dc))
+        }
+        _ => None,
+    };
+
+    // 区分ごとの (交流の合計, 直流の合計, 件数)
+    let width = params.bin_width;
+    let mut sums: Vec<(f64, f64, usize)> = Vec::new();
+    for (ac, dc) in (0..series.len()).filter_map(sample) {
+        let bin = (ac / inverter_kw / width) as usize;
+        if sums.len() <= bin {
+            sums.resize(bin + 1, (0.0, 0.0, 0));
+        }
+        sums[bin].0 += ac;
+        sums[bin].1 += dc;
+        sums[bin].2 += 1;
+    }
+    let curve = EfficiencyCurve {
+        bins: sums
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, _, count))| *count >= params.min_bin_count)
+            .map(|(bin, (ac, dc, count))| EfficiencyBin {
+                load_fraction: (bin as f64 + 0.5) * width,
+                efficiency: ac / dc,
+                count: *count,
+            })
+            .collect(),
+    };
+
+    let days = series
+        .day_ranges()
+        .into_iter()
+        .filter_map(|(date, range)| {
+            let (mut ac_sum, mut dc_sum, mut expected, mut count) = (0.0, 0.0, 0.0, 0);
+            for (ac, dc) in range.filter_map(sample) {
+                ac_sum += ac;
+                dc_sum += dc;
+                expected += dc * curve.at(ac / inverter_kw)?;
+                count += 1;
+            }
+            if count < params.min_day_count {
+                return None;
+            }
+            let relative = ac_sum / expected;
+            Some(DayEfficiency {
+                date,
+                efficiency: ac_sum / dc_sum,
+                relative,
+                count,
+                degraded: relative < 1.0 - params.degraded_threshold,
+            })
+        })
+        .collect();
+
+    Ok(InverterEfficiency { curve, days })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(points: &[(f64, f64)]) -> EfficiencyCurve {
+        EfficiencyCurve {
+            bins: points
+                .iter()
+                .map(|(load_fraction, efficiency)| EfficiencyBin {
+                    load_fraction: *load_fraction,
+                    efficiency: *efficiency,
+                    count: 100,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn weighted_efficiencies() {
+        // 重みの合計は1なので、効率が一定なら加重効率も同じ値
+        let flat = curve(&[(0.05, 0.95), (1.0, 0.95)]);
+        assert!((flat.euro_efficiency().unwrap() - 0.95).abs() < 1e-9);
+        assert!((flat.cec_efficiency().unwrap() - 0.95).abs() < 1e-9);
+
+        // ヨーロッパ効率とCEC効率の重みで手計算した値
+        let curve = curve(&[
+            (0.05, 0.90),
+            (0.1, 0.93),
+            (0.2, 0.95),
+            (0.3, 0.96),
+            (0.5, 0.97),
+            (0.75, 0.965),
+            (1.0, 0.96),
+        ]);
+        assert!(curve.covers_weights());
+        assert!((curve.euro_efficiency().unwrap() - 0.9599).abs() < 1e-9);
+        assert!((curve.cec_efficiency().unwrap() - 0.96305).abs() < 1e-9);
+        assert_eq!(EfficiencyCurve::default().euro_efficiency(), None);
+    }
+}
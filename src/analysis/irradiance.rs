@@ -0,0 +1,158 @@
+// 実測の日射量と理論値(大気外日射量・晴天日射量)の比較
+// 日射量の単位は計測値に合わせてkw/m^2
+use std::ops::Range;
+
+use chrono::{DateTime, Local, NaiveDate};
+
+use super::Summary;
+use crate::config::Site;
+use crate::model;
+use crate::timeseries;
+
+// 晴天指数を求める晴天日射量の下限(kw/m^2)。朝夕は比が不安定になるので除く
+pub const MIN_CLEAR_SKY_KW: f64 = 0.05;
+
+// 同じ時刻に揃えた実測値と理論値
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct IrradianceComparison {
+    pub dts: Vec<DateTime<Local>>,
+    pub measured: Vec<f64>,
+    // 水平面の大気外日射量
+    pub extraterrestrial: Vec<f64>,
+    // 地点のclear_sky_modelで計算した晴天時の全天日射量
+    pub clear_sky: Vec<f64>,
+    // 晴天指数(実測値/晴天日射量)。晴天日射量が小さい時刻はNaN
+    pub clear_sky_index: Vec<f64>,
+}
+
+// 比較結果の集計
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ComparisonSummary {
+    pub measured: Summary,
+    // 積算値(kwh/m^2)
+    pub extraterrestrial_kwh: f64,
+    pub clear_sky_kwh: f64,
+    // 積算値の比(実測/大気外、実測/晴天)
+    pub clearness_index: f64,
+    pub clear_sky_ratio: f64,
+    // 晴天指数が求まった時刻の件数と平均値
+    pub daytime_count: usize,
+    pub mean_clear_sky_index: f64,
+    // 晴天日射量に対する二乗平均平方根誤差(kw/m^2)
+    pub rmse_vs_clear_sky: f64,
+}
+
+impl IrradianceComparison {
+    pub fn new(site: &Site, dts: Vec<DateTime<Local>>, measured: Vec<f64>) -> Self {
+        let extraterrestrial = model::extraterrestrial_kw_series(site, &dts);
+        let geometry = model::solar_geometry(site, &dts);
+        let clear_sky = model::clear_sky_series(site, site.clear_sky_model, &dts, &geometry)
+            .iter()
+            .map(|clear_sky| clear_sky.ghi / 1000.0)
+            .collect::<Vec<f64>>();
+        let clear_sky_index = measured
+            .iter()
+            .zip(clear_sky.iter())
+            .map(|(measured, clear_sky)| {
+                if *clear_sky >= MIN_CLEAR_SKY_KW {
+                    measured / clear_sky
+                } else {
+                    f64::NAN
+                }
+            })
+            .collect();
+
+        IrradianceComparison {
+            dts,
+            measured,
+            extraterrestrial,
+            clear_sky,
+            clear_sky_index,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dts.is_empty()
+    }
+
+    // rangeの範囲の集計。空の範囲のときはNone
+    pub fn summary(&self, range: Range<usize>) -> Option<ComparisonSummary> {
+        let dts = &self.dts[range.clone()];
+        let measured = Summary::of(dts, &self.measured[range.clone()])?;
+        // 実測値と同じ時刻の値を積算する
+        let extraterrestrial_kwh = super::integrate(dts, &self.extraterrestrial[range.clone()]);
+        let clear_sky_kwh = super::integrate(dts, &self.clear_sky[range.clone()]);
+
+        let daytime = range
+            .clone()
+            .filter(|i| !self.clear_sky_index[*i].is_nan())
+            .collect::<Vec<usize>>();
+        let daytime_count = daytime.len();
+        let mean_clear_sky_index = daytime
+            .iter()
+            .map(|i| self.clear_sky_index[*i])
+            .sum::<f64>()
+            / daytime_count as f64;
+        let rmse_vs_clear_sky = (range
+            .clone()
+            .map(|i| (self.measured[i] - self.clear_sky[i]).powi(2))
+            .sum::<f64>()
+            / range.len() as f64)
+            .sqrt();
+
+        Some(ComparisonSummary {
+            measured,
+            extraterrestrial_kwh,
+            clear_sky_kwh,
+            clearness_index: measured.integrated / extraterrestrial_kwh,
+            clear_sky_ratio: measured.integrated / clear_sky_kwh,
+            daytime_count,
+            mean_clear_sky_index,
+            rmse_vs_clear_sky,
+        })
+    }
+
+    // 日ごとの集計
+    pub fn day_summaries(&self) -> Vec<(NaiveDate, ComparisonSummary)> {
+        timeseries::day_ranges(&self.dts)
+            .into_iter()
+            .filter_map(|(date, range)| self.summary(range).map(|summary| (date, summary)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn summary_skips_excluded_interval() {
+        // 12時から1時間のうち、20分から10分間の値が品質管理で除かれて無い
+        let site = Site::default();
+        let start = Local.with_ymd_and_hms(2022, 10, 9, 12, 0, 0).unwrap();
+        let dts = (0..3600)
+            .filter(|i| !(1200..1800).contains(i))
+            .map(|i| start + Duration::seconds(i))
+            .collect::<Vec<_>>();
+
+        let summary = Summary::of(&dts, &vec![0.5; dts.len()]).unwrap();
+        assert_eq!(summary.count, 3000);
+        // 除かれた前後の間隔は積算しない
+        assert!((summary.integrated - 0.5 * 2998.0 / 3600.0).abs() < 1e-12);
+
+        // 実測値が晴天日射量と同じなら、理論値も同じ時刻を積算するので比は1
+        let clear_sky =
+            IrradianceComparison::new(&site, dts.clone(), vec![0.0; dts.len()]).clear_sky;
+        let comparison = IrradianceComparison::new(&site, dts, clear_sky);
+        let summary = comparison.summary(0..comparison.len()).unwrap();
+        assert!((summary.clear_sky_ratio - 1.0).abs() < 1e-12);
+        assert!(summary.rmse_vs_clear_sky < 1e-12);
+    }
+}
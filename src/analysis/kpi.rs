@@ -0,0 +1,182 @@
+// 性能比(PR)・気温補正した性能比・発電量(kWh/kWp)・設備利用率
+// 日射量は実測の全天日射量から求めたパネル面の日射量を使う
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone};
+
+use super::energy::{self, YieldPeriod};
+use crate::config::Site;
+use crate::error::Result;
+use crate::es::Field;
+use crate::model;
+use crate::pvsystem;
+use crate::timeseries::TimeSeries;
+
+// 1つの集計単位の積算値。指標は積算値の比として求める
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Kpi {
+    pub start: NaiveDate,
+    pub rated_kw: f64,
+    // 集計した期間の暦の上の長さ(h)
+    pub hours: f64,
+    // 交流出力の積算値(kWh)
+    pub ac_kwh: f64,
+    // パネル面の日射量の積算値(kWh/m^2)
+    pub insolation: f64,
+    // セル温度が25℃のときとの出力の違いを考慮した理想的な発電量(kWh)
+    pub temperature_corrected_kwh: f64,
+}
+
+impl Kpi {
+    // 定格出力1kWあたりの発電量(kWh/kWp)
+    pub fn specific_yield(&self) -> f64 {
+        self.ac_kwh / self.rated_kw
+    }
+
+    // 基準日射強度(1kW/m^2)で割った日射量(h)
+    pub fn reference_yield(&self) -> f64 {
+        self.insolation
+    }
+
+    pub fn performance_ratio(&self) -> f64 {
+        self.specific_yield() / self.reference_yield()
+    }
+
+    pub fn temperature_corrected_performance_ratio(&self) -> f64 {
+        self.ac_kwh / self.temperature_corrected_kwh
+    }
+
+    pub fn capacity_factor(&self) -> f64 {
+        self.ac_kwh / (self.rated_kw * self.hours)
+    }
+}
+
+// 日ごとの積算値。seriesにはac_pw, solar_irradiance, air_temperatureが必要
+// 3つの値が全て揃っている時刻だけを積算し、max_gap_secondsより長く間隔が空いた区間は積算しない
+pub fn daily_kpis(site: &Site, series: &TimeSeries, max_gap_seconds: i64) -> Result<Vec<Kpi>> {
+    let rated_kw = site.require_rated_kw()?;
+    let dts = series.dts();
+    let ac = series.valid(Field::AcPw)?;
+    let air_temperature = series.valid(Field::AirTemperature)?;
+    // kw/m^2。日射量の値が無い時刻はNone
+    let poa = model::measured_poa(site, series)?
+        .iter()
+        .map(|poa| poa.map(|poa| poa.global / 1000.0))
+        .collect::<Vec<Option<f64>>>();
+    // 性能比の分子と分母が同じ時刻を積算するよう、どれかの値が無い時刻は全てNoneにする
+    let samples = (0..series.len())
+        .map(|i| {
+            let (ac, poa, air_temperature) = (ac[i]?, poa[i]?, air_temperature[i]?);
+            let cell_temperature =
+                pvsystem::calc_cell_temperature(&site.pv, poa * 1000.0, air_temperature);
+            let temperature_corrected =
+                rated_kw * poa * (1.0 + site.pv.gamma_pdc * (cell_temperature - 25.0));
+            Some((ac, poa, temperature_corrected))
+        })
+        .collect::<Vec<Option<(f64, f64, f64)>>>();
+    let column = |value: fn((f64, f64, f64)) -> f64| {
+        samples
+            .iter()
+            .map(|sample| sample.map(value))
+            .collect::<Vec<Option<f64>>>()
+    };
+    let (ac, poa, temperature_corrected) = (
+        column(|sample| sample.0),
+        column(|sample| sample.1),
+        column(|sample| sample.2),
+    );
+
+    let kpis = series
+        .day_ranges()
+        .into_iter()
+        .map(|(date, range)| Kpi {
+            start: date,
+            rated_kw,
+            hours: calendar_day_hours(date),
+            ac_kwh: energy::integrate(dts, &ac, range.clone(), max_gap_seconds).0,
+            insolation: energy::integrate(dts, &poa, range.clone(), max_gap_seconds).0,
+            temperature_corrected_kwh: energy::integrate(
+                dts,
+                &temperature_corrected,
+                range,
+                max_gap_seconds,
+            )
+            .0,
+        })
+        .collect();
+
+    Ok(kpis)
+}
+
+// 0時から翌日の0時までの時間(h)。夏時間の切り替えの日は23時間か25時間になる
+fn calendar_day_hours(date: NaiveDate) -> f64 {
+    let midnight = |date: NaiveDate| {
+        Local
+            .from_local_datetime(&date.and_time(NaiveTime::default()))
+            .earliest()
+    };
+    match (midnight(date), date.succ_opt().and_then(midnight)) {
+        (Some(start), Some(end)) => (end - start).num_seconds() as f64 / 3600.0,
+        _ => 24.0,
+    }
+}
+
+// 日ごとの積算値を月・年ごとにまとめる
+pub fn aggregate(daily: &[Kpi], period: YieldPeriod) -> Vec<Kpi> {
+    let mut kpis: Vec<Kpi> = Vec::new();
+    for day in daily {
+        let start = period.start(day.start);
+        match kpis.last_mut() {
+            Some(last) if last.start == start => {
+                last.hours += day.hours;
+                last.ac_kwh += day.ac_kwh;
+                last.insolation += day.insolation;
+                last.temperature_corrected_kwh += day.temperature_corrected_kwh;
+            }
+            _ => kpis.push(Kpi { start, ..*day }),
+        }
+    }
+    kpis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qc;
+    use chrono::Duration;
+
+    #[test]
+    fn excluded_irradiance_does_not_change_pr() {
+        // 水平に置いたパネルなのでパネル面の日射量は全天日射量と同じ
+        // 交流出力は常に日射量の0.8倍なので性能比は0.8
+        let start = Local.with_ymd_and_hms(2022, 10, 9, 10, 0, 0).unwrap();
+        let len = 3600;
+        let dts = (0..len)
+            .map(|i| start + Duration::seconds(i))
+            .collect::<Vec<_>>();
+        let mut irradiance = vec![0.6; len as usize];
+        // 品質管理で除かれる値の時間帯。交流出力はそのまま残る
+        irradiance[1000..2000].fill(5.0);
+        let mut series = TimeSeries::new(dts)
+            .with_column(Field::SolarIrradiance, irradiance)
+            .unwrap()
+            .with_column(Field::AirTemperature, vec![20.0; len as usize])
+            .unwrap()
+            .with_column(Field::AcPw, vec![0.6 * 0.8 * 5.0; len as usize])
+            .unwrap();
+        let site = Site {
+            rated_kw: Some(5.0),
+            ..Site::default()
+        };
+        qc::apply_qc(&site, &mut series).unwrap();
+        assert_eq!(series.exclude_flagged(), 1000);
+
+        let kpis = daily_kpis(&site, &series, 60).unwrap();
+        assert_eq!(kpis.len(), 1);
+        let kpi = kpis[0];
+        assert!((kpi.performance_ratio() - 0.8).abs() < 1e-6);
+        // 積算するのは値が揃っている時刻だけだが、設備利用率は1日の長さで割る
+        assert!((kpi.insolation - 0.6 * 2598.0 / 3600.0).abs() < 1e-6);
+        assert_eq!(kpi.hours, 24.0);
+        assert!((kpi.capacity_factor() - kpi.ac_kwh / (5.0 * 24.0)).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,83 @@
+// 読み込んだ系列に対する集計・分析
+
+pub mod anomaly;
+pub mod battery;
+pub mod calibration;
+pub mod clipping;
+pub mod clock;
+pub mod energy;
+pub mod grid;
+pub mod inverter;
+pub mod irradiance;
+pub mod kpi;
+pub mod sky;
+
+use chrono::{DateTime, Local};
+
+// 値の列の基本統計量
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    // 時刻の間隔に合わせて時間で積算した値(kwならkwh)。間隔が空いたところは積算しない
+    pub integrated: f64,
+}
+
+impl Summary {
+    // valuesはdtsの時刻の値。時刻は飛んでいてもよい
+    pub fn of(dts: &[DateTime<Local>], values: &[f64]) -> Option<Summary> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let count = values.len();
+        let (min, max) = values
+            .iter()
+            .fold((f64::NAN, f64::NAN), |(m, n), v| (v.min(m), v.max(n)));
+        let sum = values.iter().sum::<f64>();
+
+        Some(Summary {
+            count,
+            min,
+            max,
+            mean: sum / count as f64,
+            integrated: integrate(dts, values),
+        })
+    }
+}
+
+// 欠損の無い値の列を時間で積算する。max_gap_secondsは発電量の積算と同じ
+fn integrate(dts: &[DateTime<Local>], values: &[f64]) -> f64 {
+    let values = values
+        .iter()
+        .map(|v| Some(*v))
+        .collect::<Vec<Option<f64>>>();
+    energy::integrate(
+        dts,
+        &values,
+        0..values.len(),
+        energy::EnergyParams::default().max_gap_seconds,
+    )
+    .0
+}
+
+// ピアソンの相関係数。どちらかが一定値のときはNone
+pub fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len() as f64;
+    let mean_a = a.iter().sum::<f64>() / n;
+    let mean_b = b.iter().sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b.iter()) {
+        let (dx, dy) = (x - mean_a, y - mean_b);
+        cov += dx * dy;
+        var_a += dx * dx;
+        var_b += dy * dy;
+    }
+    if var_a <= 0.0 || var_b <= 0.0 {
+        return None;
+    }
+    Some(cov / (var_a * var_b).sqrt())
+}
@@ -0,0 +1,348 @@
+// 実測の日射量と晴天日射量から天気(晴天・部分的に曇り・曇天)を判定する
+// 晴天の判定はReno & Hansen (2016) の基準を1分平均値に適用する
+use std::fmt;
+
+use chrono::{DateTime, Local, NaiveDate};
+
+use super::irradiance::{IrradianceComparison, MIN_CLEAR_SKY_KW};
+use crate::timeseries;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkyCondition {
+    Clear,
+    PartlyCloudy,
+    Overcast,
+}
+
+impl SkyCondition {
+    // CSVなどで使う名前
+    pub fn name(self) -> &'static str {
+        match self {
+            SkyCondition::Clear => "clear",
+            SkyCondition::PartlyCloudy => "partly_cloudy",
+            SkyCondition::Overcast => "overcast",
+        }
+    }
+}
+
+impl fmt::Display for SkyCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            SkyCondition::Clear => "晴天",
+            SkyCondition::PartlyCloudy => "部分的に曇り",
+            SkyCondition::Overcast => "曇天",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+// 判定の基準。日射量の単位はW/m^2、傾きは1分あたり
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ClearSkyCriteria {
+    // 判定する区間の長さ(分)
+    pub window_minutes: usize,
+    // 平均値の差の上限
+    pub mean_diff: f64,
+    // 最大値の差の上限
+    pub max_diff: f64,
+    // 折れ線の長さの差の範囲
+    pub lower_line_length: f64,
+    pub upper_line_length: f64,
+    // 傾きの標準偏差を平均値で割った値の上限
+    pub var_diff: f64,
+    // 傾きの差の最大値の上限
+    pub slope_dev: f64,
+    // 晴天日射量の倍率を合わせ込む回数の上限
+    pub max_iterations: usize,
+    // 晴天指数(実測/晴天)がこれ未満の区間・日は曇天とする
+    pub overcast_index: f64,
+    // 晴天の区間がこの割合以上の日を晴天の日とする
+    pub clear_day_fraction: f64,
+}
+
+impl Default for ClearSkyCriteria {
+    fn default() -> Self {
+        ClearSkyCriteria {
+            window_minutes: 10,
+            mean_diff: 75.0,
+            max_diff: 75.0,
+            lower_line_length: -5.0,
+            upper_line_length: 10.0,
+            var_diff: 0.005,
+            slope_dev: 8.0,
+            max_iterations: 20,
+            overcast_index: 0.4,
+            clear_day_fraction: 0.8,
+        }
+    }
+}
+
+// 日中の1区間の判定結果
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct IntervalSky {
+    pub start: DateTime<Local>,
+    pub condition: SkyCondition,
+    // 区間の平均の晴天指数
+    pub clear_sky_index: f64,
+}
+
+// 1日の判定結果
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct DaySky {
+    pub date: NaiveDate,
+    pub condition: SkyCondition,
+    // 日中の区間のうち晴天と判定した割合
+    pub clear_fraction: f64,
+    // 日中の積算値の比(実測/晴天)
+    pub clear_sky_ratio: f64,
+    // 晴天日射量に掛けた倍率(晴天の区間に合わせ込んだ値)
+    pub alpha: f64,
+    pub intervals: Vec<IntervalSky>,
+}
+
+// 1分平均値の列
+struct Minutes {
+    starts: Vec<DateTime<Local>>,
+    measured: Vec<f64>,
+    clear_sky: Vec<f64>,
+}
+
+pub fn classify_days(
+    comparison: &IrradianceComparison,
+    criteria: &ClearSkyCriteria,
+) -> Vec<DaySky> {
+    timeseries::day_ranges(&comparison.dts)
+        .into_iter()
+        .filter_map(|(date, range)| {
+            let minutes = minute_means(comparison, range);
+            classify_day(date, &minutes, criteria)
+        })
+        .collect()
+}
+
+fn minute_means(comparison: &IrradianceComparison, range: std::ops::Range<usize>) -> Minutes {
+    let mut minutes = Minutes {
+        starts: Vec::new(),
+        measured: Vec::new(),
+        clear_sky: Vec::new(),
+    };
+    let mut current: Option<(i64, usize)> = None;
+    for i in range {
+        let dt = comparison.dts[i];
+        let minute = dt.timestamp().div_euclid(60);
+        match current {
+            Some((last, ref mut count)) if last == minute => {
+                *count += 1;
+                *minutes.measured.last_mut().unwrap() += comparison.measured[i];
+                *minutes.clear_sky.last_mut().unwrap() += comparison.clear_sky[i];
+            }
+            _ => {
+                finish_minute(&mut minutes, current);
+                current = Some((minute, 1));
+                minutes.starts.push(dt);
+                minutes.measured.push(comparison.measured[i]);
+                minutes.clear_sky.push(comparison.clear_sky[i]);
+            }
+        }
+    }
+    finish_minute(&mut minutes, current);
+    minutes
+}
+
+// 合計を平均値にしてW/m^2に直す
+fn finish_minute(minutes: &mut Minutes, current: Option<(i64, usize)>) {
+    if let Some((_, count)) = current {
+        let scale = 1000.0 / count as f64;
+        *minutes.measured.last_mut().unwrap() *= scale;
+        *minutes.clear_sky.last_mut().unwrap() *= scale;
+    }
+}
+
+fn classify_day(date: NaiveDate, minutes: &Minutes, criteria: &ClearSkyCriteria) -> Option<DaySky> {
+    let window = criteria.window_minutes.max(2);
+    // 晴天日射量が小さい朝夕の区間は判定しない
+    let windows = (0..minutes.measured.len() / window)
+        .map(|w| w * window..(w + 1) * window)
+        .filter(|range| {
+            minutes.clear_sky[range.clone()]
+                .iter()
+                .all(|clear_sky| *clear_sky >= MIN_CLEAR_SKY_KW * 1000.0)
+        })
+        .collect::<Vec<_>>();
+    if windows.is_empty() {
+        return None;
+    }
+
+    // 晴天と判定した区間に晴天日射量の倍率を合わせ込んでから判定し直す
+    let mut alpha = 1.0;
+    let mut clear = Vec::new();
+    for _ in 0..criteria.max_iterations.max(1) {
+        clear = windows
+            .iter()
+            .map(|range| {
+                let clear_sky = minutes.clear_sky[range.clone()]
+                    .iter()
+                    .map(|value| value * alpha)
+                    .collect::<Vec<f64>>();
+                is_clear_window(&minutes.measured[range.clone()], &clear_sky, criteria)
+            })
+            .collect::<Vec<bool>>();
+
+        let (numerator, denominator) = windows
+            .iter()
+            .zip(clear.iter())
+            .filter(|(_, clear)| **clear)
+            .flat_map(|(range, _)| range.clone())
+            .fold((0.0, 0.0), |(n, d), i| {
+                let clear_sky = minutes.clear_sky[i];
+                (
+                    n + minutes.measured[i] * clear_sky,
+                    d + clear_sky * clear_sky,
+                )
+            });
+        if denominator <= 0.0 {
+            break;
+        }
+        let new_alpha = numerator / denominator;
+        if (new_alpha - alpha).abs() < 1e-4 {
+            alpha = new_alpha;
+            break;
+        }
+        alpha = new_alpha;
+    }
+
+    let intervals = windows
+        .iter()
+        .zip(clear.iter())
+        .map(|(range, clear)| {
+            let measured = minutes.measured[range.clone()].iter().sum::<f64>();
+            let clear_sky = minutes.clear_sky[range.clone()].iter().sum::<f64>();
+            let clear_sky_index = measured / clear_sky;
+            let condition = if *clear {
+                SkyCondition::Clear
+            } else if clear_sky_index < criteria.overcast_index {
+                SkyCondition::Overcast
+            } else {
+                SkyCondition::PartlyCloudy
+            };
+            IntervalSky {
+                start: minutes.starts[range.start],
+                condition,
+                clear_sky_index,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let clear_count = clear.iter().filter(|clear| **clear).count();
+    let clear_fraction = clear_count as f64 / windows.len() as f64;
+    let (measured, clear_sky) = windows
+        .iter()
+        .flat_map(|range| range.clone())
+        .fold((0.0, 0.0), |(m, c), i| {
+            (m + minutes.measured[i], c + minutes.clear_sky[i])
+        });
+    let clear_sky_ratio = measured / clear_sky;
+    let condition = if clear_fraction >= criteria.clear_day_fraction {
+        SkyCondition::Clear
+    } else if clear_sky_ratio < criteria.overcast_index {
+        SkyCondition::Overcast
+    } else {
+        SkyCondition::PartlyCloudy
+    };
+
+    Some(DaySky {
+        date,
+        condition,
+        clear_fraction,
+        clear_sky_ratio,
+        alpha,
+        intervals,
+    })
+}
+
+// Reno & Hansenの5つの基準を全て満たせば晴天
+fn is_clear_window(measured: &[f64], clear_sky: &[f64], criteria: &ClearSkyCriteria) -> bool {
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let max = |values: &[f64]| values.iter().cloned().fold(f64::NAN, f64::max);
+    let diffs = |values: &[f64]| values.windows(2).map(|w| w[1] - w[0]).collect::<Vec<f64>>();
+    // 1分刻みの折れ線の長さ
+    let line_length = |values: &[f64]| {
+        diffs(values)
+            .iter()
+            .map(|d| (d * d + 1.0).sqrt())
+            .sum::<f64>()
+    };
+
+    let measured_mean = mean(measured);
+    let measured_diffs = diffs(measured);
+    let clear_sky_diffs = diffs(clear_sky);
+
+    let mean_ok = (measured_mean - mean(clear_sky)).abs() < criteria.mean_diff;
+    let max_ok = (max(measured) - max(clear_sky)).abs() < criteria.max_diff;
+    let line_length_diff = line_length(measured) - line_length(clear_sky);
+    let line_length_ok = criteria.lower_line_length < line_length_diff
+        && line_length_diff < criteria.upper_line_length;
+
+    let diffs_mean = mean(&measured_diffs);
+    let slope_std = (measured_diffs
+        .iter()
+        .map(|d| (d - diffs_mean).powi(2))
+        .sum::<f64>()
+        / (measured_diffs.len() as f64 - 1.0).max(1.0))
+    .sqrt();
+    let var_ok = measured_mean > 0.0 && slope_std / measured_mean < criteria.var_diff;
+    let slope_ok = measured_diffs
+        .iter()
+        .zip(clear_sky_diffs.iter())
+        .all(|(m, c)| (m - c).abs() < criteria.slope_dev);
+
+    mean_ok && max_ok && line_length_ok && var_ok && slope_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    // 10分間に600W/m^2から1分あたり2W/m^2ずつ増える晴天日射量
+    fn clear_sky() -> Vec<f64> {
+        (0..10).map(|i| 600.0 + 2.0 * i as f64).collect()
+    }
+
+    // Reno & Hansen (2016) の表1の10分間の区間の基準で判定する
+    #[test]
+    fn reno_hansen_criteria() {
+        let criteria = ClearSkyCriteria::default();
+        let clear_sky = clear_sky();
+        assert!(is_clear_window(&clear_sky, &clear_sky, &criteria));
+
+        // 平均値の差が75W/m^2未満なら晴天
+        let dimmed = clear_sky.iter().map(|v| v - 50.0).collect::<Vec<f64>>();
+        assert!(is_clear_window(&dimmed, &clear_sky, &criteria));
+        let overcast = clear_sky.iter().map(|v| v * 0.3).collect::<Vec<f64>>();
+        assert!(!is_clear_window(&overcast, &clear_sky, &criteria));
+
+        // 雲が1分間だけかかると、折れ線の長さと傾きの差が基準を超える
+        let mut cloudy = clear_sky.clone();
+        cloudy[5] = 500.0;
+        assert!(!is_clear_window(&cloudy, &clear_sky, &criteria));
+    }
+
+    #[test]
+    fn clear_sky_scale_is_fitted_to_clear_windows() {
+        // 晴天日射量より一様に8%低い日は、倍率を合わせ込んで晴天と判定する
+        let start = Local.with_ymd_and_hms(2022, 10, 1, 11, 0, 0).unwrap();
+        let clear_sky = (0..60).map(|i| 800.0 + i as f64).collect::<Vec<f64>>();
+        let minutes = Minutes {
+            starts: (0..60).map(|i| start + Duration::minutes(i)).collect(),
+            measured: clear_sky.iter().map(|v| v * 0.92).collect(),
+            clear_sky,
+        };
+        let day = classify_day(start.date_naive(), &minutes, &ClearSkyCriteria::default()).unwrap();
+        assert_eq!(day.condition, SkyCondition::Clear);
+        assert!((day.alpha - 0.92).abs() < 1e-4);
+    }
+}
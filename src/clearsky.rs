@@ -0,0 +1,303 @@
+// 晴天時の地表での日射量のモデル
+// 日射量の単位は全てW/m^2、角度の単位は度
+use serde::Deserialize;
+
+use crate::q;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClearSkyModel {
+    // 天頂角だけから全天日射量を求める簡単なモデル
+    Haurwitz,
+    // Linke混濁係数を使うIneichen-Perezモデル
+    #[default]
+    Ineichen,
+    // オゾン・水蒸気・エアロゾルを考慮するBirdモデル
+    Bird,
+}
+
+impl std::str::FromStr for ClearSkyModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "haurwitz" => Ok(ClearSkyModel::Haurwitz),
+            "ineichen" => Ok(ClearSkyModel::Ineichen),
+            "bird" => Ok(ClearSkyModel::Bird),
+            _ => Err(format!(
+                "不明な晴天モデル \"{}\" です (指定可能な値: haurwitz, ineichen, bird)",
+                s
+            )),
+        }
+    }
+}
+
+// 全天日射量(水平面)・法線面直達日射量・散乱日射量(水平面)
+// Haurwitzモデルは全天日射量しか求まらないので、dniとdhiはNaNになる
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearSkyIrradiance {
+    pub ghi: f64,
+    pub dni: f64,
+    pub dhi: f64,
+}
+
+impl ClearSkyIrradiance {
+    fn zero() -> Self {
+        ClearSkyIrradiance {
+            ghi: 0.0,
+            dni: 0.0,
+            dhi: 0.0,
+        }
+    }
+}
+
+// Birdモデルの大気の条件
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct BirdParams {
+    // オゾン量(atm-cm)
+    pub ozone: f64,
+    // 可降水量(cm)
+    pub precipitable_water: f64,
+    // 波長380nmと500nmのエアロゾルの光学的厚さ
+    pub aod380: f64,
+    pub aod500: f64,
+    // エアロゾルの前方散乱の割合
+    pub asymmetry: f64,
+    // 地表面のアルベド
+    pub albedo: f64,
+}
+
+impl Default for BirdParams {
+    fn default() -> Self {
+        BirdParams {
+            ozone: 0.3,
+            precipitable_water: 1.42,
+            aod380: 0.15,
+            aod500: 0.1,
+            asymmetry: 0.85,
+            albedo: 0.2,
+        }
+    }
+}
+
+// モデルの計算に使う地点・時刻ごとの値
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearSkyInputs {
+    // 大気差を補正した天頂角
+    pub zenith: f64,
+    // 大気外の法線面日射量
+    pub dni_extra: f64,
+    // 標高(m)
+    pub altitude: f64,
+    pub linke_turbidity: f64,
+    // IneichenモデルでPerezの補正を使うか
+    pub perez_enhancement: bool,
+    pub bird: BirdParams,
+}
+
+pub fn calc_clear_sky(model: ClearSkyModel, inputs: &ClearSkyInputs) -> ClearSkyIrradiance {
+    match model {
+        ClearSkyModel::Haurwitz => ClearSkyIrradiance {
+            ghi: haurwitz(inputs.zenith),
+            dni: f64::NAN,
+            dhi: f64::NAN,
+        },
+        ClearSkyModel::Ineichen => ineichen(
+            inputs.zenith,
+            inputs.altitude,
+            inputs.linke_turbidity,
+            inputs.dni_extra,
+            inputs.perez_enhancement,
+        ),
+        ClearSkyModel::Bird => bird(
+            inputs.zenith,
+            inputs.altitude,
+            inputs.dni_extra,
+            &inputs.bird,
+        ),
+    }
+}
+
+// Haurwitz (1945) の全天日射量
+pub fn haurwitz(zenith: f64) -> f64 {
+    let cos_zenith = zenith.to_radians().cos();
+    if cos_zenith <= 0.0 {
+        return 0.0;
+    }
+    1098.0 * cos_zenith * (-0.059 / cos_zenith).exp()
+}
+
+// Ineichen & Perez (2002) のモデル
+// perez_enhancementがtrueなら、大気路程が長いときの過小評価を補うPerezの補正を掛ける(pvlibの既定は掛けない)
+pub fn ineichen(
+    zenith: f64,
+    altitude: f64,
+    linke_turbidity: f64,
+    dni_extra: f64,
+    perez_enhancement: bool,
+) -> ClearSkyIrradiance {
+    // 太陽が地平線より下のときエアマスはNaNになるが、ineichen_with_airmassで0にする
+    let airmass = q::calc_absolute_airmass(
+        q::calc_relative_airmass(zenith),
+        q::calc_pressure_from_altitude(altitude),
+    );
+    ineichen_with_airmass(
+        zenith,
+        airmass,
+        altitude,
+        linke_turbidity,
+        dni_extra,
+        perez_enhancement,
+    )
+}
+
+// 絶対エアマスを与えてIneichenモデルを計算する
+fn ineichen_with_airmass(
+    zenith: f64,
+    airmass: f64,
+    altitude: f64,
+    linke_turbidity: f64,
+    dni_extra: f64,
+    perez_enhancement: bool,
+) -> ClearSkyIrradiance {
+    let cos_zenith = zenith.to_radians().cos();
+    if cos_zenith <= 0.0 {
+        return ClearSkyIrradiance::zero();
+    }
+    let tl = linke_turbidity;
+
+    let fh1 = (-altitude / 8000.0).exp();
+    let fh2 = (-altitude / 1250.0).exp();
+    let cg1 = 5.09e-5 * altitude + 0.868;
+    let cg2 = 3.92e-5 * altitude + 0.0387;
+
+    let mut ghi = (-cg2 * airmass * (fh1 + fh2 * (tl - 1.0))).exp();
+    if perez_enhancement {
+        ghi *= (0.01 * airmass.powf(1.8)).exp();
+    }
+    let ghi = cg1 * dni_extra * cos_zenith * ghi.max(0.0);
+
+    let b = 0.664 + 0.163 / fh1;
+    let bnci = dni_extra * (b * (-0.09 * airmass * (tl - 1.0)).exp()).max(0.0);
+    let bnci_2 = ghi
+        * ((1.0 - (0.1 - 0.2 * (-tl).exp()) / (0.1 + 0.882 / fh1)) / cos_zenith).clamp(0.0, 1e20);
+    let dni = bnci.min(bnci_2);
+
+    ClearSkyIrradiance {
+        ghi,
+        dni,
+        dhi: ghi - dni * cos_zenith,
+    }
+}
+
+// Bird & Hulstrom (1981) のモデル
+pub fn bird(zenith: f64, altitude: f64, dni_extra: f64, params: &BirdParams) -> ClearSkyIrradiance {
+    let cos_zenith = zenith.to_radians().cos();
+    if cos_zenith <= 0.0 {
+        return ClearSkyIrradiance::zero();
+    }
+    let airmass = q::calc_relative_airmass(zenith);
+    let am_press = q::calc_absolute_airmass(airmass, q::calc_pressure_from_altitude(altitude));
+
+    // レイリー散乱・オゾン・その他の気体・水蒸気・エアロゾルによる透過率
+    let t_rayleigh = (-0.0903 * am_press.powf(0.84) * (1.0 + am_press - am_press.powf(1.01))).exp();
+    let am_o3 = params.ozone * airmass;
+    let t_ozone = 1.0
+        - 0.1611 * am_o3 * (1.0 + 139.48 * am_o3).powf(-0.3034)
+        - 0.002715 * am_o3 / (1.0 + 0.044 * am_o3 + 0.0003 * am_o3.powi(2));
+    let t_gases = (-0.0127 * am_press.powf(0.26)).exp();
+    let am_h2o = airmass * params.precipitable_water;
+    let t_water = 1.0 - 2.4959 * am_h2o / ((1.0 + 79.034 * am_h2o).powf(0.6828) + 6.385 * am_h2o);
+    let taua = 0.2758 * params.aod380 + 0.35 * params.aod500;
+    let t_aerosol =
+        (-taua.powf(0.873) * (1.0 + taua - taua.powf(0.7088)) * airmass.powf(0.9108)).exp();
+    let taa = 1.0 - 0.1 * (1.0 - airmass + airmass.powf(1.06)) * (1.0 - t_aerosol);
+    let rs = 0.0685 + (1.0 - params.asymmetry) * (1.0 - t_aerosol / taa);
+
+    let dni = 0.9662 * dni_extra * t_aerosol * t_water * t_gases * t_ozone * t_rayleigh;
+    let direct_horizontal = dni * cos_zenith;
+    let scattered = dni_extra
+        * cos_zenith
+        * 0.79
+        * t_ozone
+        * t_gases
+        * t_water
+        * taa
+        * (0.5 * (1.0 - t_rayleigh) + params.asymmetry * (1.0 - t_aerosol / taa))
+        / (1.0 - airmass + airmass.powf(1.02));
+    let ghi = (direct_horizontal + scattered) / (1.0 - params.albedo * rs);
+
+    ClearSkyIrradiance {
+        ghi,
+        dni,
+        dhi: ghi - direct_horizontal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn haurwitz_matches_pvlib() {
+        // pvlibのtest_clearsky.pyのtest_haurwitzの値 (太陽高度5, 10, 30, 50, 90度)
+        for (elevation, expected) in [
+            (5.0, 48.6298687941956),
+            (10.0, 135.741748091813),
+            (30.0, 487.894132885425),
+            (50.0, 778.766689344363),
+            (90.0, 1035.09203253450),
+        ] {
+            assert_close(haurwitz(90.0 - elevation), expected, 1e-6);
+        }
+        assert_eq!(haurwitz(90.05), 0.0);
+    }
+
+    #[test]
+    fn ineichen_matches_pvlib() {
+        // pvlibのtest_clearsky.pyのtest_ineichen_nansの値
+        // 天頂角10度、絶対エアマス1、リンケ混濁係数3、大気外日射量1370W/m^2、標高0m
+        let irradiance = ineichen_with_airmass(10.0, 1.0, 0.0, 3.0, 1370.0, false);
+        assert_close(irradiance.ghi, 1042.72590228, 1e-6);
+        assert_close(irradiance.dni, 946.35279683, 1e-6);
+        assert_close(irradiance.dhi, 110.75033088, 1e-6);
+    }
+
+    #[test]
+    fn ineichen_perez_enhancement_only_changes_ghi() {
+        // Ineichen & Perez (2002) の式を手計算した値
+        // 天頂角30度、標高700m、リンケ混濁係数3、大気外日射量1367W/m^2
+        let plain = ineichen(30.0, 700.0, 3.0, 1367.0, false);
+        assert_close(plain.ghi, 925.8215, 1e-3);
+        assert_close(plain.dni, 950.7365, 1e-3);
+        // Perezの補正は全天日射量だけに掛かり、直達日射量は変わらない
+        let enhanced = ineichen(30.0, 700.0, 3.0, 1367.0, true);
+        assert_close(enhanced.ghi, 936.1852, 1e-3);
+        assert_close(enhanced.dni, plain.dni, 1e-9);
+    }
+
+    #[test]
+    fn bird_matches_hand_calculation() {
+        // Bird & Hulstrom (1981) の式を手計算した値(pvlibのtest_birdと同じ大気の条件)
+        // 天頂角30度、標高1600m、大気外日射量1367W/m^2
+        let params = BirdParams {
+            precipitable_water: 1.5,
+            ..BirdParams::default()
+        };
+        let irradiance = bird(30.0, 1600.0, 1367.0, &params);
+        assert_close(irradiance.ghi, 925.1443, 1e-3);
+        assert_close(irradiance.dni, 938.0460, 1e-3);
+        assert_close(irradiance.dhi, 112.7727, 1e-3);
+    }
+}
@@ -0,0 +1,1921 @@
+use std::io::Write;
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use clap::{Args, Parser, Subcommand};
+use plotters::style::{BLUE, GREEN, MAGENTA, RED};
+use tracing::info;
+
+use rust_solar_power_data_visualization::analysis::anomaly::{self, AnomalyParams, Severity};
+use rust_solar_power_data_visualization::analysis::battery::{self, BatteryParams};
+use rust_solar_power_data_visualization::analysis::calibration::{self, DriftParams};
+use rust_solar_power_data_visualization::analysis::clipping::{self, ClippingParams};
+use rust_solar_power_data_visualization::analysis::clock;
+use rust_solar_power_data_visualization::analysis::energy::{self, EnergyParams, YieldPeriod};
+use rust_solar_power_data_visualization::analysis::grid::{self, Distribution, GridQuantity};
+use rust_solar_power_data_visualization::analysis::inverter::{self, InverterParams};
+use rust_solar_power_data_visualization::analysis::irradiance::{
+    ComparisonSummary, IrradianceComparison,
+};
+use rust_solar_power_data_visualization::analysis::kpi;
+use rust_solar_power_data_visualization::analysis::sky::{self, ClearSkyCriteria, SkyCondition};
+use rust_solar_power_data_visualization::analysis::Summary;
+use rust_solar_power_data_visualization::clearsky::ClearSkyModel;
+use rust_solar_power_data_visualization::config::{Config, Site};
+use rust_solar_power_data_visualization::es::{self, EsSource, Field};
+use rust_solar_power_data_visualization::logging::Verbosity;
+use rust_solar_power_data_visualization::model;
+use rust_solar_power_data_visualization::plot::{self, Line, Marker};
+use rust_solar_power_data_visualization::q;
+use rust_solar_power_data_visualization::qc::{self, QcFlags};
+use rust_solar_power_data_visualization::solar_position::{SolarGeometry, SolarPositionModel};
+use rust_solar_power_data_visualization::timeseries;
+use rust_solar_power_data_visualization::{filepath, Error, Result, TimeSeries};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "太陽光発電の計測データを取得・可視化・分析する")]
+pub struct Cli {
+    /// デバッグログと処理時間を表示する
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// エラー以外のログとプログレスバーを表示しない
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// 設定ファイル (省略するとカレントディレクトリの solar.toml があれば読み込む)
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+
+    /// 設定ファイルに定義した地点の名前 (省略すると最初の地点)
+    #[arg(long, global = true)]
+    pub site: Option<String>,
+
+    /// 設置場所の緯度(度)。設定ファイルの値より優先する
+    #[arg(long, global = true)]
+    pub lat: Option<f64>,
+
+    /// 設置場所の経度(度)。設定ファイルの値より優先する
+    #[arg(long, global = true)]
+    pub lng: Option<f64>,
+
+    /// 読み込むときに時刻に足す秒数(ロガーの時計のずれの補正)。設定ファイルの値より優先する
+    #[arg(long, global = true, allow_hyphen_values = true)]
+    pub clock_offset: Option<i64>,
+
+    /// 品質管理で除くと判定した値も分析に使う
+    #[arg(long, global = true)]
+    pub no_qc: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// 指定した期間のデータをElasticsearchから取得してキャッシュする
+    Fetch(PeriodArgs),
+
+    /// 指定した期間の項目をグラフにする
+    Plot {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// グラフにする項目 (省略すると設定ファイルの [plot] field)
+        #[arg(long)]
+        field: Option<Field>,
+
+        /// 出力先のPNGファイル (省略すると設定ファイルの [plot] out)
+        #[arg(long)]
+        out: Option<String>,
+
+        /// 大気外日射量の理論値を重ねて描画する
+        #[arg(long)]
+        theory: bool,
+
+        /// 日の出・南中・日の入りの時刻に縦線を引く
+        #[arg(long)]
+        sun: bool,
+
+        /// 晴天日射量を重ねて描画する (haurwitz, ineichen, bird)。値を省略すると設定ファイルの値
+        #[arg(long, value_name = "MODEL")]
+        clear_sky: Option<Option<ClearSkyModel>>,
+
+        /// 晴天時のパネル面の日射量を重ねて描画する
+        #[arg(long)]
+        poa: bool,
+    },
+
+    /// 指定した期間の項目の統計値を表示する
+    Analyze {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 集計する項目
+        #[arg(long, default_value = "solar_irradiance")]
+        field: Field,
+    },
+
+    /// 指定した期間の項目をCSVで出力する
+    Export {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 出力する項目(複数指定可)
+        #[arg(long = "field", default_value = "solar_irradiance", num_args = 1..)]
+        fields: Vec<Field>,
+
+        /// 出力先のCSVファイル(省略すると標準出力)
+        #[arg(long)]
+        out: Option<String>,
+
+        /// 実測の日射量から求めた直達・散乱日射量とパネル面の日射量(W/m^2)の列を追加する
+        #[arg(long)]
+        decompose: bool,
+    },
+
+    /// 実測の日射量を大気外日射量・晴天日射量と比べる
+    Compare {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 時刻ごとの実測値・理論値・晴天指数を出力するCSVファイル
+        #[arg(long)]
+        out: Option<String>,
+
+        /// 実測値と理論値を重ねたグラフの出力先のPNGファイル
+        #[arg(long)]
+        plot: Option<String>,
+    },
+
+    /// 日ごとに晴天・部分的に曇り・曇天を判定する
+    Classify {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 10分ごとの判定結果も表示する
+        #[arg(long)]
+        intervals: bool,
+
+        /// 晴天と判定した日付だけを表示する
+        #[arg(long, conflicts_with = "intervals")]
+        clear_only: bool,
+    },
+
+    /// 実測の日射量と大気外日射量の相互相関からロガーの時計のずれを推定する
+    Clock {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 探すずれの最大値(時間)
+        #[arg(long, default_value_t = 12, value_parser = clap::value_parser!(i64).range(0..))]
+        max_offset_hours: i64,
+    },
+
+    /// 品質管理で判定された値の件数を項目・判定ごとに表示する
+    Qc {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 判定する項目(複数指定可)
+        #[arg(
+            long = "field",
+            default_value = "solar_irradiance",
+            num_args = 1..
+        )]
+        fields: Vec<Field>,
+    },
+
+    /// 日・月・年ごとの発電量を出力の積算値と積算電力量のカウンタから求める
+    Energy {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 集計する単位 (day, month, year)
+        #[arg(long, default_value = "day")]
+        by: YieldPeriod,
+
+        /// 集計結果を出力するCSVファイル
+        #[arg(long)]
+        out: Option<String>,
+    },
+
+    /// 性能比・気温補正した性能比・定格出力あたりの発電量・設備利用率を求める
+    Kpi {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 集計する単位 (day, month, year)
+        #[arg(long, default_value = "day")]
+        by: YieldPeriod,
+
+        /// 集計結果を出力するCSVファイル
+        #[arg(long)]
+        out: Option<String>,
+    },
+
+    /// パワーコンディショナの負荷率ごとの変換効率と加重効率を求め、効率が落ちた日を検出する
+    Inverter {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 日ごとの効率も表示する
+        #[arg(long)]
+        days: bool,
+
+        /// 負荷率ごとの効率を出力するCSVファイル
+        #[arg(long)]
+        out: Option<String>,
+    },
+
+    /// 交流出力がパワーコンディショナの定格で頭打ちになった時間帯と失われた発電量を求める
+    Clipping {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 頭打ちになった時間帯も表示する
+        #[arg(long)]
+        periods: bool,
+    },
+
+    /// 予測との差の外れ値・出力の急な低下・値の固着・ありえない値を検出する。
+    /// 品質管理で除く値も検出の対象にするので、--no-qcに関わらず除かずに読み込む
+    Anomaly {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 表示する最低の重大度 (warning, critical)
+        #[arg(long, default_value = "warning")]
+        min_severity: Severity,
+
+        /// 検出結果を出力するCSVファイル
+        #[arg(long)]
+        out: Option<String>,
+    },
+
+    /// 系統の周波数と交流電圧の分布、許容範囲を外れた時間帯(周波数の逸脱・電圧のサグ/スウェル)を求める
+    Grid {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 許容範囲を外れた時間帯も表示する
+        #[arg(long)]
+        events: bool,
+
+        /// 許容範囲を外れた時間帯を出力するCSVファイル
+        #[arg(long)]
+        out: Option<String>,
+
+        /// 周波数と電圧の度数分布を出力するCSVファイル
+        #[arg(long)]
+        histogram: Option<String>,
+    },
+
+    /// 蓄電池の残量から日ごとの充放電量・等価サイクル数・放電の深さの分布・発電量との相関を求める
+    Battery {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 放電の1回ごとの深さも表示する
+        #[arg(long)]
+        cycles: bool,
+
+        /// 日ごとの集計結果を出力するCSVファイル
+        #[arg(long)]
+        out: Option<String>,
+
+        /// 残量と発電出力のグラフを出力するPNGファイル
+        #[arg(long)]
+        plot: Option<String>,
+
+        /// 放電の深さの度数分布のグラフを出力するPNGファイル
+        #[arg(long)]
+        depth_plot: Option<String>,
+    },
+
+    /// 晴天の日の南中時刻付近の実測/晴天日射量の比の推移から日射計の汚れ・劣化を調べる
+    Drift {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 変化点とみなす比の平均値の差の下限
+        #[arg(long, default_value_t = 0.03)]
+        min_shift: f64,
+
+        /// 変化点の前後それぞれに必要な晴天の日数
+        #[arg(long, default_value_t = 5)]
+        min_segment_days: usize,
+
+        /// 比の推移のグラフの出力先のPNGファイル
+        #[arg(long)]
+        out: Option<String>,
+    },
+
+    /// 実測の日射量と気温から予測した交流出力を実測値と比べる
+    Predict {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// 予測値と実測値を重ねたグラフの出力先のPNGファイル
+        #[arg(long)]
+        out: Option<String>,
+    },
+
+    /// 指定した日時の太陽の位置と、その日の日の出・南中・日の入りの時刻を表示する
+    Sun {
+        /// 日時 (例: 2022-09-28T12:00:00)
+        #[arg(long, value_parser = parse_datetime)]
+        at: DateTime<Local>,
+
+        /// 太陽の位置の計算方法 (spencer, spa)。省略すると設定ファイルの値
+        #[arg(long)]
+        model: Option<SolarPositionModel>,
+    },
+
+    /// 取得済みのJSONファイルを管理する
+    #[command(subcommand)]
+    Cache(CacheCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// キャッシュ済みの日付とファイルサイズを表示する
+    List,
+
+    /// キャッシュを削除する(期間を省略すると全て削除する)
+    Clear {
+        /// 削除する期間の開始日
+        #[arg(long, value_parser = parse_datetime)]
+        from: Option<DateTime<Local>>,
+
+        /// 削除する期間の終了日(この日は含まない)
+        #[arg(long, value_parser = parse_datetime)]
+        to: Option<DateTime<Local>>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct PeriodArgs {
+    /// 期間の開始日時 (例: 2022-09-28, 2022-09-28T06:00:00)
+    #[arg(long, value_parser = parse_datetime)]
+    pub from: DateTime<Local>,
+
+    /// 期間の終了日時(この日時は含まない)。省略すると開始日時の1日後
+    #[arg(long, value_parser = parse_datetime)]
+    pub to: Option<DateTime<Local>>,
+}
+
+impl PeriodArgs {
+    // 開始日時から終了日時までの日数
+    pub fn span(&self) -> f64 {
+        let to = self.to.unwrap_or(self.from + Duration::days(1));
+        (to - self.from).num_seconds() as f64 / 86400.0
+    }
+}
+
+fn parse_datetime(s: &str) -> std::result::Result<DateTime<Local>, String> {
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+        })
+        .map_err(|_| {
+            format!(
+                "日時 \"{}\" を解析できません (YYYY-MM-DD か YYYY-MM-DDTHH:MM:SS で指定してください)",
+                s
+            )
+        })?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("日時 \"{}\" は存在しません", s))
+}
+
+impl Cli {
+    pub fn verbosity(&self) -> Verbosity {
+        if self.verbose {
+            Verbosity::Verbose
+        } else if self.quiet {
+            Verbosity::Quiet
+        } else {
+            Verbosity::Normal
+        }
+    }
+}
+
+// サブコマンドが共通で使う設定
+struct Context {
+    config: Config,
+    site: Site,
+    source: EsSource,
+    // 品質管理で除くと判定した値を欠損として扱うか
+    exclude_flagged: bool,
+}
+
+impl Context {
+    fn new(cli: &Cli) -> Result<Context> {
+        let config = Config::load(cli.config.as_deref())?;
+        let mut site = config.site(cli.site.as_deref())?.clone();
+        if let Some(lat) = cli.lat {
+            site.latitude = lat;
+        }
+        if let Some(lng) = cli.lng {
+            site.longitude = lng;
+        }
+        if let Some(clock_offset) = cli.clock_offset {
+            site.clock_offset = clock_offset;
+        }
+        let source = config.source(&site);
+
+        Ok(Context {
+            config,
+            site,
+            source,
+            exclude_flagged: !cli.no_qc,
+        })
+    }
+
+    // 期間の項目を読み込んで品質管理の判定結果を付ける
+    // --no-qcを指定しなかったときは、除くと判定した値を欠損として扱う。値は書き換えない
+    fn load_series(&self, period: &PeriodArgs, fields: &[Field]) -> Result<TimeSeries> {
+        let mut series =
+            es::load_series_for_period(&self.source, &period.from, period.span(), fields)?;
+        qc::apply_qc(&self.site, &mut series)?;
+        if self.exclude_flagged {
+            let excluded = series.exclude_flagged();
+            if excluded > 0 {
+                info!(excluded, "品質管理で除くと判定した値を欠損として扱います");
+            }
+        }
+        Ok(series)
+    }
+
+    fn load_field(
+        &self,
+        period: &PeriodArgs,
+        field: Field,
+    ) -> Result<(Vec<DateTime<Local>>, Vec<f64>)> {
+        // 欠損と除いた値の時刻は返さない
+        let series = self.load_series(period, &[field])?;
+        Ok(series
+            .dts()
+            .iter()
+            .zip(series.valid(field)?)
+            .filter_map(|(dt, value)| Some((*dt, value?)))
+            .unzip())
+    }
+}
+
+pub fn run(cli: &Cli) -> Result<()> {
+    let ctx = Context::new(cli)?;
+    match &cli.command {
+        Command::Fetch(period) => {
+            es::fetch_docs_for_period(&ctx.source, &period.from, period.span())
+        }
+        Command::Plot {
+            period,
+            field,
+            out,
+            theory,
+            sun,
+            clear_sky,
+            poa,
+        } => {
+            let field = match field {
+                Some(field) => *field,
+                None => ctx.config.plot_field()?,
+            };
+            let out = out.as_deref().unwrap_or(&ctx.config.plot.out);
+            let clear_sky = clear_sky.map(|model| model.unwrap_or(ctx.site.clear_sky_model));
+            let overlays = Overlays {
+                theory: *theory,
+                sun: *sun,
+                clear_sky,
+                poa: *poa,
+            };
+            run_plot(&ctx, period, field, out, &overlays)
+        }
+        Command::Analyze { period, field } => run_analyze(&ctx, period, *field),
+        Command::Export {
+            period,
+            fields,
+            out,
+            decompose,
+        } => run_export(&ctx, period, fields, out.as_deref(), *decompose),
+        Command::Compare { period, out, plot } => {
+            run_compare(&ctx, period, out.as_deref(), plot.as_deref())
+        }
+        Command::Classify {
+            period,
+            intervals,
+            clear_only,
+        } => run_classify(&ctx, period, *intervals, *clear_only),
+        Command::Clock {
+            period,
+            max_offset_hours,
+        } => run_clock(&ctx, period, *max_offset_hours),
+        Command::Qc { period, fields } => run_qc(&ctx, period, fields),
+        Command::Energy { period, by, out } => run_energy(&ctx, period, *by, out.as_deref()),
+        Command::Kpi { period, by, out } => run_kpi(&ctx, period, *by, out.as_deref()),
+        Command::Inverter { period, days, out } => {
+            run_inverter(&ctx, period, *days, out.as_deref())
+        }
+        Command::Clipping { period, periods } => run_clipping(&ctx, period, *periods),
+        Command::Anomaly {
+            period,
+            min_severity,
+            out,
+        } => run_anomaly(&ctx, period, *min_severity, out.as_deref()),
+        Command::Grid {
+            period,
+            events,
+            out,
+            histogram,
+        } => run_grid(&ctx, period, *events, out.as_deref(), histogram.as_deref()),
+        Command::Battery {
+            period,
+            cycles,
+            out,
+            plot,
+            depth_plot,
+        } => run_battery(
+            &ctx,
+            period,
+            *cycles,
+            out.as_deref(),
+            plot.as_deref(),
+            depth_plot.as_deref(),
+        ),
+        Command::Drift {
+            period,
+            min_shift,
+            min_segment_days,
+            out,
+        } => {
+            let mut params = DriftParams::default();
+            params.min_shift = *min_shift;
+            params.min_segment_days = *min_segment_days;
+            run_drift(&ctx, period, &params, out.as_deref())
+        }
+        Command::Predict { period, out } => run_predict(&ctx, period, out.as_deref()),
+        Command::Sun { at, model } => {
+            let mut site = ctx.site.clone();
+            if let Some(model) = model {
+                site.solar_position_model = *model;
+            }
+            run_sun(&site, at)
+        }
+        Command::Cache(command) => run_cache(&ctx, command),
+    }
+}
+
+// plotで実測値に重ねて描画するもの
+struct Overlays {
+    theory: bool,
+    sun: bool,
+    clear_sky: Option<ClearSkyModel>,
+    poa: bool,
+}
+
+fn run_plot(
+    ctx: &Context,
+    period: &PeriodArgs,
+    field: Field,
+    out: &str,
+    overlays: &Overlays,
+) -> Result<()> {
+    // 品質管理で判定された値は除かずに灰色で示す
+    let mut series =
+        es::load_series_for_period(&ctx.source, &period.from, period.span(), &[field])?;
+    qc::apply_qc(&ctx.site, &mut series)?;
+    let flagged = series
+        .flags(field)
+        .map(|flags| {
+            flags
+                .iter()
+                .map(|flags| !flags.is_empty())
+                .collect::<Vec<bool>>()
+        })
+        .unwrap_or_default();
+    let values = values_or_missing(&period.from, series.require(field)?.to_vec())?;
+    let dt_all = series.dts().to_vec();
+
+    let theory_values = if overlays.theory {
+        model::extraterrestrial_kw_series(&ctx.site, &dt_all)
+    } else {
+        Vec::new()
+    };
+
+    let geometry = if overlays.clear_sky.is_some() || overlays.poa {
+        model::solar_geometry(&ctx.site, &dt_all)
+    } else {
+        SolarGeometry::default()
+    };
+    let clear_sky_values = match overlays.clear_sky {
+        Some(model) => model::clear_sky_series(&ctx.site, model, &dt_all, &geometry)
+            .iter()
+            .map(|clear_sky| clear_sky.ghi / 1000.0)
+            .collect::<Vec<f64>>(),
+        None => Vec::new(),
+    };
+    let poa_values = if overlays.poa {
+        model::clear_sky_poa_series(&ctx.site, &dt_all, &geometry)
+            .iter()
+            .map(|poa| poa.global / 1000.0)
+            .collect::<Vec<f64>>()
+    } else {
+        Vec::new()
+    };
+
+    let mut lines = vec![Line {
+        label: field.label(),
+        values: &values,
+        color: RED,
+    }];
+    if overlays.theory {
+        lines.push(Line {
+            label: "extraterrestrial(kw/m^2)", // 日本語のフォントが無い環境でも描画できるよう英語にする
+            values: &theory_values,
+            color: BLUE,
+        });
+    }
+    if overlays.clear_sky.is_some() {
+        lines.push(Line {
+            label: "clear-sky GHI(kw/m^2)",
+            values: &clear_sky_values,
+            color: GREEN,
+        });
+    }
+    if overlays.poa {
+        lines.push(Line {
+            label: "clear-sky POA(kw/m^2)",
+            values: &poa_values,
+            color: MAGENTA,
+        });
+    }
+
+    let caption = format!(
+        "{} {} {}",
+        ctx.site.name,
+        field.label(),
+        period.from.date_naive()
+    );
+    let size = (ctx.config.plot.width, ctx.config.plot.height);
+
+    let mut markers = Vec::new();
+    if overlays.sun {
+        let mut dates = dt_all.iter().map(|dt| dt.date_naive()).collect::<Vec<_>>();
+        dates.dedup();
+        for date in dates {
+            let sun_times = model::sun_times(&ctx.site, date);
+            if let Some(sunrise) = sun_times.sunrise {
+                markers.push(Marker {
+                    label: "sunrise",
+                    dt: sunrise,
+                });
+            }
+            markers.push(Marker {
+                label: "noon",
+                dt: sun_times.solar_noon,
+            });
+            if let Some(sunset) = sun_times.sunset {
+                markers.push(Marker {
+                    label: "sunset",
+                    dt: sunset,
+                });
+            }
+        }
+    }
+
+    plot::plot_lines_with_flags(out, size, &caption, &dt_all, &lines, &markers, &flagged)?;
+    info!(out, "グラフを出力しました");
+
+    Ok(())
+}
+
+fn run_analyze(ctx: &Context, period: &PeriodArgs, field: Field) -> Result<()> {
+    let (dt_all, values) = ctx.load_field(period, field)?;
+    let summary = Summary::of(&dt_all, &values)
+        .ok_or_else(|| Error::MissingData(period.from.date_naive().to_string()))?;
+
+    print_site(&ctx.site);
+    println!("項目: {}", field.label());
+    println!(
+        "期間: {} ~ {}",
+        dt_all.first().unwrap(),
+        dt_all.last().unwrap()
+    );
+    println!("件数: {}", summary.count);
+    println!("最小: {:.4}", summary.min);
+    println!("最大: {:.4}", summary.max);
+    println!("平均: {:.4}", summary.mean);
+    println!("積算値(×h): {:.4}", summary.integrated);
+
+    if field == Field::SolarIrradiance {
+        let comparison = IrradianceComparison::new(&ctx.site, dt_all, values);
+        if let Some(summary) = comparison.summary(0..comparison.len()) {
+            print_comparison(&ctx.site, &summary);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_export(
+    ctx: &Context,
+    period: &PeriodArgs,
+    fields: &[Field],
+    out: Option<&str>,
+    decompose: bool,
+) -> Result<()> {
+    let mut load_fields = fields.to_vec();
+    if decompose && !load_fields.contains(&Field::SolarIrradiance) {
+        load_fields.push(Field::SolarIrradiance);
+    }
+    let series = ctx.load_series(period, &load_fields)?;
+    let mut header = fields
+        .iter()
+        .map(|field| field.name().to_string())
+        .collect::<Vec<_>>();
+    // 値の無い時刻は空欄にする
+    let mut values_all = fields
+        .iter()
+        .map(|field| series.valid(*field))
+        .collect::<Result<Vec<Vec<Option<f64>>>>>()?;
+
+    // 品質管理の判定結果(ビットの組み合わせ)の列
+    for field in fields {
+        if let Some(flags) = series.flags(*field) {
+            header.push(format!("{}_qc", field.name()));
+            values_all.push(
+                flags
+                    .iter()
+                    .map(|flags| Some(flags.bits() as f64))
+                    .collect(),
+            );
+        }
+    }
+
+    if decompose {
+        // 計測値の単位はkw/m^2。値の無い時刻は0として分離し、結果を空欄にする
+        let valid = series.valid(Field::SolarIrradiance)?;
+        let ghi = valid
+            .iter()
+            .map(|value| value.map_or(0.0, |value| value * 1000.0))
+            .collect::<Vec<f64>>();
+        let geometry = model::solar_geometry(&ctx.site, series.dts());
+        let decomposition =
+            model::decompose_with_geometry(&ctx.site, series.dts(), &geometry, &ghi)?;
+        let poa = model::poa_series(
+            &ctx.site,
+            &geometry,
+            &ghi,
+            &decomposition.dni,
+            &decomposition.dhi,
+        )
+        .iter()
+        .map(|poa| poa.global)
+        .collect::<Vec<f64>>();
+        let mask = |values: Vec<f64>| {
+            values
+                .into_iter()
+                .zip(valid.iter())
+                .map(|(value, valid)| valid.map(|_| value))
+                .collect::<Vec<Option<f64>>>()
+        };
+        header.extend(["dni", "dhi", "poa_global"].map(String::from));
+        values_all.extend([mask(decomposition.dni), mask(decomposition.dhi), mask(poa)]);
+    }
+
+    let mut writer = create_output(out)?;
+    let out_name = out.unwrap_or(STDOUT_NAME);
+
+    writeln!(writer, "datetime,{}", header.join(",")).map_err(|e| Error::output(out_name, e))?;
+    for (i, dt) in series.dts().iter().enumerate() {
+        let row = values_all
+            .iter()
+            .map(|values| values[i].map_or(String::new(), |value| value.to_string()))
+            .collect::<Vec<_>>();
+        writeln!(
+            writer,
+            "{},{}",
+            dt.format("%Y-%m-%dT%H:%M:%S"),
+            row.join(",")
+        )
+        .map_err(|e| Error::output(out_name, e))?;
+    }
+    writer.flush().map_err(|e| Error::output(out_name, e))?;
+
+    Ok(())
+}
+
+fn run_compare(
+    ctx: &Context,
+    period: &PeriodArgs,
+    out: Option<&str>,
+    plot_out: Option<&str>,
+) -> Result<()> {
+    let (dt_all, values) = ctx.load_field(period, Field::SolarIrradiance)?;
+    let values = values_or_missing(&period.from, values)?;
+    let comparison = IrradianceComparison::new(&ctx.site, dt_all, values);
+
+    print_site(&ctx.site);
+    println!("日付\t実測(kWh/m^2)\t大気外(kWh/m^2)\t晴天(kWh/m^2)\t実測/大気外\t実測/晴天\t晴天指数の平均");
+    for (date, summary) in comparison.day_summaries() {
+        println!(
+            "{}\t{:.4}\t{:.4}\t{:.4}\t{:.4}\t{:.4}\t{:.4}",
+            date,
+            summary.measured.integrated,
+            summary.extraterrestrial_kwh,
+            summary.clear_sky_kwh,
+            summary.clearness_index,
+            summary.clear_sky_ratio,
+            summary.mean_clear_sky_index
+        );
+    }
+    if let Some(summary) = comparison.summary(0..comparison.len()) {
+        println!("期間全体:");
+        print_comparison(&ctx.site, &summary);
+        println!(
+            "晴天指数の平均: {:.4} ({}件)",
+            summary.mean_clear_sky_index, summary.daytime_count
+        );
+        println!(
+            "晴天日射量に対する二乗平均平方根誤差: {:.4}kw/m^2",
+            summary.rmse_vs_clear_sky
+        );
+    }
+
+    if let Some(out) = out {
+        let mut writer = create_output(Some(out))?;
+        writeln!(
+            writer,
+            "datetime,solar_irradiance,extraterrestrial,clear_sky,clear_sky_index"
+        )
+        .map_err(|e| Error::output(out, e))?;
+        for i in 0..comparison.len() {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                comparison.dts[i].format("%Y-%m-%dT%H:%M:%S"),
+                comparison.measured[i],
+                comparison.extraterrestrial[i],
+                comparison.clear_sky[i],
+                comparison.clear_sky_index[i]
+            )
+            .map_err(|e| Error::output(out, e))?;
+        }
+        writer.flush().map_err(|e| Error::output(out, e))?;
+        info!(out, "CSVを出力しました");
+    }
+
+    if let Some(plot_out) = plot_out {
+        let lines = [
+            Line {
+                label: Field::SolarIrradiance.label(),
+                values: &comparison.measured,
+                color: RED,
+            },
+            Line {
+                label: "extraterrestrial(kw/m^2)",
+                values: &comparison.extraterrestrial,
+                color: BLUE,
+            },
+            Line {
+                label: "clear-sky GHI(kw/m^2)",
+                values: &comparison.clear_sky,
+                color: GREEN,
+            },
+        ];
+        let caption = format!(
+            "{} measured vs theoretical {}",
+            ctx.site.name,
+            period.from.date_naive()
+        );
+        let size = (ctx.config.plot.width, ctx.config.plot.height);
+        plot::plot_lines(plot_out, size, &caption, &comparison.dts, &lines)?;
+        info!(out = plot_out, "グラフを出力しました");
+    }
+
+    Ok(())
+}
+
+fn run_classify(
+    ctx: &Context,
+    period: &PeriodArgs,
+    intervals: bool,
+    clear_only: bool,
+) -> Result<()> {
+    let (dt_all, values) = ctx.load_field(period, Field::SolarIrradiance)?;
+    let values = values_or_missing(&period.from, values)?;
+    let comparison = IrradianceComparison::new(&ctx.site, dt_all, values);
+    let days = sky::classify_days(&comparison, &ClearSkyCriteria::default());
+
+    if clear_only {
+        for day in days
+            .iter()
+            .filter(|day| day.condition == SkyCondition::Clear)
+        {
+            println!("{}", day.date);
+        }
+        return Ok(());
+    }
+
+    print_site(&ctx.site);
+    println!("日付\t判定\t晴天の区間の割合\t実測/晴天");
+    for day in days.iter() {
+        println!(
+            "{}\t{}\t{:.3}\t{:.4}",
+            day.date, day.condition, day.clear_fraction, day.clear_sky_ratio
+        );
+        if intervals {
+            for interval in day.intervals.iter() {
+                println!(
+                    "  {}\t{}\t{:.4}",
+                    interval.start.format("%H:%M"),
+                    interval.condition,
+                    interval.clear_sky_index
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_clock(ctx: &Context, period: &PeriodArgs, max_offset_hours: i64) -> Result<()> {
+    let (dt_all, values) = ctx.load_field(period, Field::SolarIrradiance)?;
+    let values = values_or_missing(&period.from, values)?;
+    let comparison = IrradianceComparison::new(&ctx.site, dt_all, values);
+    let offsets = clock::estimate_offsets(
+        &ctx.site,
+        &comparison,
+        &ClearSkyCriteria::default(),
+        max_offset_hours * 3600,
+    )?;
+
+    print_site(&ctx.site);
+    println!("設定済みの補正: {}秒", ctx.site.clock_offset);
+    println!("日付\t晴天\tずれ(秒)\t相関係数");
+    // 晴天ではない日はずれを推定しない
+    for (date, _) in timeseries::day_ranges(&comparison.dts) {
+        match offsets.iter().find(|offset| offset.date == date) {
+            Some(offset) => println!(
+                "{}\t○\t{}\t{:.4}",
+                offset.date, offset.offset_seconds, offset.correlation
+            ),
+            None => println!("{}\t-\t-\t-", date),
+        }
+    }
+
+    match clock::median_offset(&offsets) {
+        Some(median) => {
+            let total = ctx.site.clock_offset + median;
+            println!("晴天の日のずれの中央値: {}秒", median);
+            println!(
+                "補正するには設定ファイルの clock_offset を {} にするか、--clock-offset {} を指定してください",
+                total, total
+            );
+        }
+        None => println!("晴天の日が無いのでずれを推定できません"),
+    }
+
+    Ok(())
+}
+
+fn run_qc(ctx: &Context, period: &PeriodArgs, fields: &[Field]) -> Result<()> {
+    let mut series = es::load_series_for_period(&ctx.source, &period.from, period.span(), fields)?;
+    qc::apply_qc(&ctx.site, &mut series)?;
+
+    print_site(&ctx.site);
+    let names = QcFlags::ALL.map(|flag| flag.name()).join("\t");
+    println!("項目\t件数\t{}\t除外", names);
+    for field in fields {
+        let Some(flags) = series.flags(*field) else {
+            println!("{}\t(判定する基準がありません)", field);
+            continue;
+        };
+        let counts = QcFlags::ALL
+            .iter()
+            .map(|flag| {
+                flags
+                    .iter()
+                    .filter(|flags| flags.contains(*flag))
+                    .count()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\t");
+        let excluded = flags.iter().filter(|flags| flags.is_excluded()).count();
+        println!("{}\t{}\t{}\t{}", field, flags.len(), counts, excluded);
+    }
+
+    Ok(())
+}
+
+fn run_energy(
+    ctx: &Context,
+    period: &PeriodArgs,
+    by: YieldPeriod,
+    out: Option<&str>,
+) -> Result<()> {
+    let fields = [
+        Field::AcPw,
+        Field::DcPw,
+        Field::SingleUnitIntegratedPowerGeneration,
+        Field::TotalUnitIntegratedPowerGeneration,
+    ];
+    let series = ctx.load_series(period, &fields)?;
+    if series.is_empty() {
+        return Err(Error::MissingData(period.from.date_naive().to_string()));
+    }
+    let params = EnergyParams::default();
+    let daily = energy::daily_yields(&series, &params)?;
+    let yields = energy::aggregate(&daily, by, &params);
+
+    // カウンタの値が無いときは空欄にする
+    let counter = |value: Option<f64>| value.map(|v| format!("{:.3}", v)).unwrap_or_default();
+
+    print_site(&ctx.site);
+    println!("期間\t交流(kWh)\t直流(kWh)\t自機カウンタ(kWh)\t全体カウンタ(kWh)\t欠損(秒)\t不一致");
+    for y in yields.iter() {
+        println!(
+            "{}\t{:.3}\t{:.3}\t{}\t{}\t{}\t{}",
+            by.label(y.start),
+            y.ac_kwh,
+            y.dc_kwh,
+            counter(y.single_unit_counter_kwh),
+            counter(y.total_unit_counter_kwh),
+            y.missing_seconds,
+            if y.discrepancy { "※" } else { "" }
+        );
+    }
+    if yields.iter().any(|y| y.discrepancy) {
+        println!("※: 交流出力の積算値と自機のカウンタの増加量が食い違っています");
+    }
+
+    if let Some(out) = out {
+        let mut writer = create_output(Some(out))?;
+        writeln!(
+            writer,
+            "period,ac_kwh,dc_kwh,single_unit_counter_kwh,total_unit_counter_kwh,missing_seconds,discrepancy"
+        )
+        .map_err(|e| Error::output(out, e))?;
+        for y in yields.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{}",
+                by.label(y.start),
+                y.ac_kwh,
+                y.dc_kwh,
+                counter(y.single_unit_counter_kwh),
+                counter(y.total_unit_counter_kwh),
+                y.missing_seconds,
+                y.discrepancy
+            )
+            .map_err(|e| Error::output(out, e))?;
+        }
+        writer.flush().map_err(|e| Error::output(out, e))?;
+        info!(out, "CSVを出力しました");
+    }
+
+    Ok(())
+}
+
+fn run_kpi(ctx: &Context, period: &PeriodArgs, by: YieldPeriod, out: Option<&str>) -> Result<()> {
+    let fields = [Field::AcPw, Field::SolarIrradiance, Field::AirTemperature];
+    let series = ctx.load_series(period, &fields)?;
+    if series.is_empty() {
+        return Err(Error::MissingData(period.from.date_naive().to_string()));
+    }
+    let daily = kpi::daily_kpis(&ctx.site, &series, EnergyParams::default().max_gap_seconds)?;
+    let kpis = kpi::aggregate(&daily, by);
+
+    print_site(&ctx.site);
+    println!("期間\t発電量(kWh)\t日射量(kWh/m^2)\t発電量(kWh/kWp)\t性能比\t気温補正した性能比\t設備利用率");
+    for k in kpis.iter() {
+        println!(
+            "{}\t{:.3}\t{:.3}\t{:.3}\t{:.4}\t{:.4}\t{:.4}",
+            by.label(k.start),
+            k.ac_kwh,
+            k.insolation,
+            k.specific_yield(),
+            k.performance_ratio(),
+            k.temperature_corrected_performance_ratio(),
+            k.capacity_factor()
+        );
+    }
+
+    if let Some(out) = out {
+        let mut writer = create_output(Some(out))?;
+        writeln!(
+            writer,
+            "period,ac_kwh,insolation_kwh_m2,specific_yield,performance_ratio,temperature_corrected_performance_ratio,capacity_factor"
+        )
+        .map_err(|e| Error::output(out, e))?;
+        for k in kpis.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{}",
+                by.label(k.start),
+                k.ac_kwh,
+                k.insolation,
+                k.specific_yield(),
+                k.performance_ratio(),
+                k.temperature_corrected_performance_ratio(),
+                k.capacity_factor()
+            )
+            .map_err(|e| Error::output(out, e))?;
+        }
+        writer.flush().map_err(|e| Error::output(out, e))?;
+        info!(out, "CSVを出力しました");
+    }
+
+    Ok(())
+}
+
+fn run_inverter(ctx: &Context, period: &PeriodArgs, days: bool, out: Option<&str>) -> Result<()> {
+    let inverter_kw = ctx.site.require_inverter_kw()?;
+    let series = ctx.load_series(period, &[Field::AcPw, Field::DcPw])?;
+    let result = inverter::analyze_efficiency(&series, inverter_kw, &InverterParams::default())?;
+    if result.curve.bins.is_empty() {
+        return Err(Error::MissingData(format!(
+            "{} (効率を求められる時刻がありません)",
+            period.from.date_naive()
+        )));
+    }
+
+    // 求められなかったときは「-」にする
+    let format = |value: Option<f64>| {
+        value
+            .map(|v| format!("{:.4}", v))
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    print_site(&ctx.site);
+    println!("パワーコンディショナの定格出力: {}kW", inverter_kw);
+    println!("負荷率\t効率\t件数");
+    for bin in result.curve.bins.iter() {
+        println!(
+            "{:.3}\t{:.4}\t{}",
+            bin.load_fraction, bin.efficiency, bin.count
+        );
+    }
+    println!("ヨーロッパ効率: {}", format(result.curve.euro_efficiency()));
+    println!("CEC効率: {}", format(result.curve.cec_efficiency()));
+    if !result.curve.covers_weights() {
+        println!("(計測された負荷率の範囲外の効率は端の区分の値で代用しています)");
+    }
+
+    if days {
+        println!("日付\t効率\t予想との比\t件数");
+    }
+    for day in result.days.iter().filter(|day| days || day.degraded) {
+        println!(
+            "{}\t{:.4}\t{:.4}\t{}{}",
+            day.date,
+            day.efficiency,
+            day.relative,
+            day.count,
+            if day.degraded { "\t効率低下" } else { "" }
+        );
+    }
+    let degraded = result.days.iter().filter(|day| day.degraded).count();
+    println!("効率が落ちた日: {}日 / {}日", degraded, result.days.len());
+
+    if let Some(out) = out {
+        let mut writer = create_output(Some(out))?;
+        writeln!(writer, "load_fraction,efficiency,count").map_err(|e| Error::output(out, e))?;
+        for bin in result.curve.bins.iter() {
+            writeln!(
+                writer,
+                "{},{},{}",
+                bin.load_fraction, bin.efficiency, bin.count
+            )
+            .map_err(|e| Error::output(out, e))?;
+        }
+        writer.flush().map_err(|e| Error::output(out, e))?;
+        info!(out, "CSVを出力しました");
+    }
+
+    Ok(())
+}
+
+fn run_clipping(ctx: &Context, period: &PeriodArgs, periods: bool) -> Result<()> {
+    let fields = [Field::AcPw, Field::SolarIrradiance, Field::AirTemperature];
+    let series = ctx.load_series(period, &fields)?;
+    if series.is_empty() {
+        return Err(Error::MissingData(period.from.date_naive().to_string()));
+    }
+    let days = clipping::detect_clipping(&ctx.site, &series, &ClippingParams::default())?;
+
+    print_site(&ctx.site);
+    println!(
+        "パワーコンディショナの定格出力: {}kW",
+        ctx.site.require_inverter_kw()?
+    );
+    println!("日付\t発電量(kWh)\t損失(kWh)\t損失の割合\t頭打ち(秒)\t補正の倍率");
+    for day in days.iter() {
+        println!(
+            "{}\t{:.3}\t{:.3}\t{:.4}\t{}\t{:.3}",
+            day.date,
+            day.ac_kwh,
+            day.lost_kwh,
+            day.lost_fraction(),
+            day.clipped_seconds(),
+            day.scale
+        );
+        if periods {
+            for period in day.periods.iter() {
+                println!(
+                    "  {} ~ {}\t{:.3}kWh\t最大 {:.3}kW",
+                    period.start.format("%H:%M:%S"),
+                    period.end.format("%H:%M:%S"),
+                    period.lost_kwh,
+                    period.peak_potential_kw
+                );
+            }
+        }
+    }
+
+    let ac_kwh = days.iter().map(|day| day.ac_kwh).sum::<f64>();
+    let lost_kwh = days.iter().map(|day| day.lost_kwh).sum::<f64>();
+    println!(
+        "期間全体: 発電量 {:.3}kWh、損失 {:.3}kWh ({:.2}%)",
+        ac_kwh,
+        lost_kwh,
+        lost_kwh / (ac_kwh + lost_kwh) * 100.0
+    );
+
+    Ok(())
+}
+
+fn run_anomaly(
+    ctx: &Context,
+    period: &PeriodArgs,
+    min_severity: Severity,
+    out: Option<&str>,
+) -> Result<()> {
+    let fields = [
+        Field::AcPw,
+        Field::DcPw,
+        Field::SolarIrradiance,
+        Field::AirTemperature,
+    ];
+    // 品質管理で除く値こそ検出したい異常なので、品質管理を適用せずに読み込む
+    let series = es::load_series_for_period(&ctx.source, &period.from, period.span(), &fields)?;
+    if series.is_empty() {
+        return Err(Error::MissingData(period.from.date_naive().to_string()));
+    }
+    if ctx.site.rated_kw.is_none() {
+        info!("定格出力(rated_kw)が設定されていないので、予測との差と出力の低下は調べません");
+    }
+    let anomalies = anomaly::detect_anomalies(&ctx.site, &series, &AnomalyParams::default())?
+        .into_iter()
+        .filter(|anomaly| anomaly.severity >= min_severity)
+        .collect::<Vec<_>>();
+
+    print_site(&ctx.site);
+    println!("開始\t終了\t重大度\t種類\t項目\t大きさ");
+    for anomaly in anomalies.iter() {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{:.3}",
+            anomaly.start.format("%Y-%m-%d %H:%M:%S"),
+            anomaly.end.format("%H:%M:%S"),
+            anomaly.severity,
+            anomaly.kind,
+            anomaly.field,
+            anomaly.magnitude
+        );
+    }
+    let critical = anomalies
+        .iter()
+        .filter(|anomaly| anomaly.severity == Severity::Critical)
+        .count();
+    println!("検出: {}件 (うち重大 {}件)", anomalies.len(), critical);
+
+    if let Some(out) = out {
+        let mut writer = create_output(Some(out))?;
+        writeln!(writer, "start,end,severity,kind,field,magnitude")
+            .map_err(|e| Error::output(out, e))?;
+        for anomaly in anomalies.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                anomaly.start.format("%Y-%m-%dT%H:%M:%S"),
+                anomaly.end.format("%Y-%m-%dT%H:%M:%S"),
+                anomaly.severity.name(),
+                anomaly.kind.name(),
+                anomaly.field,
+                anomaly.magnitude
+            )
+            .map_err(|e| Error::output(out, e))?;
+        }
+        writer.flush().map_err(|e| Error::output(out, e))?;
+        info!(out, "CSVを出力しました");
+    }
+
+    Ok(())
+}
+
+fn run_grid(
+    ctx: &Context,
+    period: &PeriodArgs,
+    events: bool,
+    out: Option<&str>,
+    histogram: Option<&str>,
+) -> Result<()> {
+    let series = ctx.load_series(period, &[Field::Frequency, Field::AcV])?;
+    if series.is_empty() {
+        return Err(Error::MissingData(period.from.date_naive().to_string()));
+    }
+    let params = ctx.site.grid;
+    let result = grid::analyze_grid(&series, &params)?;
+
+    // 値が無いときは空欄にする
+    let columns = |distribution: Option<Distribution>, precision: usize| {
+        distribution
+            .map(|d| {
+                format!(
+                    "{:.p$}\t{:.p$}\t{:.p$}\t{:.p$}\t{:.p$}\t{:.3}",
+                    d.min,
+                    d.p5,
+                    d.median,
+                    d.p95,
+                    d.max,
+                    d.within_tolerance * 100.0,
+                    p = precision
+                )
+            })
+            .unwrap_or_else(|| "\t\t\t\t\t".to_string())
+    };
+
+    print_site(&ctx.site);
+    println!(
+        "基準: 周波数 {}±{}Hz、電圧 {}±{}V",
+        params.nominal_frequency,
+        params.frequency_tolerance,
+        params.nominal_voltage,
+        params.voltage_tolerance
+    );
+    println!(
+        "日付\t周波数 最小\t5%\t中央値\t95%\t最大\t範囲内(%)\t電圧 最小\t5%\t中央値\t95%\t最大\t範囲内(%)"
+    );
+    for day in result.days.iter() {
+        println!(
+            "{}\t{}\t{}",
+            day.date,
+            columns(day.frequency, 3),
+            columns(day.voltage, 1)
+        );
+    }
+    if let Some(d) = result.frequency {
+        println!(
+            "周波数: 平均 {:.4}Hz、標準偏差 {:.4}Hz、基準からのずれの平均 {:.4}Hz、範囲内 {:.3}%",
+            d.mean,
+            d.std_dev,
+            d.mean_abs_deviation,
+            d.within_tolerance * 100.0
+        );
+    }
+    if let Some(d) = result.voltage {
+        println!(
+            "電圧: 平均 {:.2}V、標準偏差 {:.2}V、基準からのずれの平均 {:.2}V、範囲内 {:.3}%",
+            d.mean,
+            d.std_dev,
+            d.mean_abs_deviation,
+            d.within_tolerance * 100.0
+        );
+    }
+
+    for quantity in [GridQuantity::Frequency, GridQuantity::Voltage] {
+        for above in [true, false] {
+            let matched = result
+                .events
+                .iter()
+                .filter(|event| event.quantity == quantity && event.above == above)
+                .collect::<Vec<_>>();
+            if let Some(event) = matched.first() {
+                println!(
+                    "{}: {}回、合計 {}秒",
+                    event,
+                    matched.len(),
+                    matched.iter().map(|e| e.duration_seconds()).sum::<i64>()
+                );
+            }
+        }
+    }
+    if result.events.is_empty() {
+        println!("許容範囲を外れた時間帯はありません");
+    }
+    if events {
+        for event in result.events.iter() {
+            println!(
+                "  {} ~ {}\t{}\t{}秒\t{}",
+                event.start.format("%Y-%m-%d %H:%M:%S"),
+                event.end.format("%H:%M:%S"),
+                event,
+                event.duration_seconds(),
+                event.extreme
+            );
+        }
+    }
+
+    if let Some(out) = out {
+        let mut writer = create_output(Some(out))?;
+        writeln!(writer, "start,end,kind,field,duration_seconds,extreme")
+            .map_err(|e| Error::output(out, e))?;
+        for event in result.events.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                event.start.format("%Y-%m-%dT%H:%M:%S"),
+                event.end.format("%Y-%m-%dT%H:%M:%S"),
+                event.name(),
+                event.quantity.field(),
+                event.duration_seconds(),
+                event.extreme
+            )
+            .map_err(|e| Error::output(out, e))?;
+        }
+        writer.flush().map_err(|e| Error::output(out, e))?;
+        info!(out, "CSVを出力しました");
+    }
+
+    if let Some(out) = histogram {
+        let mut writer = create_output(Some(out))?;
+        writeln!(writer, "field,lower,upper,count").map_err(|e| Error::output(out, e))?;
+        // 周波数は0.01Hz、電圧は1Vごと
+        for (quantity, width) in [
+            (GridQuantity::Frequency, 0.01),
+            (GridQuantity::Voltage, 1.0),
+        ] {
+            let field = quantity.field();
+            for (lower, count) in grid::histogram(&series.recorded(field)?, width) {
+                writeln!(
+                    writer,
+                    "{},{:.2},{:.2},{}",
+                    field,
+                    lower,
+                    lower + width,
+                    count
+                )
+                .map_err(|e| Error::output(out, e))?;
+            }
+        }
+        writer.flush().map_err(|e| Error::output(out, e))?;
+        info!(out, "CSVを出力しました");
+    }
+
+    Ok(())
+}
+
+fn run_battery(
+    ctx: &Context,
+    period: &PeriodArgs,
+    cycles: bool,
+    out: Option<&str>,
+    plot_out: Option<&str>,
+    depth_plot: Option<&str>,
+) -> Result<()> {
+    let fields = [Field::RemainingStorageBatteryCapacity, Field::AcPw];
+    let series = ctx.load_series(period, &fields)?;
+    if series.is_empty() {
+        return Err(Error::MissingData(period.from.date_naive().to_string()));
+    }
+    let params = BatteryParams::default();
+    let result = battery::analyze_battery(&series, &params)?;
+
+    // 残量の値が無いときは空欄にする
+    let soc = |value: Option<f64>| value.map(|v| format!("{:.1}", v)).unwrap_or_default();
+
+    print_site(&ctx.site);
+    println!(
+        "日付\t最小(%)\t最大(%)\t充電(%)\t放電(%)\t等価サイクル\t満充電(秒)\t空(秒)\t発電量(kWh)"
+    );
+    for day in result.days.iter() {
+        println!(
+            "{}\t{}\t{}\t{:.1}\t{:.1}\t{:.3}\t{}\t{}\t{:.3}",
+            day.date,
+            soc(day.min_soc),
+            soc(day.max_soc),
+            day.charged,
+            day.discharged,
+            day.equivalent_full_cycles(),
+            day.full_seconds,
+            day.empty_seconds,
+            day.pv_kwh
+        );
+    }
+    println!(
+        "等価サイクル数: {:.3} (満充電は{}%以上、空は{}%以下)",
+        result.equivalent_full_cycles(),
+        params.full_soc,
+        params.empty_soc
+    );
+
+    println!("放電の深さ(%)\t回数");
+    let histogram = result.depth_histogram(10.0);
+    for (lower, count) in histogram.iter() {
+        println!("{:.0}~{:.0}\t{}", lower, lower + 10.0, count);
+    }
+    if cycles {
+        for cycle in result.cycles.iter() {
+            println!(
+                "  {} ~ {}\t{:.1}% → {:.1}%\t深さ {:.1}%",
+                cycle.start.format("%Y-%m-%d %H:%M:%S"),
+                cycle.end.format("%Y-%m-%d %H:%M:%S"),
+                cycle.from_soc,
+                cycle.to_soc,
+                cycle.depth()
+            );
+        }
+    }
+
+    // 相関を求められないときは表示しない
+    let r = |value: Option<f64>| value.map_or("-".to_string(), |r| format!("{:.3}", r));
+    println!(
+        "発電との相関: 日ごとの発電量と充電量 {}、1時間ごとの出力と残量の変化 {}",
+        r(result.daily_correlation),
+        r(result.hourly_correlation)
+    );
+
+    if let Some(out) = out {
+        let mut writer = create_output(Some(out))?;
+        writeln!(
+            writer,
+            "date,min_soc,max_soc,charged,discharged,equivalent_full_cycles,full_seconds,empty_seconds,pv_kwh"
+        )
+        .map_err(|e| Error::output(out, e))?;
+        for day in result.days.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{}",
+                day.date,
+                soc(day.min_soc),
+                soc(day.max_soc),
+                day.charged,
+                day.discharged,
+                day.equivalent_full_cycles(),
+                day.full_seconds,
+                day.empty_seconds,
+                day.pv_kwh
+            )
+            .map_err(|e| Error::output(out, e))?;
+        }
+        writer.flush().map_err(|e| Error::output(out, e))?;
+        info!(out, "CSVを出力しました");
+    }
+
+    let size = (ctx.config.plot.width, ctx.config.plot.height);
+    if let Some(out) = plot_out {
+        let soc_values = series.require(Field::RemainingStorageBatteryCapacity)?;
+        let mut lines = vec![Line {
+            label: "state of charge (%)",
+            values: soc_values,
+            color: BLUE,
+        }];
+        // 定格出力が分かれば発電出力を定格に対する割合(%)で重ねる
+        let pv_values = match ctx.site.rated_kw {
+            Some(rated_kw) => series
+                .require(Field::AcPw)?
+                .iter()
+                .map(|v| v / rated_kw * 100.0)
+                .collect::<Vec<f64>>(),
+            None => Vec::new(),
+        };
+        if ctx.site.rated_kw.is_some() {
+            lines.push(Line {
+                label: "PV output (% of rated)",
+                values: &pv_values,
+                color: RED,
+            });
+        }
+        let caption = format!("{} battery state of charge", ctx.site.name);
+        plot::plot_lines(out, size, &caption, series.dts(), &lines)?;
+        info!(out, "グラフを出力しました");
+    }
+    if let Some(out) = depth_plot {
+        let labels = histogram
+            .iter()
+            .map(|(lower, _)| format!("{:.0}-{:.0}%", lower, lower + 10.0))
+            .collect::<Vec<_>>();
+        let counts = histogram
+            .iter()
+            .map(|(_, count)| *count as f64)
+            .collect::<Vec<_>>();
+        let caption = format!("{} depth of discharge", ctx.site.name);
+        plot::plot_bars(out, size, &caption, &labels, &counts)?;
+        info!(out, "グラフを出力しました");
+    }
+
+    Ok(())
+}
+
+fn run_drift(
+    ctx: &Context,
+    period: &PeriodArgs,
+    params: &DriftParams,
+    out: Option<&str>,
+) -> Result<()> {
+    let (dt_all, values) = ctx.load_field(period, Field::SolarIrradiance)?;
+    let values = values_or_missing(&period.from, values)?;
+    let comparison = IrradianceComparison::new(&ctx.site, dt_all, values);
+    let days = sky::classify_days(&comparison, &ClearSkyCriteria::default());
+    let drift = calibration::analyze_drift(&ctx.site, &comparison, &days, params);
+
+    print_site(&ctx.site);
+    if drift.ratios.is_empty() {
+        println!("晴天の日が無いので比の推移を求められません");
+        return Ok(());
+    }
+    println!("日付\t南中時刻\t実測/晴天");
+    for ratio in drift.ratios.iter() {
+        println!(
+            "{}\t{}\t{:.4}",
+            ratio.date,
+            ratio.solar_noon.format("%H:%M:%S"),
+            ratio.ratio
+        );
+    }
+    if let Some(trend) = drift.trend {
+        println!(
+            "傾向: 最初の晴天の日 {:.4}、1年あたり {:+.4}",
+            trend.intercept, trend.slope_per_year
+        );
+    }
+    if drift.change_points.is_empty() {
+        println!("変化点はありません");
+    }
+    for change_point in drift.change_points.iter() {
+        println!(
+            "変化点: {} ({:.4} → {:.4})",
+            change_point.date, change_point.before, change_point.after
+        );
+        if change_point.after < change_point.before {
+            println!("  感度が下がっています。日射計の清掃か校正を検討してください");
+        }
+    }
+
+    if let Some(out) = out {
+        let first = drift.ratios[0].date;
+        let dts = drift
+            .ratios
+            .iter()
+            .map(|ratio| ratio.solar_noon)
+            .collect::<Vec<_>>();
+        let ratios = drift
+            .ratios
+            .iter()
+            .map(|ratio| ratio.ratio)
+            .collect::<Vec<f64>>();
+        let mut lines = vec![Line {
+            label: "measured/clear-sky at noon",
+            values: &ratios,
+            color: RED,
+        }];
+        let trend_values = match drift.trend {
+            Some(trend) => drift
+                .ratios
+                .iter()
+                .map(|ratio| trend.at((ratio.date - first).num_days() as f64))
+                .collect::<Vec<f64>>(),
+            None => Vec::new(),
+        };
+        if drift.trend.is_some() {
+            lines.push(Line {
+                label: "trend",
+                values: &trend_values,
+                color: BLUE,
+            });
+        }
+        let markers = drift
+            .change_points
+            .iter()
+            .map(|change_point| Marker {
+                label: "change",
+                dt: model::sun_times(&ctx.site, change_point.date).solar_noon,
+            })
+            .collect::<Vec<_>>();
+        let caption = format!("{} pyranometer drift", ctx.site.name);
+        let size = (ctx.config.plot.width, ctx.config.plot.height);
+        plot::plot_lines_with_markers(out, size, &caption, &dts, &lines, &markers)?;
+        info!(out, "グラフを出力しました");
+    }
+
+    Ok(())
+}
+
+fn run_predict(ctx: &Context, period: &PeriodArgs, out: Option<&str>) -> Result<()> {
+    let fields = [Field::SolarIrradiance, Field::AirTemperature, Field::AcPw];
+    let series = ctx.load_series(period, &fields)?;
+    if series.is_empty() {
+        return Err(Error::MissingData(period.from.date_naive().to_string()));
+    }
+    let estimates = model::expected_power(&ctx.site, &series)?;
+    let actual = series.valid(Field::AcPw)?;
+    // 実測と予測の両方がある時刻だけを比べる
+    let pairs = actual
+        .iter()
+        .zip(estimates.iter())
+        .map(|(actual, estimate)| Some(((*actual)?, (*estimate)?.ac)))
+        .collect::<Vec<Option<(f64, f64)>>>();
+    // 両方の値がある時刻だけを時刻の間隔に合わせて積算する
+    let max_gap_seconds = EnergyParams::default().max_gap_seconds;
+    let dts = series.dts();
+    let actual_kw = pairs
+        .iter()
+        .map(|pair| pair.map(|(actual, _)| actual))
+        .collect::<Vec<Option<f64>>>();
+    let expected_kw = pairs
+        .iter()
+        .map(|pair| pair.map(|(_, expected)| expected))
+        .collect::<Vec<Option<f64>>>();
+    let kwh = |range: std::ops::Range<usize>| {
+        (
+            energy::integrate(dts, &actual_kw, range.clone(), max_gap_seconds).0,
+            energy::integrate(dts, &expected_kw, range, max_gap_seconds).0,
+        )
+    };
+
+    print_site(&ctx.site);
+    println!("日付\t実測(kWh)\t予測(kWh)\t実測/予測");
+    for (date, range) in series.day_ranges() {
+        let (actual_kwh, expected_kwh) = kwh(range);
+        println!(
+            "{}\t{:.3}\t{:.3}\t{:.4}",
+            date,
+            actual_kwh,
+            expected_kwh,
+            actual_kwh / expected_kwh
+        );
+    }
+
+    let (actual_kwh, expected_kwh) = kwh(0..series.len());
+    let compared = pairs.iter().flatten().count();
+    let rmse = (pairs
+        .iter()
+        .flatten()
+        .map(|(a, e)| (a - e).powi(2))
+        .sum::<f64>()
+        / compared as f64)
+        .sqrt();
+    // 頭打ちになった時刻と直前の時刻の間の秒数を足す
+    let clipped = (1..series.len())
+        .filter(|i| estimates[*i].is_some_and(|estimate| estimate.clipped))
+        .map(|i| (dts[i] - dts[i - 1]).num_seconds())
+        .filter(|gap| *gap <= max_gap_seconds)
+        .sum::<i64>();
+    println!(
+        "合計: 実測 {:.3}kWh, 予測 {:.3}kWh, 実測/予測 {:.4}",
+        actual_kwh,
+        expected_kwh,
+        actual_kwh / expected_kwh
+    );
+    println!("二乗平均平方根誤差: {:.4}kW", rmse);
+    println!("予測で定格出力に頭打ちになった時間: {}秒", clipped);
+
+    if let Some(out) = out {
+        // グラフでは値の無い時刻を0として描く
+        let actual = series.require(Field::AcPw)?;
+        let expected = estimates
+            .iter()
+            .map(|estimate| estimate.map_or(0.0, |estimate| estimate.ac))
+            .collect::<Vec<f64>>();
+        let lines = [
+            Line {
+                label: Field::AcPw.label(),
+                values: actual,
+                color: RED,
+            },
+            Line {
+                label: "expected ac-pw(kw)",
+                values: &expected,
+                color: BLUE,
+            },
+        ];
+        let caption = format!(
+            "{} expected vs actual {}",
+            ctx.site.name,
+            period.from.date_naive()
+        );
+        let size = (ctx.config.plot.width, ctx.config.plot.height);
+        plot::plot_lines(out, size, &caption, series.dts(), &lines)?;
+        info!(out, "グラフを出力しました");
+    }
+
+    Ok(())
+}
+
+fn run_sun(site: &Site, at: &DateTime<Local>) -> Result<()> {
+    let position = model::solar_position(site, at);
+    let sun_times = model::sun_times(site, at.date_naive());
+    let format_time = |dt: Option<DateTime<Local>>| {
+        dt.map_or("なし".to_string(), |dt| dt.format("%H:%M:%S").to_string())
+    };
+
+    print_site(site);
+    println!("日時: {}", at);
+    println!("計算方法: {:?}", site.solar_position_model);
+    println!("天頂角: {:.3}度", position.zenith);
+    println!("太陽高度: {:.3}度", position.elevation);
+    println!("方位角: {:.3}度", position.azimuth);
+    println!("時角: {:.3}度", position.hour_angle);
+    println!("赤緯: {:.3}度", position.declination);
+    println!("日の出: {}", format_time(sun_times.sunrise));
+    println!("南中: {}", format_time(Some(sun_times.solar_noon)));
+    println!("日の入り: {}", format_time(sun_times.sunset));
+    println!("大気外法線面日射量: {:.1}W/m^2", q::calc_dni_extra(at));
+    println!("エアマス: {:.3}", model::absolute_airmass(site, at));
+    let clear_sky = model::clear_sky(site, at);
+    println!(
+        "晴天日射量({:?}): 全天 {:.1}W/m^2, 直達 {:.1}W/m^2, 散乱 {:.1}W/m^2",
+        site.clear_sky_model, clear_sky.ghi, clear_sky.dni, clear_sky.dhi
+    );
+    let poa = model::clear_sky_poa(site, at);
+    println!(
+        "晴天時のパネル面の日射量({:?}): 合計 {:.1}W/m^2, 直達 {:.1}W/m^2, 天空散乱 {:.1}W/m^2, 地面反射 {:.1}W/m^2",
+        site.sky_diffuse_model, poa.global, poa.direct, poa.sky_diffuse, poa.ground_diffuse
+    );
+
+    Ok(())
+}
+
+fn run_cache(ctx: &Context, command: &CacheCommand) -> Result<()> {
+    let files = filepath::list_cached_files(&ctx.source.cache_dir)?;
+    match command {
+        CacheCommand::List => {
+            for file in files.iter() {
+                println!("{}\t{:>10} bytes\t{}", file.date, file.size, file.path);
+            }
+            let total = files.iter().map(|file| file.size).sum::<u64>();
+            println!("{}日分 合計 {} bytes", files.len(), total);
+        }
+        CacheCommand::Clear { from, to } => {
+            let from = from.map(|dt| dt.date_naive());
+            let to = to.map(|dt| dt.date_naive());
+            let mut removed = 0;
+            for file in files.iter() {
+                let after_from = from.is_none_or(|from| from <= file.date);
+                let before_to = to.is_none_or(|to| file.date < to);
+                if after_from && before_to {
+                    std::fs::remove_file(&file.path).map_err(|e| Error::cache_io(&file.path, e))?;
+                    removed += 1;
+                }
+            }
+            info!(removed, "キャッシュを削除しました");
+        }
+    }
+
+    Ok(())
+}
+
+fn print_site(site: &Site) {
+    println!(
+        "地点: {} (緯度 {}, 経度 {}, 標高 {}m, UTC{:+})",
+        site.name, site.latitude, site.longitude, site.altitude, site.timezone
+    );
+    println!(
+        "パネル: 傾斜角 {}度, 方位角 {}度, 定格出力 {}",
+        site.tilt,
+        site.azimuth,
+        site.rated_kw
+            .map_or("未設定".to_string(), |rated_kw| format!(
+                "{}kW",
+                rated_kw
+            ))
+    );
+}
+
+fn print_comparison(site: &Site, summary: &ComparisonSummary) {
+    println!(
+        "大気外日射量の積算値(kwh/m^2): {:.4}",
+        summary.extraterrestrial_kwh
+    );
+    println!("大気外日射量に対する比: {:.4}", summary.clearness_index);
+    println!(
+        "晴天日射量({:?})の積算値(kwh/m^2): {:.4}",
+        site.clear_sky_model, summary.clear_sky_kwh
+    );
+    println!("晴天日射量に対する比: {:.4}", summary.clear_sky_ratio);
+}
+
+// エラーの表示で標準出力を指す名前
+const STDOUT_NAME: &str = "標準出力";
+
+// CSVなどの出力先。省略したときは標準出力
+fn create_output(out: Option<&str>) -> Result<Box<dyn Write>> {
+    Ok(match out {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path).map_err(|e| Error::output(path, e))?,
+        )),
+        None => Box::new(std::io::BufWriter::new(std::io::stdout())),
+    })
+}
+
+fn values_or_missing(from: &DateTime<Local>, values: Vec<f64>) -> Result<Vec<f64>> {
+    if values.is_empty() {
+        return Err(Error::MissingData(from.date_naive().to_string()));
+    }
+    Ok(values)
+}
@@ -0,0 +1,397 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, Local};
+use serde::Deserialize;
+
+use crate::analysis::grid::GridParams;
+use crate::clearsky::{BirdParams, ClearSkyModel};
+use crate::decomposition::{DecompositionModel, DirintCoefficients};
+use crate::error::{Error, Result};
+use crate::es::{EsSource, Field};
+use crate::pvsystem::PvSystemParams;
+use crate::q;
+use crate::qc::QcParams;
+use crate::solar_position::SolarPositionModel;
+use crate::spa::SpaParams;
+use crate::transposition::SkyDiffuseModel;
+
+// --configを指定しなかったときに読み込む設定ファイル
+pub const DEFAULT_CONFIG_PATH: &str = "solar.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Config {
+    pub elasticsearch: ElasticsearchConfig,
+    pub plot: PlotConfig,
+    pub sites: Vec<Site>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct ElasticsearchConfig {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct PlotConfig {
+    pub field: String,
+    pub out: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+// 計測地点(発電所)の情報
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Site {
+    pub name: String,
+    // 緯度・経度(度)
+    pub latitude: f64,
+    pub longitude: f64,
+    // 標高(m)
+    #[serde(default)]
+    pub altitude: f64,
+    // 計測データの時刻の協定世界時からの時差(時間)。日本標準時なら9
+    #[serde(default = "default_timezone")]
+    pub timezone: f64,
+    // パネルの傾斜角(水平が0度)と方位角(北が0度、南が180度)
+    #[serde(default)]
+    pub tilt: f64,
+    #[serde(default = "default_azimuth")]
+    pub azimuth: f64,
+    // 地面のアルベド(反射率)
+    #[serde(default = "default_albedo")]
+    pub albedo: f64,
+    // パネル面への散乱日射の計算方法("isotropic", "hay-davies", "perez")
+    #[serde(default)]
+    pub sky_diffuse_model: SkyDiffuseModel,
+    // 実測の全天日射量を直達・散乱に分ける方法("erbs", "disc", "dirint")
+    #[serde(default)]
+    pub decomposition_model: DecompositionModel,
+    // DIRINTモデルの係数表のファイル
+    pub dirint_coefficients: Option<String>,
+    // 読み込んだ係数表。設定ファイルを読み込むときに1度だけ読み込む
+    #[serde(skip)]
+    pub dirint_table: Option<Arc<DirintCoefficients>>,
+    // 定格出力(kW)
+    pub rated_kw: Option<f64>,
+    // 発電出力の予測に使う定数
+    #[serde(default)]
+    pub pv: PvSystemParams,
+    // 品質管理で判定する値の範囲
+    #[serde(default)]
+    pub qc: QcParams,
+    // 系統の周波数・電圧の基準値と許容範囲
+    #[serde(default)]
+    pub grid: GridParams,
+    // 太陽の位置の計算方法("spencer" か "spa")
+    #[serde(default)]
+    pub solar_position_model: SolarPositionModel,
+    // 晴天日射量の計算方法("haurwitz", "ineichen", "bird")
+    #[serde(default)]
+    pub clear_sky_model: ClearSkyModel,
+    // Linke混濁係数。1つなら通年、12個なら1月から12月までの月ごとの値
+    #[serde(default = "default_linke_turbidity")]
+    pub linke_turbidity: Vec<f64>,
+    // IneichenモデルでPerezの補正を使うか。pvlibと同じく既定では使わない
+    #[serde(default)]
+    pub perez_enhancement: bool,
+    // Birdモデルの大気の条件
+    #[serde(default)]
+    pub bird: BirdParams,
+    #[serde(default = "default_index")]
+    pub index: String,
+    // 取得したJSONファイルの保存先。地点ごとに分けること
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: String,
+    // ロガーの時計のずれの補正(秒)。読み込むときにJPtimeに足す
+    #[serde(default)]
+    pub clock_offset: i64,
+}
+
+fn default_timezone() -> f64 {
+    9.0
+}
+
+fn default_azimuth() -> f64 {
+    180.0
+}
+
+fn default_albedo() -> f64 {
+    0.2
+}
+
+// Linke混濁係数の既定値
+const DEFAULT_LINKE_TURBIDITY: f64 = 3.0;
+
+fn default_linke_turbidity() -> Vec<f64> {
+    vec![DEFAULT_LINKE_TURBIDITY]
+}
+
+fn default_index() -> String {
+    EsSource::default().index
+}
+
+fn default_cache_dir() -> String {
+    EsSource::default().cache_dir
+}
+
+impl Default for ElasticsearchConfig {
+    fn default() -> Self {
+        ElasticsearchConfig {
+            url: EsSource::default().url,
+        }
+    }
+}
+
+impl Default for PlotConfig {
+    fn default() -> Self {
+        PlotConfig {
+            field: Field::SolarIrradiance.name().to_string(),
+            out: "images/plot.png".to_string(),
+            width: 1080,
+            height: 720,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            elasticsearch: ElasticsearchConfig::default(),
+            plot: PlotConfig::default(),
+            sites: vec![Site::default()],
+        }
+    }
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        Site {
+            name: "recyclekan".to_string(),
+            latitude: 33.82794,
+            longitude: 132.75093,
+            altitude: 0.0,
+            timezone: default_timezone(),
+            tilt: 0.0,
+            azimuth: default_azimuth(),
+            albedo: default_albedo(),
+            sky_diffuse_model: SkyDiffuseModel::default(),
+            decomposition_model: DecompositionModel::default(),
+            dirint_coefficients: None,
+            dirint_table: None,
+            rated_kw: None,
+            pv: PvSystemParams::default(),
+            qc: QcParams::default(),
+            grid: GridParams::default(),
+            solar_position_model: SolarPositionModel::default(),
+            clear_sky_model: ClearSkyModel::default(),
+            linke_turbidity: default_linke_turbidity(),
+            perez_enhancement: false,
+            bird: BirdParams::default(),
+            index: default_index(),
+            cache_dir: default_cache_dir(),
+            clock_offset: 0,
+        }
+    }
+}
+
+impl Config {
+    // pathを指定したときはそのファイルを必ず読み込む
+    // 省略したときはsolar.tomlがあれば読み込み、無ければ既定値を使う
+    pub fn load(path: Option<&str>) -> Result<Config> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => DEFAULT_CONFIG_PATH,
+            None => return Ok(Config::default()),
+        };
+
+        let toml_str = std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("{} を読み込めません: {}", path, e)))?;
+        let mut config = toml::from_str::<Config>(&toml_str)
+            .map_err(|e| Error::Config(format!("{}: {}", path, e)))?;
+        if config.sites.is_empty() {
+            return Err(Error::Config(format!(
+                "{}: [[sites]] が1つも定義されていません",
+                path
+            )));
+        }
+        config.plot_field()?;
+        for site in config.sites.iter_mut() {
+            if !matches!(site.linke_turbidity.len(), 1 | 12) {
+                return Err(Error::Config(format!(
+                    "{}: 地点 \"{}\" の linke_turbidity は1個か12個の値で指定してください",
+                    path, site.name
+                )));
+            }
+            site.load_dirint_table()?;
+        }
+
+        Ok(config)
+    }
+
+    // nameを省略したときは最初に定義された地点
+    pub fn site(&self, name: Option<&str>) -> Result<&Site> {
+        match name {
+            Some(name) => self
+                .sites
+                .iter()
+                .find(|site| site.name == name)
+                .ok_or_else(|| {
+                    let names = self
+                        .sites
+                        .iter()
+                        .map(|site| site.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    Error::Config(format!(
+                        "地点 \"{}\" は定義されていません (定義済みの地点: {})",
+                        name, names
+                    ))
+                }),
+            None => self
+                .sites
+                .first()
+                .ok_or_else(|| Error::Config("地点が1つも定義されていません".to_string())),
+        }
+    }
+
+    pub fn source(&self, site: &Site) -> EsSource {
+        EsSource {
+            url: self.elasticsearch.url.clone(),
+            index: site.index.clone(),
+            cache_dir: site.cache_dir.clone(),
+            clock_offset: site.clock_offset,
+        }
+    }
+
+    pub fn plot_field(&self) -> Result<Field> {
+        self.plot
+            .field
+            .parse::<Field>()
+            .map_err(|e| Error::Config(format!("[plot] field: {}", e)))
+    }
+}
+
+impl Site {
+    // DIRINTを使うときは係数表を読み込んでおく。係数表のファイルが無ければエラー
+    pub fn load_dirint_table(&mut self) -> Result<()> {
+        self.dirint_table = match (&self.decomposition_model, &self.dirint_coefficients) {
+            (DecompositionModel::Dirint, Some(path)) => {
+                Some(Arc::new(DirintCoefficients::load(path)?))
+            }
+            (DecompositionModel::Dirint, None) => {
+                return Err(Error::Config(format!(
+                    "地点 \"{}\" はDIRINTモデルを使うので係数表(dirint_coefficients)が必要です",
+                    self.name
+                )))
+            }
+            _ => None,
+        };
+        Ok(())
+    }
+
+    // 計測データの時刻が基準にしている標準時の子午線の経度
+    pub fn std_meridian(&self) -> f64 {
+        self.timezone * 15.0
+    }
+
+    // 定格出力を使う処理で、設定されていないときはエラーにする
+    pub fn require_rated_kw(&self) -> Result<f64> {
+        self.rated_kw.ok_or_else(|| {
+            Error::Config(format!(
+                "地点 \"{}\" の rated_kw (定格出力) が設定されていません",
+                self.name
+            ))
+        })
+    }
+
+    // パワーコンディショナの定格出力(kW)。pv.inverter_kwを省略したときはrated_kw
+    pub fn require_inverter_kw(&self) -> Result<f64> {
+        match self.pv.inverter_kw {
+            Some(inverter_kw) => Ok(inverter_kw),
+            None => self.require_rated_kw(),
+        }
+    }
+
+    // 標高から推定した気圧(Pa)
+    pub fn pressure(&self) -> f64 {
+        q::calc_pressure_from_altitude(self.altitude)
+    }
+
+    pub fn spa_params(&self) -> SpaParams {
+        SpaParams {
+            elevation: self.altitude,
+            pressure: self.pressure() / 100.0,
+            ..Default::default()
+        }
+    }
+
+    // 値が1つも無いときは既定値
+    pub fn linke_turbidity_at(&self, dt: &DateTime<Local>) -> f64 {
+        match self.linke_turbidity.as_slice() {
+            [] => DEFAULT_LINKE_TURBIDITY,
+            [value] => *value,
+            values => values[dt.month0() as usize % values.len()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // 一時ディレクトリに書いた設定ファイルのパス
+    fn write_config(name: &str, contents: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("config-test-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path.display().to_string()
+    }
+
+    fn site_toml(extra: &str) -> String {
+        format!(
+            "[[sites]]\nname = \"test\"\nlatitude = 33.8\nlongitude = 132.8\n{}\n",
+            extra
+        )
+    }
+
+    #[test]
+    fn sample_config_is_valid() {
+        let config =
+            Config::load(Some(concat!(env!("CARGO_MANIFEST_DIR"), "/solar.toml"))).unwrap();
+        let site = config.site(None).unwrap();
+        assert_eq!(site.name, "recyclekan");
+        assert_eq!(site.std_meridian(), 135.0);
+        assert!(config.site(Some("unknown")).is_err());
+    }
+
+    #[test]
+    fn invalid_sites_are_rejected() {
+        let path = write_config("turbidity", &site_toml("linke_turbidity = [3.0, 3.5]"));
+        assert!(matches!(Config::load(Some(&path)), Err(Error::Config(_))));
+        let path = write_config("dirint", &site_toml("decomposition_model = \"dirint\""));
+        assert!(matches!(Config::load(Some(&path)), Err(Error::Config(_))));
+        let path = write_config("empty", "sites = []\n");
+        assert!(matches!(Config::load(Some(&path)), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn linke_turbidity_by_month() {
+        let dt = Local.with_ymd_and_hms(2022, 10, 9, 12, 0, 0).unwrap();
+        let mut site = Site::default();
+        assert_eq!(site.linke_turbidity_at(&dt), 3.0);
+        site.linke_turbidity = (1..=12).map(f64::from).collect();
+        assert_eq!(site.linke_turbidity_at(&dt), 10.0);
+        // 設定ファイルを通さずに作った地点で値が無くても既定値を使う
+        site.linke_turbidity = Vec::new();
+        assert_eq!(site.linke_turbidity_at(&dt), DEFAULT_LINKE_TURBIDITY);
+    }
+}
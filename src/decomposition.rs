@@ -0,0 +1,344 @@
+// 全天日射量(水平面)を直達成分と散乱成分に分ける
+// 日射量の単位は全てW/m^2、角度の単位は度
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+use crate::q;
+
+// 分離の計算方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecompositionModel {
+    // 晴天指数から散乱日射の割合を求める
+    Erbs,
+    // 晴天指数とエアマスから直達日射量を求める
+    #[default]
+    Disc,
+    // DISCを晴天指数の変動で補正する(係数表のファイルが必要)
+    Dirint,
+}
+
+impl std::str::FromStr for DecompositionModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "erbs" => Ok(DecompositionModel::Erbs),
+            "disc" => Ok(DecompositionModel::Disc),
+            "dirint" => Ok(DecompositionModel::Dirint),
+            _ => Err(format!(
+                "不明な分離モデル \"{}\" です (指定可能な値: erbs, disc, dirint)",
+                s
+            )),
+        }
+    }
+}
+
+// これより天頂角が大きいときは直達日射量を0とする
+const MAX_ZENITH: f64 = 87.0;
+// 晴天指数を求めるときのcos(天頂角)の下限
+const MIN_COS_ZENITH: f64 = 0.065;
+// DISCモデルのエアマスの上限
+const MAX_AIRMASS: f64 = 12.0;
+
+// 系列全体の法線面直達日射量と散乱日射量(水平面)
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct Decomposition {
+    pub dni: Vec<f64>,
+    pub dhi: Vec<f64>,
+}
+
+// 晴天指数(全天日射量と水平面の大気外日射量の比)
+pub fn calc_clearness_index(ghi: f64, zenith: f64, dni_extra: f64, max: f64) -> f64 {
+    let cos_zenith = zenith.to_radians().cos().max(MIN_COS_ZENITH);
+    (ghi / (dni_extra * cos_zenith)).clamp(0.0, max)
+}
+
+// Erbs et al. (1982)。(dni, dhi) を返す
+pub fn erbs(ghi: f64, zenith: f64, dni_extra: f64) -> (f64, f64) {
+    let kt = calc_clearness_index(ghi, zenith, dni_extra, 2.0);
+    let diffuse_fraction = if kt <= 0.22 {
+        1.0 - 0.09 * kt
+    } else if kt <= 0.8 {
+        0.9511 - 0.1604 * kt + 4.388 * kt.powi(2) - 16.638 * kt.powi(3) + 12.336 * kt.powi(4)
+    } else {
+        0.165
+    };
+    let dhi = diffuse_fraction * ghi;
+    let dni = if zenith < MAX_ZENITH {
+        ((ghi - dhi) / zenith.to_radians().cos()).max(0.0)
+    } else {
+        0.0
+    };
+    (dni, dhi)
+}
+
+// Maxwell (1987) のDISCモデルの直達日射量。pressureの単位はPa
+pub fn disc(ghi: f64, zenith: f64, dni_extra: f64, pressure: f64) -> f64 {
+    if zenith >= MAX_ZENITH || ghi <= 0.0 {
+        return 0.0;
+    }
+    let kt = calc_clearness_index(ghi, zenith, dni_extra, 1.0);
+    let airmass = disc_airmass(zenith, pressure);
+    (disc_kn(kt, airmass) * dni_extra).max(0.0)
+}
+
+fn disc_airmass(zenith: f64, pressure: f64) -> f64 {
+    q::calc_absolute_airmass(q::calc_relative_airmass(zenith), pressure).min(MAX_AIRMASS)
+}
+
+// 直達日射の透過率
+fn disc_kn(kt: f64, airmass: f64) -> f64 {
+    let (kt2, kt3) = (kt.powi(2), kt.powi(3));
+    let (a, b, c) = if kt <= 0.6 {
+        (
+            0.512 - 1.56 * kt + 2.286 * kt2 - 2.222 * kt3,
+            0.37 + 0.962 * kt,
+            -0.28 + 0.932 * kt - 2.048 * kt2,
+        )
+    } else {
+        (
+            -5.743 + 21.77 * kt - 27.49 * kt2 + 11.56 * kt3,
+            41.4 - 118.5 * kt + 66.05 * kt2 + 31.9 * kt3,
+            -47.01 + 184.2 * kt - 222.0 * kt2 + 73.81 * kt3,
+        )
+    };
+    let delta_kn = a + b * (c * airmass).exp();
+    let knc = 0.866 - 0.122 * airmass + 0.0121 * airmass.powi(2) - 0.000653 * airmass.powi(3)
+        + 1.4e-05 * airmass.powi(4);
+    knc - delta_kn
+}
+
+// DIRINTモデルの係数表
+// 晴天指数(6区分)・天頂角(6区分)・晴天指数の変動(7区分)・可降水量(5区分)の順に並んだ
+// 1260個の値で、Perez et al. (1992) の表をそのまま使う。pvlibの係数表と同じ並び
+#[derive(Debug, Clone)]
+pub struct DirintCoefficients(Vec<f64>);
+
+const DIRINT_KT_BINS: [f64; 5] = [0.24, 0.4, 0.56, 0.7, 0.8];
+const DIRINT_ZENITH_BINS: [f64; 5] = [25.0, 40.0, 55.0, 70.0, 80.0];
+const DIRINT_DELTA_KT_BINS: [f64; 5] = [0.015, 0.035, 0.07, 0.15, 0.3];
+// 可降水量と晴天指数の変動が分からないときの区分
+const DIRINT_W_UNKNOWN: usize = 4;
+const DIRINT_DELTA_KT_UNKNOWN: usize = 6;
+// 晴天指数の変動は前後1時間の時刻と比べる。この秒数以上ずれた時刻しか無ければ分からないとする
+const DIRINT_NEIGHBOR_SECONDS: i64 = 3600;
+const DIRINT_NEIGHBOR_TOLERANCE_SECONDS: i64 = 600;
+
+impl DirintCoefficients {
+    const LEN: usize = 6 * 6 * 5 * 7;
+
+    // カンマか空白で区切った数値のファイルを読み込む。#から行末まではコメント
+    pub fn load(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("{} を読み込めません: {}", path, e)))?;
+        let values = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token.parse::<f64>().map_err(|e| {
+                    Error::Config(format!(
+                        "{}: \"{}\" は数値ではありません: {}",
+                        path, token, e
+                    ))
+                })
+            })
+            .collect::<Result<Vec<f64>>>()?;
+        if values.len() != Self::LEN {
+            return Err(Error::Config(format!(
+                "{}: DIRINTの係数は{}個必要ですが{}個でした",
+                path,
+                Self::LEN,
+                values.len()
+            )));
+        }
+        Ok(DirintCoefficients(values))
+    }
+
+    fn get(&self, kt_bin: usize, zenith_bin: usize, w_bin: usize, delta_kt_bin: usize) -> f64 {
+        self.0[((kt_bin * 6 + zenith_bin) * 7 + delta_kt_bin) * 5 + w_bin]
+    }
+}
+
+// 区分の上限の一覧から値の区分の番号を求める
+fn bin_index(bins: &[f64], value: f64) -> usize {
+    bins.iter().take_while(|upper| value >= **upper).count()
+}
+
+// dts[i]からseconds秒ずれた時刻に最も近い時刻の番号。tolerance_seconds以内に無ければNone
+// dtsは昇順に並んでいること
+fn nearest_index(dts: &[DateTime<Local>], i: usize, seconds: i64) -> Option<usize> {
+    let target = dts[i] + chrono::Duration::seconds(seconds);
+    let after = dts.partition_point(|dt| *dt < target);
+    [after.checked_sub(1), Some(after).filter(|j| *j < dts.len())]
+        .into_iter()
+        .flatten()
+        .filter(|j| *j != i)
+        .min_by_key(|j| (dts[*j] - target).num_seconds().abs())
+        .filter(|j| (dts[*j] - target).num_seconds().abs() <= DIRINT_NEIGHBOR_TOLERANCE_SECONDS)
+}
+
+// Perez et al. (1992) のDIRINTモデル。前後の時刻の晴天指数の変動を使うので系列全体で計算する
+// 変動は1時間ごとの値から求めるモデルなので、前後1時間の時刻と比べる
+pub fn dirint(
+    dts: &[DateTime<Local>],
+    ghi: &[f64],
+    zenith: &[f64],
+    dni_extra: &[f64],
+    pressure: f64,
+    coefficients: &DirintCoefficients,
+) -> Vec<f64> {
+    // エアマスの影響を除いた晴天指数
+    let kt_prime = (0..ghi.len())
+        .map(|i| {
+            let kt = calc_clearness_index(ghi[i], zenith[i], dni_extra[i], 1.0);
+            let airmass = if zenith[i] < 90.0 {
+                disc_airmass(zenith[i], pressure)
+            } else {
+                MAX_AIRMASS
+            };
+            (kt / (1.031 * (-1.4 / (0.9 + 9.4 / airmass)).exp() + 0.1)).min(0.82)
+        })
+        .collect::<Vec<f64>>();
+
+    (0..ghi.len())
+        .map(|i| {
+            let dni = disc(ghi[i], zenith[i], dni_extra[i], pressure);
+            if dni <= 0.0 {
+                return 0.0;
+            }
+            let delta = |seconds: i64| {
+                nearest_index(dts, i, seconds).map(|j| (kt_prime[i] - kt_prime[j]).abs())
+            };
+            let delta_kt_bin = match (
+                delta(-DIRINT_NEIGHBOR_SECONDS),
+                delta(DIRINT_NEIGHBOR_SECONDS),
+            ) {
+                (Some(prev), Some(next)) => bin_index(&DIRINT_DELTA_KT_BINS, 0.5 * (prev + next)),
+                (Some(delta), None) | (None, Some(delta)) => {
+                    bin_index(&DIRINT_DELTA_KT_BINS, delta)
+                }
+                (None, None) => DIRINT_DELTA_KT_UNKNOWN,
+            };
+            let coefficient = coefficients.get(
+                bin_index(&DIRINT_KT_BINS, kt_prime[i]),
+                bin_index(&DIRINT_ZENITH_BINS, zenith[i]),
+                DIRINT_W_UNKNOWN,
+                delta_kt_bin,
+            );
+            dni * coefficient
+        })
+        .collect()
+}
+
+// 全天日射量の系列を分離する
+// zenithは大気差を補正した天頂角、pressureの単位はPa
+// DIRINTのときはcoefficientsが必要。dtsは晴天指数の変動を求めるのに使う
+pub fn decompose(
+    model: DecompositionModel,
+    dts: &[DateTime<Local>],
+    ghi: &[f64],
+    zenith: &[f64],
+    dni_extra: &[f64],
+    pressure: f64,
+    coefficients: Option<&DirintCoefficients>,
+) -> Result<Decomposition> {
+    let dni = match model {
+        DecompositionModel::Erbs => (0..ghi.len())
+            .map(|i| erbs(ghi[i], zenith[i], dni_extra[i]).0)
+            .collect::<Vec<f64>>(),
+        DecompositionModel::Disc => (0..ghi.len())
+            .map(|i| disc(ghi[i], zenith[i], dni_extra[i], pressure))
+            .collect::<Vec<f64>>(),
+        DecompositionModel::Dirint => {
+            let coefficients = coefficients.ok_or_else(|| {
+                Error::Config("DIRINTモデルには係数表(dirint_coefficients)が必要です".to_string())
+            })?;
+            dirint(dts, ghi, zenith, dni_extra, pressure, coefficients)
+        }
+    };
+    // 散乱日射量は全天日射量から直達成分を引いた残り
+    let dhi = (0..ghi.len())
+        .map(|i| {
+            let cos_zenith = zenith[i].to_radians().cos().max(0.0);
+            (ghi[i] - dni[i] * cos_zenith).max(0.0)
+        })
+        .collect();
+
+    Ok(Decomposition { dni, dhi })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // 期待値の晴天指数になる大気外日射量
+    fn dni_extra_from_kt(ghi: f64, zenith: f64, kt: f64) -> f64 {
+        ghi / (kt * zenith.to_radians().cos())
+    }
+
+    #[test]
+    fn erbs_matches_pvlib() {
+        // pvlibのtest_irradiance.pyのtest_erbsの値。大気外日射量は期待値の晴天指数から逆算した
+        for (ghi, zenith, kt, dni, dhi) in [
+            (50.0, 85.0, 0.405723511, 96.7192672, 41.5703604),
+            (1000.0, 10.0, 0.718132729, 794.205651, 217.860117),
+            (1000.0, 10.0, 0.768214312, 842.001578, 170.790318),
+        ] {
+            let (actual_dni, actual_dhi) = erbs(ghi, zenith, dni_extra_from_kt(ghi, zenith, kt));
+            assert!((actual_dni - dni).abs() < 1e-4, "{} != {}", actual_dni, dni);
+            assert!((actual_dhi - dhi).abs() < 1e-4, "{} != {}", actual_dhi, dhi);
+        }
+    }
+
+    // pvlibのtest_irradiance.pyのtest_disc_valueの入力 (全天日射量, 天頂角, 晴天指数, 直達日射量)
+    // 気圧は93193Pa。大気外日射量は期待値の晴天指数から逆算した
+    const DISC_CASES: [(f64, f64, f64, f64); 2] = [
+        (1038.62, 10.567, 0.79742, 830.46),
+        (254.53, 72.469, 0.63776, 676.09),
+    ];
+
+    #[test]
+    fn disc_matches_pvlib() {
+        // pvlibはKasten (1966) の相対エアマスを使うので、Kasten & Young (1989) との差の分だけずれる
+        for (ghi, zenith, kt, expected) in DISC_CASES {
+            let dni = disc(ghi, zenith, dni_extra_from_kt(ghi, zenith, kt), 93193.0);
+            assert!((dni - expected).abs() < 0.2, "{} != {}", dni, expected);
+        }
+    }
+
+    #[test]
+    fn dirint_matches_pvlib() {
+        // pvlibのtest_irradiance.pyのtest_dirint_valueとtest_dirint_no_delta_ktの値
+        // 係数表のファイルは同梱していないので、この2つの時刻で使う区分の係数だけを入れた表を使う
+        // 係数はpvlibの結果をDISCの結果で割った値で、それ以外の区分は1
+        // エアマスの影響を除いた晴天指数は0.7917と0.7711でどちらも5番目、変動は0.021で2番目の区分
+        let mut values = vec![1.0; DirintCoefficients::LEN];
+        let mut set = |kt_bin: usize, zenith_bin: usize, delta_kt_bin: usize, value: f64| {
+            values[((kt_bin * 6 + zenith_bin) * 7 + delta_kt_bin) * 5 + DIRINT_W_UNKNOWN] = value;
+        };
+        set(4, 0, 1, 868.8 / 830.46);
+        set(4, 4, 1, 699.7 / 676.09);
+        set(4, 0, DIRINT_DELTA_KT_UNKNOWN, 861.9 / 830.46);
+        set(4, 4, DIRINT_DELTA_KT_UNKNOWN, 670.4 / 676.09);
+        let table = DirintCoefficients(values);
+
+        let ghi = DISC_CASES.map(|(ghi, ..)| ghi);
+        let zenith = DISC_CASES.map(|(_, zenith, ..)| zenith);
+        let dni_extra = DISC_CASES.map(|(ghi, zenith, kt, _)| dni_extra_from_kt(ghi, zenith, kt));
+        let start = Local.with_ymd_and_hms(2014, 6, 24, 12, 0, 0).unwrap();
+        // 1時間ごとなら前後の時刻との晴天指数の変動を使い、6時間ごとなら変動は分からない
+        for (hours, expected) in [(1, [868.8, 699.7]), (6, [861.9, 670.4])] {
+            let dts = [start, start + chrono::Duration::hours(hours)];
+            let dni = dirint(&dts, &ghi, &zenith, &dni_extra, 93193.0, &table);
+            for (dni, expected) in dni.iter().zip(expected) {
+                assert!((dni - expected).abs() < 0.5, "{} != {}", dni, expected);
+            }
+        }
+    }
+}
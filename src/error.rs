@@ -0,0 +1,118 @@
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    // Elasticsearchとの通信に失敗した(タイムアウトや接続拒否など)
+    #[error("Elasticsearchへの接続に失敗しました: {0}")]
+    Connection(#[from] elasticsearch::Error),
+
+    // 認証情報が無い、もしくは認証が拒否された
+    #[error("Elasticsearchの認証に失敗しました: {0}")]
+    Auth(String),
+
+    // 成功以外のステータスコードが返された(認証の失敗は除く)
+    #[error("Elasticsearchがステータスコード {0} を返しました")]
+    Status(u16),
+
+    // レスポンスのJSONが想定した構造になっていない
+    #[error("Elasticsearchのレスポンス形式が想定と異なります: {0}")]
+    ResponseShape(String),
+
+    // jsons/ 以下のキャッシュファイルの読み書きに失敗した
+    #[error("キャッシュファイル {path} の入出力に失敗しました: {source}")]
+    CacheIo {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    // CSVなどの出力先への書き込みに失敗した
+    #[error("出力先 {path} への書き込みに失敗しました: {source}")]
+    Output {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("JSONの解析に失敗しました: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("日時 \"{0}\" の解析に失敗しました")]
+    ParseDateTime(String),
+
+    // 設定ファイルの内容が不正
+    #[error("設定が不正です: {0}")]
+    Config(String),
+
+    #[error("グラフの描画に失敗しました: {0}")]
+    Plot(String),
+
+    // 対象日のデータが取得できなかった
+    #[error("{0} のデータが存在しません")]
+    MissingData(String),
+}
+
+impl Error {
+    pub fn cache_io(path: impl Into<String>, source: std::io::Error) -> Self {
+        Error::CacheIo {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub fn output(path: impl Into<String>, source: std::io::Error) -> Self {
+        Error::Output {
+            path: path.into(),
+            source,
+        }
+    }
+
+    // 時間をおいて再実行すれば成功する可能性があるエラーかどうか
+    // 接続の失敗・タイムアウトと、混雑(429)・サーバー側のエラー(5xx)のステータスコードだけを再試行する
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Connection(e) => {
+                e.is_timeout()
+                    || e.status_code()
+                        .is_some_and(|status| is_retryable_status(status.as_u16()))
+                    || is_io_error(e)
+            }
+            Error::Status(status) => is_retryable_status(*status),
+            _ => false,
+        }
+    }
+}
+
+fn is_retryable_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+// 接続の拒否や切断は、原因をたどると入出力のエラーになっている
+fn is_io_error(e: &elasticsearch::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(e) = source {
+        if e.is::<std::io::Error>() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_only_transient_statuses() {
+        assert!(Error::Status(429).is_retryable());
+        assert!(Error::Status(503).is_retryable());
+        assert!(!Error::Status(404).is_retryable());
+        assert!(!Error::Status(400).is_retryable());
+        assert!(!Error::ResponseShape("hits.hits".to_string()).is_retryable());
+        assert!(!Error::Config("url".to_string()).is_retryable());
+    }
+}
@@ -5,17 +5,21 @@ use elasticsearch::{
         transport::{SingleNodeConnectionPool, TransportBuilder},
         Url,
     },
-    Elasticsearch, Error, ScrollParts, SearchParts,
+    Elasticsearch, ScrollParts, SearchParts,
 };
 
-use float_extras::f64;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::{cmp::Ordering, env, io::Write};
+use std::{env, io::Write};
 
 use dotenv::dotenv;
 
+use tracing::{debug, debug_span, info, info_span, trace, warn};
+
+use crate::error::{Error, Result};
 use crate::filepath;
+use crate::logging;
+use crate::timeseries::TimeSeries;
 
 // use nalgebra::Vector3;
 
@@ -110,11 +114,208 @@ struct DocumentSource {
     utctime: String,
 }
 
+// DocumentSourceのうち数値で記録されている項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Field {
+    AcI,
+    AcPw,
+    AcV,
+    AirTemperature,
+    Co2Reduction,
+    DcI,
+    DcPw,
+    DcV,
+    Frequency,
+    OilConversionAmount,
+    RemainingStorageBatteryCapacity,
+    SingleUnitIntegratedPowerGeneration,
+    SolarIrradiance,
+    SolarCellCurrent,
+    SolarCellPower,
+    SolarCellVoltage,
+    TotalAcPower,
+    TotalUnitIntegratedPowerGeneration,
+}
+
+impl Field {
+    pub const ALL: [Field; 18] = [
+        Field::AcI,
+        Field::AcPw,
+        Field::AcV,
+        Field::AirTemperature,
+        Field::Co2Reduction,
+        Field::DcI,
+        Field::DcPw,
+        Field::DcV,
+        Field::Frequency,
+        Field::OilConversionAmount,
+        Field::RemainingStorageBatteryCapacity,
+        Field::SingleUnitIntegratedPowerGeneration,
+        Field::SolarIrradiance,
+        Field::SolarCellCurrent,
+        Field::SolarCellPower,
+        Field::SolarCellVoltage,
+        Field::TotalAcPower,
+        Field::TotalUnitIntegratedPowerGeneration,
+    ];
+
+    // コマンドライン引数やCSVのヘッダで使う名前
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::AcI => "ac_i",
+            Field::AcPw => "ac_pw",
+            Field::AcV => "ac_v",
+            Field::AirTemperature => "air_temperature",
+            Field::Co2Reduction => "co2_reduction",
+            Field::DcI => "dc_i",
+            Field::DcPw => "dc_pw",
+            Field::DcV => "dc_v",
+            Field::Frequency => "frequency",
+            Field::OilConversionAmount => "oil_conversion_amount",
+            Field::RemainingStorageBatteryCapacity => "remaining_storage_battery_capacity",
+            Field::SingleUnitIntegratedPowerGeneration => "single_unit_integrated_power_generation",
+            Field::SolarIrradiance => "solar_irradiance",
+            Field::SolarCellCurrent => "solar_cell_current",
+            Field::SolarCellPower => "solar_cell_power",
+            Field::SolarCellVoltage => "solar_cell_voltage",
+            Field::TotalAcPower => "total_ac_power",
+            Field::TotalUnitIntegratedPowerGeneration => "total_unit_integrated_power_generation",
+        }
+    }
+
+    // Elasticsearch上のキー名(単位付き)
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::AcI => "ac-i(A)",
+            Field::AcPw => "ac-pw(kw)",
+            Field::AcV => "ac-v(V)",
+            Field::AirTemperature => "airTemperature(℃)",
+            Field::Co2Reduction => "co2_reduction(kg-CO2)",
+            Field::DcI => "dc-i(A)",
+            Field::DcPw => "dc-pw(kw)",
+            Field::DcV => "dc-v(V)",
+            Field::Frequency => "frequency(Hz)",
+            Field::OilConversionAmount => "oil_conversion_amount(L)",
+            Field::RemainingStorageBatteryCapacity => "remaining storage battery capacity(%)",
+            Field::SingleUnitIntegratedPowerGeneration => {
+                "single_unit_integrated_power_generation(kwh)"
+            }
+            Field::SolarIrradiance => "solarIrradiance(kw/m^2)",
+            Field::SolarCellCurrent => "solar_cell_current(A)",
+            Field::SolarCellPower => "solar_cell_power(kw)",
+            Field::SolarCellVoltage => "solar_cell_voltage(V)",
+            Field::TotalAcPower => "total_ac_power(kw)",
+            Field::TotalUnitIntegratedPowerGeneration => {
+                "total_unit_integrated_power_generation(kwh)"
+            }
+        }
+    }
+
+    fn value(self, source: &DocumentSource) -> f64 {
+        match self {
+            Field::AcI => source.ac_i,
+            Field::AcPw => source.ac_pw,
+            Field::AcV => source.ac_v,
+            Field::AirTemperature => source.air_temperature,
+            Field::Co2Reduction => source.co2_reduction,
+            Field::DcI => source.dc_i,
+            Field::DcPw => source.dc_pw,
+            Field::DcV => source.dc_v,
+            Field::Frequency => source.frequency,
+            Field::OilConversionAmount => source.oil_conversion_amount,
+            Field::RemainingStorageBatteryCapacity => source.remaining_storage_battery_capacity,
+            Field::SingleUnitIntegratedPowerGeneration => {
+                source.single_unit_integrated_power_generation
+            }
+            Field::SolarIrradiance => source.solar_irradiance,
+            Field::SolarCellCurrent => source.solar_cell_current,
+            Field::SolarCellPower => source.solar_cell_power,
+            Field::SolarCellVoltage => source.solar_cell_voltage,
+            Field::TotalAcPower => source.total_ac_power,
+            Field::TotalUnitIntegratedPowerGeneration => {
+                source.total_unit_integrated_power_generation
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Field::ALL
+            .iter()
+            .find(|field| field.name() == s || field.label() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names = Field::ALL.map(|field| field.name()).join(", ");
+                format!("不明な項目 \"{}\" です (指定可能な項目: {})", s, names)
+            })
+    }
+}
+
+// データの取得元のElasticsearchと、取得したJSONファイルの保存先
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct EsSource {
+    pub url: String,
+    pub index: String,
+    pub cache_dir: String,
+    // 読み込むときにJPtimeに足す秒数(ロガーの時計のずれの補正)
+    pub clock_offset: i64,
+}
+
+impl Default for EsSource {
+    fn default() -> Self {
+        EsSource {
+            url: "http://133.71.201.197:9200".to_string(),
+            index: "pcs_recyclekan".to_string(),
+            cache_dir: "jsons".to_string(),
+            clock_offset: 0,
+        }
+    }
+}
+
 const ISO_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
 
-fn isoformat_to_dt(dt_str: &str) -> DateTime<chrono::Local> {
-    let dt: NaiveDateTime = NaiveDateTime::parse_from_str(dt_str, ISO_DATE_FORMAT).unwrap();
-    Local.from_local_datetime(&dt).unwrap()
+// Elasticsearchへの接続に失敗した日を再試行する回数
+const MAX_FETCH_RETRIES: u32 = 3;
+
+fn isoformat_to_dt(dt_str: &str) -> Result<DateTime<chrono::Local>> {
+    let dt: NaiveDateTime = NaiveDateTime::parse_from_str(dt_str, ISO_DATE_FORMAT)
+        .map_err(|_| Error::ParseDateTime(dt_str.to_string()))?;
+    Local
+        .from_local_datetime(&dt)
+        .earliest()
+        .ok_or_else(|| Error::ParseDateTime(dt_str.to_string()))
+}
+
+// dtと同じ日の0時0分0秒
+fn day_begin(dt: &DateTime<Local>) -> Result<DateTime<Local>> {
+    Local
+        .with_ymd_and_hms(dt.year(), dt.month(), dt.day(), 0, 0, 0)
+        .earliest()
+        .ok_or_else(|| Error::ParseDateTime(dt.to_string()))
+}
+
+fn period_end(start_dt: &DateTime<Local>, span: f64) -> DateTime<Local> {
+    *start_dt + Duration::seconds((span * 86400.0).round() as i64)
+}
+
+// start_dt ~ end_dtの期間がまたがる日数
+fn days_in_period(start_dt: &DateTime<Local>, end_dt: &DateTime<Local>) -> Result<u64> {
+    if end_dt <= start_dt {
+        return Ok(0);
+    }
+    let last_day = day_begin(&(*end_dt - Duration::seconds(1)))?;
+    Ok(((last_day - day_begin(start_dt)?).num_days() + 1) as u64)
 }
 
 fn create_doc(dt: DateTime<Local>, q: f64) -> Document {
@@ -128,100 +329,144 @@ fn create_doc(dt: DateTime<Local>, q: f64) -> Document {
     }
 }
 
-fn doc_to_dt(v: &Document) -> DateTime<Local> {
+fn doc_to_dt(v: &Document) -> Result<DateTime<Local>> {
     isoformat_to_dt(&v.source.jptime)
 }
 
+fn fetch_docs_with_retry(source: &EsSource, dt: &DateTime<Local>) -> Result<()> {
+    let mut attempt = 0;
+    loop {
+        match fetch_docs_by_datetime(source, dt) {
+            Err(e) if e.is_retryable() && attempt < MAX_FETCH_RETRIES => {
+                attempt += 1;
+                warn!(
+                    date = %dt.date_naive(),
+                    attempt,
+                    max = MAX_FETCH_RETRIES,
+                    error = %e,
+                    "取得に失敗したので再試行します"
+                );
+                std::thread::sleep(std::time::Duration::from_secs(2u64.pow(attempt)));
+            }
+            result => return result,
+        }
+    }
+}
+
+fn read_docs_from_cache(source: &EsSource, dt: &DateTime<Local>) -> Result<Vec<Document>> {
+    let file_path = filepath::get_json_file_path_by_datetime(&source.cache_dir, dt)?;
+    if !std::path::Path::new(&file_path).exists() {
+        return Err(Error::MissingData(file_path));
+    }
+
+    let json_str =
+        std::fs::read_to_string(&file_path).map_err(|e| Error::cache_io(&file_path, e))?;
+    Ok(serde_json::from_str::<Vec<Document>>(&json_str)?)
+}
+
+// 対象の日時のJSONファイルがなければ取得してから読み込み、JPtimeの昇順に並べる
+fn load_day_docs(source: &EsSource, dt: &DateTime<Local>) -> Result<Vec<Document>> {
+    debug_span!("fetch").in_scope(|| fetch_docs_with_retry(source, dt))?;
+
+    let docs = debug_span!("read_cache").in_scope(|| read_docs_from_cache(source, dt))?;
+    debug!(docs = docs.len(), "キャッシュを読み込みました");
+
+    let _sort_span = debug_span!("sort").entered();
+    let mut docs_with_dt = docs
+        .into_iter()
+        .map(|doc| Ok((doc_to_dt(&doc)?, doc)))
+        .collect::<Result<Vec<(DateTime<Local>, Document)>>>()?;
+    docs_with_dt.sort_by_key(|(dt, _)| *dt);
+    Ok(docs_with_dt.into_iter().map(|(_, doc)| doc).collect())
+}
+
 pub fn load_q_and_dt_for_period(
     start_dt: &DateTime<Local>,
     span: f64,
-) -> (Vec<DateTime<Local>>, Vec<f64>) {
-    let start = std::time::Instant::now();
+) -> Result<(Vec<DateTime<Local>>, Vec<f64>)> {
+    load_field_for_period(&EsSource::default(), start_dt, span, Field::SolarIrradiance)
+}
 
-    let mut q_all = Vec::new();
+pub fn load_field_for_period(
+    source: &EsSource,
+    start_dt: &DateTime<Local>,
+    span: f64,
+    field: Field,
+) -> Result<(Vec<DateTime<Local>>, Vec<f64>)> {
+    let series = load_series_for_period(source, start_dt, span, &[field])?;
+    let values = series.require(field)?.to_vec();
+    Ok((series.dts().to_vec(), values))
+}
+
+// start_dtからspan日分のfieldsの値を1秒刻みで読み込む
+// 欠損と、取得・読み込みできなかった日は0で補完し、補完した時刻はTimeSeries::measuredでfalseにする
+pub fn load_series_for_period(
+    source: &EsSource,
+    start_dt: &DateTime<Local>,
+    span: f64,
+    fields: &[Field],
+) -> Result<TimeSeries> {
+    let _period_span = info_span!("load_period", start = %start_dt, span, ?fields).entered();
+
+    let mut values_all = vec![Vec::new(); fields.len()];
     let mut dt_all = Vec::new();
-    let mut dt_crr_fetching = start_dt.clone();
+    let mut measured_all = Vec::new();
 
-    let separated_span = float_extras::f64::modf(span);
-    let span_float = separated_span.0;
-    let span_int = separated_span.1;
+    // 期間の末尾(この日時は含まない)
+    let end_dt = period_end(start_dt, span);
 
-    let mut is_first_loop = true;
+    // 時計のずれを補正するときは、ずれた分だけ前後の日のファイルも読み込む
+    let clock_offset = Duration::seconds(source.clock_offset);
+    let mut dt_crr_fetching = *start_dt - clock_offset;
 
-    'loop_by_day: for _ in 0..(span.ceil() as i64) {
-        // 対象の日時のJSONファイルがなければ取得する
-        fetch_docs_by_datetime(&dt_crr_fetching).unwrap();
+    let mut docs_loaded = 0;
 
-        let file_path = filepath::get_json_file_path_by_datetime(&dt_crr_fetching).unwrap();
-        if !std::path::Path::new(&file_path).exists() {
-            panic!("JSONファイルが存在しない")
-        }
+    let days = days_in_period(&(*start_dt - clock_offset), &(end_dt - clock_offset))?;
+    let pb = logging::day_progress_bar(days);
+
+    for _ in 0..days {
+        let _day_span = info_span!("load_day", date = %dt_crr_fetching.date_naive()).entered();
 
-        let json_str = std::fs::read_to_string(file_path).unwrap();
-        let mut docs = serde_json::from_str::<Vec<Document>>(&json_str).unwrap();
-        println!("docs.len(): {}", docs.len());
-
-        // JPtimeの昇順にソート
-        let sort_start = std::time::Instant::now();
-        docs.sort_by(|a, b| {
-            let a_jp_time: DateTime<Local> = isoformat_to_dt(&a.source.jptime);
-            let b_jp_time: DateTime<Local> = isoformat_to_dt(&b.source.jptime);
-
-            let duration: Duration = b_jp_time - a_jp_time;
-            if duration.num_milliseconds() > 0 {
-                Ordering::Less
-            } else if duration.num_milliseconds() == 0 {
-                Ordering::Equal
-            } else {
-                Ordering::Greater
+        // 再試行しても取得できなかった日や読み込めなかった日は、データが無い日として0で補完する
+        // 認証や設定の誤りはどの日も同じなのですぐに中断する
+        let mut docs = match load_day_docs(source, &dt_crr_fetching) {
+            Ok(docs) => docs,
+            Err(e @ (Error::Auth(_) | Error::Config(_))) => return Err(e),
+            Err(e) => {
+                warn!(date = %dt_crr_fetching.date_naive(), error = %e, "読み込めなかったので欠損として扱います");
+                Vec::new()
             }
-        });
-        let sort_end = sort_start.elapsed();
-        println!(
-            "ソート: {}.{:03}秒",
-            sort_end.as_secs(),
-            sort_end.subsec_nanos() / 1_000_000
-        );
-
-        let date = Local
-            .with_ymd_and_hms(
-                dt_crr_fetching.year(),
-                dt_crr_fetching.month(),
-                dt_crr_fetching.day(),
-                0,
-                0,
-                0,
-            )
-            .unwrap();
-
-        // 欠損値を保管する処理
-        if docs.len() == 0 {
+        };
+        docs_loaded += docs.len();
+        pb.set_message(format!("{}件", docs_loaded));
+
+        let date = day_begin(&dt_crr_fetching)?;
+
+        // 欠損値を保管する処理。補完したdocsはmeasuredをfalseにする
+        let _fill_span = debug_span!("fill_gaps").entered();
+        let mut measured_per_day;
+        if docs.is_empty() {
+            warn!(date = %date.date_naive(), "データが1件も無いので0で補完します");
             docs = (0..86400)
                 .map(|second_diff_from_day_begin| {
                     create_doc(date + Duration::seconds(second_diff_from_day_begin), 0.0)
                 })
                 .collect::<Vec<Document>>();
+            measured_per_day = vec![false; docs.len()];
         } else {
             // start_dt <= first_dt <= last_dt <= end_dt
-            let first_dt = isoformat_to_dt(&docs.first().unwrap().source.jptime);
-            let last_dt = isoformat_to_dt(&docs.last().unwrap().source.jptime);
-            let start_dt = Local
-                .with_ymd_and_hms(first_dt.year(), first_dt.month(), first_dt.day(), 0, 0, 0)
-                .unwrap();
-            let end_dt = Local
-                .with_ymd_and_hms(last_dt.year(), last_dt.month(), last_dt.day() + 1, 0, 0, 0)
-                .unwrap();
-
-            println!("first_dt: {}", first_dt);
-            println!("last_dt: {}", last_dt);
-            println!("start_dt: {}", start_dt);
-            println!("end_dt: {}", end_dt);
+            let first_dt = doc_to_dt(&docs[0])?;
+            let last_dt = doc_to_dt(&docs[docs.len() - 1])?;
+            let start_dt = day_begin(&first_dt)?;
+            let end_dt = day_begin(&last_dt)? + Duration::days(1);
+
+            trace!(%first_dt, %last_dt, %start_dt, %end_dt);
 
             // 1. start_dt ~ first_dt間を保管するdocsを生成
             let mut docs_from_start_to_first = Vec::new();
             let diff_seconds_from_start = (first_dt - start_dt).num_seconds();
 
-            println!("diff_seconds_from_start: {}", diff_seconds_from_start);
+            trace!(diff_seconds_from_start);
 
             if diff_seconds_from_start != 0 {
                 docs_from_start_to_first = (0..diff_seconds_from_start)
@@ -231,145 +476,200 @@ pub fn load_q_and_dt_for_period(
                     .collect::<Vec<Document>>();
             }
 
-            if docs_from_start_to_first.len() > 0 {
-                println!(
-                    "left 0: {}",
-                    doc_to_dt(docs_from_start_to_first.first().unwrap())
-                );
-                println!(
-                    "left -1: {}",
-                    doc_to_dt(docs_from_start_to_first.last().unwrap())
-                );
+            if let (Some(left_first), Some(left_last)) = (
+                docs_from_start_to_first.first(),
+                docs_from_start_to_first.last(),
+            ) {
+                trace!(left_first = %doc_to_dt(left_first)?, left_last = %doc_to_dt(left_last)?);
             }
 
-            println!("middle 0: {}", doc_to_dt(docs.first().unwrap()));
-            println!("middle -1: {}", doc_to_dt(docs.last().unwrap()));
-
             // 2. first_dt ~ last_dt間を保管するdocsを生成
             let diff_seconds_from_last_to_end = (end_dt - last_dt).num_seconds();
-            let offset = (last_dt
-                - Local
-                    .with_ymd_and_hms(last_dt.year(), last_dt.month(), last_dt.day(), 0, 0, 0)
-                    .unwrap())
-            .num_seconds();
+            let offset = (last_dt - day_begin(&last_dt)?).num_seconds();
             let mut docs_from_last_to_end = Vec::new();
-            if offset != offset + diff_seconds_from_last_to_end {
-                docs_from_last_to_end = ((offset + 1)
-                    ..(offset + diff_seconds_from_last_to_end + 1)) // FIXME: +1しなくても良い方を探す
+            if diff_seconds_from_last_to_end > 1 {
+                // last_dtの次の秒から翌日の0時の前の秒まで。翌日の0時は翌日のデータに含まれる
+                docs_from_last_to_end = ((offset + 1)..(offset + diff_seconds_from_last_to_end))
                     .map(|second_from_start| {
                         create_doc(date + Duration::seconds(second_from_start), 0.0)
                     })
                     .collect::<Vec<Document>>();
             }
 
-            if docs_from_last_to_end.len() > 0 {
-                println!(
-                    "right 0: {}",
-                    doc_to_dt(&docs_from_last_to_end.first().unwrap())
-                );
-                println!(
-                    "right -1: {}",
-                    doc_to_dt(&docs_from_last_to_end.last().unwrap())
-                );
+            if let (Some(right_first), Some(right_last)) =
+                (docs_from_last_to_end.first(), docs_from_last_to_end.last())
+            {
+                trace!(right_first = %doc_to_dt(right_first)?, right_last = %doc_to_dt(right_last)?);
             }
 
             // 補完用に生成したdocsをマージする
+            measured_per_day = vec![false; docs_from_start_to_first.len()];
+            measured_per_day.resize(measured_per_day.len() + docs.len(), true);
+            measured_per_day.resize(measured_per_day.len() + docs_from_last_to_end.len(), false);
             docs_from_start_to_first.append(&mut docs);
             docs_from_start_to_first.append(&mut docs_from_last_to_end);
 
             docs = docs_from_start_to_first; // FIXME: メモリ効率悪そうな気がするので直す
 
-            println!("doc_to_dt(docs[0]): {}", doc_to_dt(docs.first().unwrap()));
-            println!("doc_to_dt(docs[0]): {}", doc_to_dt(docs.last().unwrap()));
-            println!(
-                "diff_seconds_from_last_to_end: {}",
-                diff_seconds_from_last_to_end
+            debug!(
+                filled = diff_seconds_from_start + diff_seconds_from_last_to_end - 1,
+                "欠損している時間帯を0で補完しました"
             );
-            println!("offset: {}\n", offset);
+            trace!(diff_seconds_from_last_to_end, offset);
         }
+        drop(_fill_span);
 
         let mut dts_per_day = docs
             .iter()
-            .map(|doc| isoformat_to_dt(&doc.source.jptime))
-            .collect::<Vec<DateTime<chrono::Local>>>();
-        // let mut dts_per_day = Vector3::from_vec(dts_per_day);
-
-        let mut last_dt = Local.with_ymd_and_hms(2400, 1, 1, 0, 0, 0).unwrap();
-        if is_first_loop {
-            last_dt = *dts_per_day.first().unwrap()
-                + Duration::days(span_int as i64)
-                + Duration::hours((span_float * 24.0) as i64);
-            is_first_loop = false;
-        }
-        let mut qs_per_day = docs
+            .map(|doc| doc_to_dt(doc).map(|dt| dt + clock_offset))
+            .collect::<Result<Vec<DateTime<chrono::Local>>>>()?;
+
+        let mut values_per_day = fields
             .iter()
-            .map(|doc| doc.source.solar_irradiance)
-            .collect::<Vec<f64>>();
-
-        for dt in dts_per_day.iter() {
-            if *dt > last_dt {
-                // 取得しようとしている期間の末尾の日時に到達した
-
-                // last_dt以下だけ抽出し、dt_all, q_allにマージしてループから抜ける
-                let mask = dts_per_day
-                    .iter()
-                    .map(|dt| *dt <= last_dt)
-                    .collect::<Vec<bool>>();
-
-                let mut mask_iter = mask.iter();
-                dts_per_day.retain(|_| *mask_iter.next().unwrap());
-                let mut mask_iter = mask.iter();
-                qs_per_day.retain(|_| *mask_iter.next().unwrap());
-
-                dt_all.append(&mut dts_per_day);
-                q_all.append(&mut qs_per_day);
-                break 'loop_by_day;
-            }
+            .map(|field| {
+                docs.iter()
+                    .map(|doc| field.value(&doc.source))
+                    .collect::<Vec<f64>>()
+            })
+            .collect::<Vec<Vec<f64>>>();
+
+        // 期間の先頭や末尾の日は、期間内(start_dt以上end_dt未満)だけ抽出してdt_all, values_allにマージする
+        let mask = dts_per_day
+            .iter()
+            .map(|dt| start_dt <= dt && *dt < end_dt)
+            .collect::<Vec<bool>>();
+
+        let mut mask_iter = mask.iter();
+        dts_per_day.retain(|_| *mask_iter.next().unwrap());
+        for values in values_per_day.iter_mut() {
+            let mut mask_iter = mask.iter();
+            values.retain(|_| *mask_iter.next().unwrap());
         }
+        let mut mask_iter = mask.iter();
+        measured_per_day.retain(|_| *mask_iter.next().unwrap());
 
         dt_all.append(&mut dts_per_day);
-        q_all.append(&mut qs_per_day);
+        measured_all.append(&mut measured_per_day);
+        for (all, per_day) in values_all.iter_mut().zip(values_per_day.iter_mut()) {
+            all.append(per_day);
+        }
 
-        dt_crr_fetching = dt_crr_fetching + Duration::days(1);
+        dt_crr_fetching += Duration::days(1);
+        pb.inc(1);
     }
+    pb.finish_and_clear();
 
-    let end = start.elapsed();
-    println!(
-        "{}.{:03}秒経過しました。",
-        end.as_secs(),
-        end.subsec_nanos() / 1_000_000
+    info!(
+        samples = dt_all.len(),
+        docs = docs_loaded,
+        "読み込みが完了しました"
     );
 
-    return (dt_all, q_all);
+    let mut series = TimeSeries::new(dt_all);
+    for (field, values) in fields.iter().zip(values_all) {
+        series.insert_column(*field, values)?;
+    }
+    series.set_measured(measured_all)?;
+    Ok(series)
+}
+
+// start_dtからspan日分のJSONファイルをキャッシュに取得する
+// 再試行しても取得できなかった日は飛ばして続け、最後にまとめてエラーにする
+// 認証や設定の誤りはどの日も同じなのですぐに中断する
+pub fn fetch_docs_for_period(
+    source: &EsSource,
+    start_dt: &DateTime<Local>,
+    span: f64,
+) -> Result<()> {
+    let _period_span = info_span!("fetch_period", start = %start_dt, span).entered();
+
+    let days = days_in_period(start_dt, &period_end(start_dt, span))?;
+    let pb = logging::day_progress_bar(days);
+    let mut dt_crr_fetching = *start_dt;
+    let mut skipped = Vec::new();
+    for _ in 0..days {
+        let date = dt_crr_fetching.date_naive();
+        pb.set_message(date.to_string());
+        match fetch_docs_with_retry(source, &dt_crr_fetching) {
+            Ok(()) => {}
+            Err(e @ (Error::Auth(_) | Error::Config(_))) => return Err(e),
+            Err(e) => {
+                warn!(%date, error = %e, "取得できなかったので飛ばします");
+                skipped.push(date.to_string());
+            }
+        }
+        dt_crr_fetching += Duration::days(1);
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    if !skipped.is_empty() {
+        warn!(days = skipped.len(), "取得できなかった日があります");
+        return Err(Error::MissingData(skipped.join(", ")));
+    }
+    Ok(())
+}
+
+fn env_credential(key: &str) -> Result<String> {
+    env::var(key).map_err(|_| Error::Auth(format!("環境変数 {} が設定されていません", key)))
+}
+
+fn hits_of(body: &mut Value) -> Result<&mut Vec<Value>> {
+    body["hits"]["hits"]
+        .as_array_mut()
+        .ok_or_else(|| Error::ResponseShape("hits.hits が配列ではありません".to_string()))
+}
+
+fn scroll_id_of(body: &Value) -> Result<String> {
+    body["_scroll_id"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::ResponseShape("_scroll_id が含まれていません".to_string()))
+}
+
+async fn response_json(response: elasticsearch::http::response::Response) -> Result<Value> {
+    let status = response.status_code();
+    if status.as_u16() == 401 || status.as_u16() == 403 {
+        return Err(Error::Auth(format!(
+            "ステータスコード {} が返されました",
+            status
+        )));
+    }
+    if !status.is_success() {
+        return Err(Error::Status(status.as_u16()));
+    }
+    Ok(response.json::<Value>().await?)
 }
 
 #[tokio::main]
-pub async fn fetch_docs_by_datetime(dt: &DateTime<Local>) -> Result<(), Error> {
+pub async fn fetch_docs_by_datetime(source: &EsSource, dt: &DateTime<Local>) -> Result<()> {
+    let json_dir_path = filepath::get_json_dir_path(&source.cache_dir)?;
+    std::fs::create_dir_all(&json_dir_path).map_err(|e| Error::cache_io(&json_dir_path, e))?;
+
+    let file_path = filepath::get_json_file_path_by_datetime(&source.cache_dir, dt)?;
+    if std::path::Path::new(&file_path).exists() {
+        // すでに存在する(認証情報が無くてもキャッシュ済みの日は読み込めるよう先に確認する)
+        debug!(file_path, "すでにファイルが存在する");
+        return Ok(());
+    }
+
     dotenv().ok();
     let user_name_key = "RECYCLE_ELASTIC_USER_NAME";
-    let user_name = env::var(user_name_key).unwrap();
+    let user_name = env_credential(user_name_key)?;
     let password_key = "RECYCLE_ELASTIC_PASSWORD";
-    let password = env::var(password_key).unwrap();
+    let password = env_credential(password_key)?;
 
-    let credentials = Credentials::Basic(user_name.into(), password.into());
-    let u = Url::parse("http://133.71.201.197:9200")?;
+    let credentials = Credentials::Basic(user_name, password);
+    let u = Url::parse(&source.url)
+        .map_err(|e| Error::Config(format!("url \"{}\" が不正です: {}", source.url, e)))?;
     let conn_pool = SingleNodeConnectionPool::new(u);
-    let transport = TransportBuilder::new(conn_pool).auth(credentials).build()?;
+    let transport = TransportBuilder::new(conn_pool)
+        .auth(credentials)
+        .build()
+        .map_err(|e| Error::Connection(e.into()))?;
     let client = Elasticsearch::new(transport);
 
-    let path = env::current_dir()?;
-    std::fs::create_dir_all(format!("{}/jsons", path.display())).unwrap_or_else(|reason| {
-        panic!("! {:?}", reason.kind());
-    });
-
-    let file_path = filepath::get_json_file_path_by_datetime(dt).unwrap();
-    if std::path::Path::new(&file_path).exists() {
-        // すでに存在する
-        println!("すでにファイルが存在する");
-        return Ok(());
-    }
-
-    let index_name = "pcs_recyclekan";
+    let index_name = source.index.as_str();
 
     let dt_next = *dt + Duration::days(1);
 
@@ -402,15 +702,17 @@ pub async fn fetch_docs_by_datetime(dt: &DateTime<Local>) -> Result<(), Error> {
         .send()
         .await?;
 
-    let mut body = response.json::<Value>().await?;
-    hits.append(body["hits"]["hits"].as_array_mut().unwrap());
+    let mut body = response_json(response).await?;
+    hits.append(hits_of(&mut body)?);
 
-    let mut s_size = body["hits"]["total"]["value"].as_i64().unwrap();
-    let mut scroll_id = body["_scroll_id"].as_str().unwrap().to_string();
+    let mut s_size = body["hits"]["total"]["value"]
+        .as_i64()
+        .ok_or_else(|| Error::ResponseShape("hits.total.value が整数ではありません".to_string()))?;
+    let mut scroll_id = scroll_id_of(&body)?;
 
     // ヒットしている間、次のバッチを要求し続ける。
     while s_size > 0 {
-        println!("{}", hits.len());
+        trace!(hits = hits.len());
 
         response = client
             .scroll(ScrollParts::None)
@@ -421,22 +723,137 @@ pub async fn fetch_docs_by_datetime(dt: &DateTime<Local>) -> Result<(), Error> {
             .send()
             .await?;
 
-        body = response.json::<Value>().await?;
+        body = response_json(response).await?;
 
-        s_size = body["hits"]["hits"].as_array().unwrap().len() as i64;
-        scroll_id = body["_scroll_id"].as_str().unwrap().to_string(); // scroll_id を取得する
+        scroll_id = scroll_id_of(&body)?; // scroll_id を取得する
 
-        let mut_hits = body["hits"]["hits"].as_array_mut().unwrap();
+        let mut_hits = hits_of(&mut body)?;
+        s_size = mut_hits.len() as i64;
         hits.append(mut_hits);
     }
 
+    // 途中で失敗したときに中途半端なキャッシュが残らないよう、一時ファイルに書いてからリネームする
+    let tmp_file_path = format!("{}.tmp", file_path);
+    let serialized = serde_json::to_string(&hits)?;
     let mut file = std::fs::OpenOptions::new()
         .write(true)
         .create(true)
-        .open(file_path)
-        .unwrap();
-    let serialized = serde_json::to_string(&hits)?;
-    file.write_all(serialized.as_bytes())?;
+        .truncate(true)
+        .open(&tmp_file_path)
+        .map_err(|e| Error::cache_io(&tmp_file_path, e))?;
+    file.write_all(serialized.as_bytes())
+        .map_err(|e| Error::cache_io(&tmp_file_path, e))?;
+    std::fs::rename(&tmp_file_path, &file_path).map_err(|e| Error::cache_io(&file_path, e))?;
+    info!(date = %dt.date_naive(), docs = hits.len(), "ダウンロードしました");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::ops::Range;
+
+    // テストごとに空のキャッシュのディレクトリを作る
+    fn cache_dir(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("es-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.display().to_string()
+    }
+
+    // dateの0時からseconds秒後までの1秒ごとのドキュメントをキャッシュに書く
+    fn write_day(cache_dir: &str, date: &DateTime<Local>, seconds: Range<i64>) {
+        let docs = seconds
+            .map(|second| create_doc(*date + Duration::seconds(second), 0.5))
+            .collect::<Vec<Document>>();
+        let path = filepath::get_json_file_path_by_datetime(cache_dir, date).unwrap();
+        std::fs::write(path, serde_json::to_string(&docs).unwrap()).unwrap();
+    }
+
+    // どのリクエストにも404を返すサーバー
+    fn not_found_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                // ヘッダと本文を読み切ってから応答する
+                while let Ok(n) = stream.read(&mut buf) {
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text[..end]
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse::<usize>().ok())?
+                            })
+                            .unwrap_or(0);
+                        if request.len() >= end + 4 + length {
+                            break;
+                        }
+                    }
+                    if n == 0 {
+                        break;
+                    }
+                }
+                let _ = stream.write_all(
+                    b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                );
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn days_are_joined_without_duplicate_timestamps() {
+        let cache_dir = cache_dir("two-days");
+        let first = Local.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap();
+        // 1日目は23:59:59まで、2日目は途中で記録が途切れる
+        write_day(&cache_dir, &first, 86390..86400);
+        write_day(&cache_dir, &(first + Duration::days(1)), 0..10);
+        let source = EsSource {
+            cache_dir: cache_dir.clone(),
+            ..EsSource::default()
+        };
+
+        let series =
+            load_series_for_period(&source, &first, 2.0, &[Field::SolarIrradiance]).unwrap();
+        assert_eq!(series.len(), 2 * 86400);
+        assert!(series.dts().windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(series.measured().iter().filter(|m| **m).count(), 20);
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+
+    #[test]
+    fn day_that_cannot_be_fetched_is_filled_as_unmeasured() {
+        let cache_dir = cache_dir("missing-day");
+        let first = Local.with_ymd_and_hms(2022, 10, 1, 0, 0, 0).unwrap();
+        write_day(&cache_dir, &first, 0..10);
+        write_day(&cache_dir, &(first + Duration::days(2)), 0..10);
+        // 2日目はキャッシュに無く、取得しようとしても404になる
+        env::set_var("RECYCLE_ELASTIC_USER_NAME", "test");
+        env::set_var("RECYCLE_ELASTIC_PASSWORD", "test");
+        let source = EsSource {
+            url: not_found_server(),
+            cache_dir: cache_dir.clone(),
+            ..EsSource::default()
+        };
+
+        let series =
+            load_series_for_period(&source, &first, 3.0, &[Field::SolarIrradiance]).unwrap();
+        assert_eq!(series.len(), 3 * 86400);
+        let measured = series.measured();
+        assert!(measured[..10].iter().all(|measured| *measured));
+        assert!(measured[86400..2 * 86400].iter().all(|measured| !*measured));
+        assert!(measured[2 * 86400..2 * 86400 + 10]
+            .iter()
+            .all(|measured| *measured));
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+}
@@ -1,13 +1,60 @@
-use std::env;
+use std::{env, path::Path};
 
-use chrono::{DateTime, Datelike, Local};
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+
+use crate::error::{Error, Result};
 
 pub fn get_json_file_name_by_datetime(dt: &DateTime<Local>) -> String {
     format!("docs_{}{:0>2}{:0>2}.json", dt.year(), dt.month(), dt.day())
 }
 
-pub fn get_json_file_path_by_datetime(dt: &DateTime<Local>) -> Result<String, std::io::Error> {
+// cache_dirが相対パスのときはカレントディレクトリからのパスとみなす
+pub fn get_json_dir_path(cache_dir: &str) -> Result<String> {
+    if Path::new(cache_dir).is_absolute() {
+        return Ok(cache_dir.to_string());
+    }
+    let path = env::current_dir().map_err(|e| Error::cache_io(".", e))?;
+    Ok(format!("{}/{}", path.display(), cache_dir))
+}
+
+pub fn get_json_file_path_by_datetime(cache_dir: &str, dt: &DateTime<Local>) -> Result<String> {
     let file_name = get_json_file_name_by_datetime(dt);
-    let path = env::current_dir()?;
-    Ok(format!("{}/jsons/{}", path.display(), file_name))
+    Ok(format!("{}/{}", get_json_dir_path(cache_dir)?, file_name))
+}
+
+pub struct CachedFile {
+    pub date: NaiveDate,
+    pub path: String,
+    pub size: u64,
+}
+
+// cache_dir以下にある docs_YYYYMMDD.json を日付順に列挙する
+pub fn list_cached_files(cache_dir: &str) -> Result<Vec<CachedFile>> {
+    let dir = get_json_dir_path(cache_dir)?;
+    if !Path::new(&dir).exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(&dir).map_err(|e| Error::cache_io(&dir, e))? {
+        let entry = entry.map_err(|e| Error::cache_io(&dir, e))?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let date = match file_name
+            .strip_prefix("docs_")
+            .and_then(|s| s.strip_suffix(".json"))
+            .and_then(|s| NaiveDate::parse_from_str(s, "%Y%m%d").ok())
+        {
+            Some(date) => date,
+            None => continue,
+        };
+        let path = entry.path().display().to_string();
+        let size = entry
+            .metadata()
+            .map_err(|e| Error::cache_io(&path, e))?
+            .len();
+        files.push(CachedFile { date, path, size });
+    }
+    files.sort_by_key(|file| file.date);
+
+    Ok(files)
 }
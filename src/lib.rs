@@ -0,0 +1,33 @@
+// 太陽光発電の計測データの取得と、日射量の理論モデルによる分析
+//
+// データの取得: es, filepath
+// 時系列: timeseries
+// 太陽の位置と日射量の理論値: q, solar_position, spa, clearsky, transposition
+// 実測の日射量の分離: decomposition
+// 発電出力の予測: pvsystem
+// 地点の設定を使った系列の計算: model
+// 計測値の品質管理: qc
+// 分析: analysis
+// 描画: plot
+
+pub mod analysis;
+pub mod clearsky;
+pub mod config;
+pub mod decomposition;
+pub mod error;
+pub mod es;
+pub mod filepath;
+pub mod logging;
+pub mod model;
+pub mod plot;
+pub mod pvsystem;
+pub mod q;
+pub mod qc;
+pub mod solar_position;
+pub mod spa;
+pub mod timeseries;
+pub mod transposition;
+
+pub use error::{Error, Result};
+pub use es::{EsSource, Field};
+pub use timeseries::TimeSeries;
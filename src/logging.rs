@@ -0,0 +1,101 @@
+use std::io::Write;
+use std::sync::OnceLock;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+impl Verbosity {
+    fn default_filter(self) -> &'static str {
+        match self {
+            Verbosity::Quiet => "error",
+            Verbosity::Normal => "info",
+            Verbosity::Verbose => "debug",
+        }
+    }
+}
+
+static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
+
+fn verbosity() -> Verbosity {
+    *VERBOSITY.get().unwrap_or(&Verbosity::Normal)
+}
+
+// プログレスバーはすべてここに追加し、ログはバーを消してから書く
+static PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
+
+fn progress() -> &'static MultiProgress {
+    PROGRESS.get_or_init(MultiProgress::new)
+}
+
+// ログの1件分をためておき、プログレスバーを一時的に消して標準エラー出力に書く
+// バーとログが同じ行に混ざらないようにする
+struct SuspendingWriter(Vec<u8>);
+
+impl Write for SuspendingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let buf = std::mem::take(&mut self.0);
+        progress().suspend(|| std::io::stderr().write_all(&buf))
+    }
+}
+
+impl Drop for SuspendingWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+// ログの出力先(標準エラー出力)を初期化する
+// RUST_LOGが設定されていればそちらを優先する
+pub fn init(verbosity: Verbosity) {
+    VERBOSITY.get_or_init(|| verbosity);
+
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(verbosity.default_filter()));
+
+    // --verboseのときはspanの終了時に処理時間(time.busy)を出力する
+    let span_events = if verbosity == Verbosity::Verbose {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(span_events)
+        .with_writer(|| SuspendingWriter(Vec::new()))
+        .with_target(false)
+        .init();
+}
+
+// 複数日の取得の進捗を表示するプログレスバー
+// --quietのときは何も表示しない
+pub fn day_progress_bar(days: u64) -> ProgressBar {
+    let pb = ProgressBar::new(days);
+    if verbosity() == Verbosity::Quiet {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+        return pb;
+    }
+
+    pb.set_style(
+        ProgressStyle::with_template(
+            "{spinner} [{elapsed_precise}] {bar:30} {pos}/{len}日 {msg} (残り {eta})",
+        )
+        .unwrap(),
+    );
+    progress().add(pb)
+}
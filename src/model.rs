@@ -0,0 +1,305 @@
+// 地点の設定を使った日射量の理論値・実測値の換算・発電出力の予測
+// 時刻の系列に対してまとめて計算する。各モデルの式はclearsky, transposition, decomposition, pvsystemにある
+use chrono::{DateTime, Local, NaiveDate};
+
+use crate::clearsky::{self, ClearSkyInputs, ClearSkyIrradiance, ClearSkyModel};
+use crate::config::Site;
+use crate::decomposition::{self, Decomposition};
+use crate::error::Result;
+use crate::es::Field;
+use crate::pvsystem::{self, PowerEstimate};
+use crate::q;
+use crate::solar_position::{self, SolarGeometry, SolarPosition, SolarPositionModel, SunTimes};
+use crate::spa;
+use crate::timeseries::TimeSeries;
+use crate::transposition::{self, PoaIrradiance, TranspositionInputs};
+
+// siteのsolar_position_modelで指定した方法で、siteの緯度・経度・時差で計算した太陽の位置
+pub fn solar_position(site: &Site, dt: &DateTime<Local>) -> SolarPosition {
+    solar_position::calc_solar_position_by_model(
+        site.solar_position_model,
+        dt,
+        site.latitude,
+        site.longitude,
+        site.std_meridian(),
+        &site.spa_params(),
+    )
+}
+
+pub fn solar_positions(site: &Site, dts: &[DateTime<Local>]) -> Vec<SolarPosition> {
+    solar_position::calc_solar_positions_by_model(
+        site.solar_position_model,
+        dts,
+        site.latitude,
+        site.longitude,
+        site.std_meridian(),
+        &site.spa_params(),
+    )
+}
+
+pub fn sun_times(site: &Site, date: NaiveDate) -> SunTimes {
+    solar_position::calc_sun_times(date, site.latitude, site.longitude, site.std_meridian())
+}
+
+// siteの緯度・経度・時差で計算した大気外日射量(kw/m^2)
+pub fn extraterrestrial_kw(site: &Site, dt: &DateTime<Local>) -> f64 {
+    q::calc_q_kw_with_meridian(dt, site.latitude, site.longitude, site.std_meridian())
+}
+
+pub fn extraterrestrial_kw_series(site: &Site, dts: &[DateTime<Local>]) -> Vec<f64> {
+    q::calc_q_kw_series(dts, site.latitude, site.longitude, site.std_meridian())
+}
+
+// 時刻の系列に対して日射量のモデルで使う値をまとめて計算する
+pub fn solar_geometry(site: &Site, dts: &[DateTime<Local>]) -> SolarGeometry {
+    let positions = solar_positions(site, dts);
+    let apparent_zenith = positions
+        .iter()
+        .map(|position| refracted_zenith(site, position))
+        .collect();
+    SolarGeometry {
+        positions,
+        apparent_zenith,
+        dni_extra: q::calc_dni_extra_series(dts),
+    }
+}
+
+// 大気差を補正した天頂角(度)
+pub fn apparent_zenith(site: &Site, dt: &DateTime<Local>) -> f64 {
+    refracted_zenith(site, &solar_position(site, dt))
+}
+
+// SPAは補正済みの値を返すのでそのまま使う
+fn refracted_zenith(site: &Site, position: &SolarPosition) -> f64 {
+    match site.solar_position_model {
+        SolarPositionModel::Spa => position.zenith,
+        _ => {
+            let params = site.spa_params();
+            position.zenith
+                - spa::refraction_correction(
+                    position.elevation,
+                    params.pressure,
+                    params.temperature,
+                    params.atmos_refract,
+                )
+        }
+    }
+}
+
+// 気圧で補正した絶対エアマス。太陽が地平線より下にあるときはNaN
+pub fn absolute_airmass(site: &Site, dt: &DateTime<Local>) -> f64 {
+    q::calc_absolute_airmass(
+        q::calc_relative_airmass(apparent_zenith(site, dt)),
+        site.pressure(),
+    )
+}
+
+// clear_sky_modelで指定した方法で計算した晴天日射量(W/m^2)
+pub fn clear_sky(site: &Site, dt: &DateTime<Local>) -> ClearSkyIrradiance {
+    clear_sky_by_model(site, site.clear_sky_model, dt)
+}
+
+pub fn clear_sky_by_model(
+    site: &Site,
+    model: ClearSkyModel,
+    dt: &DateTime<Local>,
+) -> ClearSkyIrradiance {
+    clear_sky_at(
+        site,
+        model,
+        dt,
+        apparent_zenith(site, dt),
+        q::calc_dni_extra(dt),
+    )
+}
+
+pub fn clear_sky_series(
+    site: &Site,
+    model: ClearSkyModel,
+    dts: &[DateTime<Local>],
+    geometry: &SolarGeometry,
+) -> Vec<ClearSkyIrradiance> {
+    dts.iter()
+        .enumerate()
+        .map(|(i, dt)| {
+            clear_sky_at(
+                site,
+                model,
+                dt,
+                geometry.apparent_zenith[i],
+                geometry.dni_extra[i],
+            )
+        })
+        .collect()
+}
+
+fn clear_sky_at(
+    site: &Site,
+    model: ClearSkyModel,
+    dt: &DateTime<Local>,
+    zenith: f64,
+    dni_extra: f64,
+) -> ClearSkyIrradiance {
+    let inputs = ClearSkyInputs {
+        zenith,
+        dni_extra,
+        altitude: site.altitude,
+        linke_turbidity: site.linke_turbidity_at(dt),
+        perez_enhancement: site.perez_enhancement,
+        bird: site.bird,
+    };
+    clearsky::calc_clear_sky(model, &inputs)
+}
+
+// 水平面の日射量(W/m^2)から求めたパネル面の日射量(W/m^2)
+pub fn poa(site: &Site, dt: &DateTime<Local>, ghi: f64, dni: f64, dhi: f64) -> PoaIrradiance {
+    let position = solar_position(site, dt);
+    poa_at(
+        site,
+        position.azimuth,
+        refracted_zenith(site, &position),
+        q::calc_dni_extra(dt),
+        (ghi, dni, dhi),
+    )
+}
+
+// 日射量の系列(W/m^2)から求めたパネル面の日射量の系列
+pub fn poa_series(
+    site: &Site,
+    geometry: &SolarGeometry,
+    ghi: &[f64],
+    dni: &[f64],
+    dhi: &[f64],
+) -> Vec<PoaIrradiance> {
+    (0..ghi.len())
+        .map(|i| {
+            poa_at(
+                site,
+                geometry.positions[i].azimuth,
+                geometry.apparent_zenith[i],
+                geometry.dni_extra[i],
+                (ghi[i], dni[i], dhi[i]),
+            )
+        })
+        .collect()
+}
+
+// irradianceは (ghi, dni, dhi)
+fn poa_at(
+    site: &Site,
+    solar_azimuth: f64,
+    zenith: f64,
+    dni_extra: f64,
+    irradiance: (f64, f64, f64),
+) -> PoaIrradiance {
+    let (ghi, dni, dhi) = irradiance;
+    let inputs = TranspositionInputs {
+        solar_zenith: zenith,
+        solar_azimuth,
+        ghi,
+        dni,
+        dhi,
+        dni_extra,
+        airmass: q::calc_relative_airmass(zenith),
+    };
+    transposition::calc_poa(
+        site.sky_diffuse_model,
+        site.tilt,
+        site.azimuth,
+        site.albedo,
+        &inputs,
+    )
+}
+
+// 晴天日射量から求めたパネル面の日射量(W/m^2)
+// Haurwitzモデルは直達・散乱に分けられないので使えない(NaNになる)
+pub fn clear_sky_poa(site: &Site, dt: &DateTime<Local>) -> PoaIrradiance {
+    let clear_sky = clear_sky(site, dt);
+    poa(site, dt, clear_sky.ghi, clear_sky.dni, clear_sky.dhi)
+}
+
+pub fn clear_sky_poa_series(
+    site: &Site,
+    dts: &[DateTime<Local>],
+    geometry: &SolarGeometry,
+) -> Vec<PoaIrradiance> {
+    let clear_sky = clear_sky_series(site, site.clear_sky_model, dts, geometry);
+    (0..dts.len())
+        .map(|i| {
+            poa_at(
+                site,
+                geometry.positions[i].azimuth,
+                geometry.apparent_zenith[i],
+                geometry.dni_extra[i],
+                (clear_sky[i].ghi, clear_sky[i].dni, clear_sky[i].dhi),
+            )
+        })
+        .collect()
+}
+
+// 実測の全天日射量(W/m^2)の系列をdecomposition_modelで指定した方法で分ける
+pub fn decompose(site: &Site, dts: &[DateTime<Local>], ghi: &[f64]) -> Result<Decomposition> {
+    decompose_with_geometry(site, dts, &solar_geometry(site, dts), ghi)
+}
+
+pub fn decompose_with_geometry(
+    site: &Site,
+    dts: &[DateTime<Local>],
+    geometry: &SolarGeometry,
+    ghi: &[f64],
+) -> Result<Decomposition> {
+    decomposition::decompose(
+        site.decomposition_model,
+        dts,
+        ghi,
+        &geometry.apparent_zenith,
+        &geometry.dni_extra,
+        site.pressure(),
+        site.dirint_table.as_deref(),
+    )
+}
+
+// 実測の日射量を分離してから求めたパネル面の日射量。日射量の値が無い時刻はNone
+// seriesには日射量(solar_irradiance)が必要
+pub fn measured_poa(site: &Site, series: &TimeSeries) -> Result<Vec<Option<PoaIrradiance>>> {
+    let valid = series.valid(Field::SolarIrradiance)?;
+    // 計測値の単位はkw/m^2。値の無い時刻は分離の計算に影響しないよう0にする
+    let ghi = valid
+        .iter()
+        .map(|value| value.map_or(0.0, |value| value * 1000.0))
+        .collect::<Vec<f64>>();
+    let geometry = solar_geometry(site, series.dts());
+    let decomposition = decompose_with_geometry(site, series.dts(), &geometry, &ghi)?;
+    Ok(poa_series(
+        site,
+        &geometry,
+        &ghi,
+        &decomposition.dni,
+        &decomposition.dhi,
+    )
+    .into_iter()
+    .zip(valid.iter())
+    .map(|(poa, value)| value.map(|_| poa))
+    .collect())
+}
+
+// 実測の日射量と気温から予測した発電出力。日射量か気温の値が無い時刻はNone
+// seriesには日射量(solar_irradiance)と気温(air_temperature)が必要
+pub fn expected_power(site: &Site, series: &TimeSeries) -> Result<Vec<Option<PowerEstimate>>> {
+    let rated_kw = site.require_rated_kw()?;
+    let air_temperature = series.valid(Field::AirTemperature)?;
+    let poa = measured_poa(site, series)?;
+
+    Ok(poa
+        .iter()
+        .zip(air_temperature.iter())
+        .map(|(poa, air_temperature)| {
+            Some(pvsystem::estimate_power(
+                &site.pv,
+                rated_kw,
+                (*poa)?.global,
+                (*air_temperature)?,
+            ))
+        })
+        .collect())
+}
@@ -0,0 +1,243 @@
+use chrono::{DateTime, Local};
+use plotters::prelude::*;
+
+use crate::error::{Error, Result};
+
+// 折れ線1本分のデータ
+pub struct Line<'a> {
+    pub label: &'a str,
+    pub values: &'a [f64],
+    pub color: RGBColor,
+}
+
+// 縦線で示す時刻(日の出・日の入りなど)
+pub struct Marker<'a> {
+    pub label: &'a str,
+    pub dt: DateTime<Local>,
+}
+
+fn plot_err<E: std::error::Error>(e: E) -> Error {
+    Error::Plot(e.to_string())
+}
+
+// 時刻を横軸にした折れ線グラフをPNGで出力する
+pub fn plot_lines(
+    out_path: &str,
+    size: (u32, u32),
+    caption: &str,
+    dt_all: &[DateTime<Local>],
+    lines: &[Line],
+) -> Result<()> {
+    plot_lines_with_markers(out_path, size, caption, dt_all, lines, &[])
+}
+
+pub fn plot_lines_with_markers(
+    out_path: &str,
+    size: (u32, u32),
+    caption: &str,
+    dt_all: &[DateTime<Local>],
+    lines: &[Line],
+    markers: &[Marker],
+) -> Result<()> {
+    plot_lines_with_flags(out_path, size, caption, dt_all, lines, markers, &[])
+}
+
+// flaggedがtrueの時刻の最初の折れ線の値を灰色の点で示す(品質管理で判定された値)
+pub fn plot_lines_with_flags(
+    out_path: &str,
+    size: (u32, u32),
+    caption: &str,
+    dt_all: &[DateTime<Local>],
+    lines: &[Line],
+    markers: &[Marker],
+    flagged: &[bool],
+) -> Result<()> {
+    let (first_dt, last_dt) = match (dt_all.first(), dt_all.last()) {
+        (Some(first_dt), Some(last_dt)) => (*first_dt, *last_dt),
+        _ => return Err(Error::MissingData(caption.to_string())),
+    };
+
+    /* (1) 描画先の情報を設定 */
+    // 描画先を指定。画像出力する場合はBitMapBackend
+    let root = BitMapBackend::new(out_path, size).into_drawing_area();
+
+    // 背景を白にする
+    root.fill(&WHITE).map_err(plot_err)?;
+
+    /* (2) グラフ全般の設定 */
+    /* y軸の最大最小値を算出
+    f32型はNaNが定義されていてys.iter().max()等が使えないので工夫が必要
+    */
+    let (y_min, y_max) = lines
+        .iter()
+        .flat_map(|line| line.values.iter())
+        .fold((f64::NAN, f64::NAN), |(m, n), v| (v.min(m), v.max(n)));
+    // 全て同じ値だと範囲が0になって描画できないので少し広げる
+    let (y_min, y_max) = if y_min < y_max {
+        (y_min, y_max)
+    } else {
+        (y_min - 0.5, y_max + 0.5)
+    };
+
+    let font = ("sans-serif", 20);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, font.into_font()) // キャプションのフォントやサイズ
+        .margin(10) // 上下左右全ての余白
+        .x_label_area_size(16) // x軸ラベル部分の余白
+        .y_label_area_size(42) // y軸ラベル部分の余白
+        .build_cartesian_2d(
+            // x軸とy軸の数値の範囲を指定する
+            first_dt..last_dt, // x軸の範囲
+            y_min..y_max,      // y軸の範囲
+        )
+        .map_err(plot_err)?;
+
+    /* (3) グラフの描画 */
+
+    // x軸y軸、グリッド線などを描画
+    chart
+        .configure_mesh()
+        .x_label_formatter(&|dt| dt.format("%m/%d %H:%M").to_string())
+        .draw()
+        .map_err(plot_err)?;
+
+    // 折れ線グラフの定義＆描画
+    for line in lines {
+        let color = line.color;
+        let line_series = LineSeries::new(
+            dt_all.iter().zip(line.values.iter()).map(|(x, y)| (*x, *y)),
+            &color,
+        );
+        chart
+            .draw_series(line_series)
+            .map_err(plot_err)?
+            .label(line.label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+
+    let has_flagged = flagged.iter().any(|flagged| *flagged);
+    if let (true, Some(line)) = (has_flagged, lines.first()) {
+        let grey = RGBColor(160, 160, 160);
+        chart
+            .draw_series(
+                dt_all
+                    .iter()
+                    .zip(line.values.iter())
+                    .zip(flagged.iter())
+                    .filter(|(_, flagged)| **flagged)
+                    .map(|((x, y), _)| Circle::new((*x, *y), 2, grey.filled())),
+            )
+            .map_err(plot_err)?
+            .label("QC flagged")
+            .legend(move |(x, y)| Circle::new((x + 10, y), 3, grey.filled()));
+    }
+
+    // 描画範囲内の時刻だけ縦線とラベルを描く
+    for marker in markers
+        .iter()
+        .filter(|marker| first_dt <= marker.dt && marker.dt <= last_dt)
+    {
+        chart
+            .draw_series(LineSeries::new(
+                vec![(marker.dt, y_min), (marker.dt, y_max)],
+                BLACK.mix(0.4),
+            ))
+            .map_err(plot_err)?;
+        chart
+            .draw_series(std::iter::once(Text::new(
+                marker.label.to_string(),
+                (marker.dt, y_max),
+                ("sans-serif", 14).into_font(),
+            )))
+            .map_err(plot_err)?;
+    }
+
+    // 複数系列のときか品質管理で判定された値があるときだけ凡例を表示する
+    if lines.len() > 1 || has_flagged {
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(plot_err)?;
+    }
+
+    root.present().map_err(plot_err)?;
+
+    Ok(())
+}
+
+// 区分ごとの値の棒グラフをPNGで出力する(度数分布など)
+pub fn plot_bars(
+    out_path: &str,
+    size: (u32, u32),
+    caption: &str,
+    labels: &[String],
+    values: &[f64],
+) -> Result<()> {
+    if values.is_empty() {
+        return Err(Error::MissingData(caption.to_string()));
+    }
+
+    let root = BitMapBackend::new(out_path, size).into_drawing_area();
+    root.fill(&WHITE).map_err(plot_err)?;
+
+    let y_max = values.iter().cloned().fold(0.0, f64::max);
+    // 全て0だと範囲が0になって描画できないので少し広げる
+    let y_max = if y_max > 0.0 { y_max * 1.1 } else { 1.0 };
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 20).into_font())
+        .margin(10)
+        .x_label_area_size(24)
+        .y_label_area_size(42)
+        // 0..nの区分はn+1個になるので1つ減らす
+        .build_cartesian_2d((0..values.len() - 1).into_segmented(), 0.0..y_max)
+        .map_err(plot_err)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(values.len())
+        .x_label_formatter(&|segment| match segment {
+            SegmentValue::CenterOf(i) => labels.get(*i).cloned().unwrap_or_default(),
+            _ => String::new(),
+        })
+        .draw()
+        .map_err(plot_err)?;
+
+    chart
+        .draw_series(values.iter().enumerate().map(|(i, value)| {
+            Rectangle::new(
+                [
+                    (SegmentValue::Exact(i), 0.0),
+                    (SegmentValue::Exact(i + 1), *value),
+                ],
+                BLUE.mix(0.6).filled(),
+            )
+        }))
+        .map_err(plot_err)?;
+    // 隣の棒と区別できるように白い枠を描く
+    chart
+        .draw_series(
+            values
+                .iter()
+                .enumerate()
+                .filter(|(_, value)| **value > 0.0)
+                .map(|(i, value)| {
+                    Rectangle::new(
+                        [
+                            (SegmentValue::Exact(i), 0.0),
+                            (SegmentValue::Exact(i + 1), *value),
+                        ],
+                        WHITE.stroke_width(2),
+                    )
+                }),
+        )
+        .map_err(plot_err)?;
+
+    root.present().map_err(plot_err)?;
+
+    Ok(())
+}
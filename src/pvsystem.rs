@@ -0,0 +1,214 @@
+// パネル面の日射量と気温から発電出力を予測するモデル(PVWatts相当)
+// 日射量の単位はW/m^2、出力の単位はkW、温度の単位は℃
+use serde::Deserialize;
+
+// セル温度の計算方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CellTemperatureModel {
+    // 公称動作セル温度から線形に求める
+    Noct,
+    // 風速による冷却を考慮するSandiaの経験式
+    #[default]
+    Sandia,
+    // 熱損失係数で表すFaimanのモデル
+    Faiman,
+}
+
+impl std::str::FromStr for CellTemperatureModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "noct" => Ok(CellTemperatureModel::Noct),
+            "sandia" => Ok(CellTemperatureModel::Sandia),
+            "faiman" => Ok(CellTemperatureModel::Faiman),
+            _ => Err(format!(
+                "不明なセル温度モデル \"{}\" です (指定可能な値: noct, sandia, faiman)",
+                s
+            )),
+        }
+    }
+}
+
+// 発電システムの定数。定格出力は地点のrated_kwを使う
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct PvSystemParams {
+    // 最大出力の温度係数(1/℃)
+    pub gamma_pdc: f64,
+    pub cell_temperature_model: CellTemperatureModel,
+    // 公称動作セル温度(℃)
+    pub noct: f64,
+    // Sandiaモデルの係数(既定値はガラス/セル/ガラスの架台設置)
+    pub sandia_a: f64,
+    pub sandia_b: f64,
+    pub sandia_delta_t: f64,
+    // Faimanモデルの熱損失係数
+    pub faiman_u0: f64,
+    pub faiman_u1: f64,
+    // 風速(m/s)。計測していないので一定とみなす
+    pub wind_speed: f64,
+    // パワーコンディショナの定格出力(kW)。省略すると地点のrated_kwと同じ
+    pub inverter_kw: Option<f64>,
+    // パワーコンディショナの公称効率
+    pub inverter_efficiency: f64,
+    // 配線・汚れ・ミスマッチなどの直流側の損失の割合
+    pub losses: f64,
+}
+
+impl Default for PvSystemParams {
+    fn default() -> Self {
+        PvSystemParams {
+            gamma_pdc: -0.0037,
+            cell_temperature_model: CellTemperatureModel::default(),
+            noct: 45.0,
+            sandia_a: -3.47,
+            sandia_b: -0.0594,
+            sandia_delta_t: 3.0,
+            faiman_u0: 25.0,
+            faiman_u1: 6.84,
+            wind_speed: 1.0,
+            inverter_kw: None,
+            inverter_efficiency: 0.96,
+            losses: 0.14,
+        }
+    }
+}
+
+// PVWattsのパワーコンディショナの効率曲線の基準効率
+const INVERTER_REFERENCE_EFFICIENCY: f64 = 0.9637;
+
+// ある時刻の予測値
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct PowerEstimate {
+    pub cell_temperature: f64,
+    // 損失を差し引いた直流出力
+    pub dc: f64,
+    pub ac: f64,
+    // パワーコンディショナの定格で出力が頭打ちになったか
+    pub clipped: bool,
+}
+
+pub fn calc_cell_temperature(params: &PvSystemParams, poa: f64, air_temperature: f64) -> f64 {
+    let wind_speed = params.wind_speed;
+    match params.cell_temperature_model {
+        CellTemperatureModel::Noct => air_temperature + (params.noct - 20.0) / 800.0 * poa,
+        CellTemperatureModel::Sandia => {
+            let module_temperature =
+                poa * (params.sandia_a + params.sandia_b * wind_speed).exp() + air_temperature;
+            module_temperature + poa / 1000.0 * params.sandia_delta_t
+        }
+        CellTemperatureModel::Faiman => {
+            air_temperature + poa / (params.faiman_u0 + params.faiman_u1 * wind_speed)
+        }
+    }
+}
+
+// 損失を差し引く前の直流出力
+pub fn calc_dc_power(
+    params: &PvSystemParams,
+    rated_kw: f64,
+    poa: f64,
+    cell_temperature: f64,
+) -> f64 {
+    poa / 1000.0 * rated_kw * (1.0 + params.gamma_pdc * (cell_temperature - 25.0))
+}
+
+// PVWattsのパワーコンディショナのモデル。(交流出力, 頭打ちになったか) を返す
+pub fn calc_ac_power(params: &PvSystemParams, inverter_kw: f64, dc: f64) -> (f64, bool) {
+    if dc <= 0.0 {
+        return (0.0, false);
+    }
+    let efficiency = params.inverter_efficiency;
+    // 定格の交流出力に対応する直流入力を基準にした負荷率
+    let zeta = dc / (inverter_kw / efficiency);
+    let ac =
+        efficiency / INVERTER_REFERENCE_EFFICIENCY * (-0.0162 * zeta - 0.0059 / zeta + 0.9858) * dc;
+    if ac > inverter_kw {
+        (inverter_kw, true)
+    } else {
+        (ac.max(0.0), false)
+    }
+}
+
+pub fn estimate_power(
+    params: &PvSystemParams,
+    rated_kw: f64,
+    poa: f64,
+    air_temperature: f64,
+) -> PowerEstimate {
+    let cell_temperature = calc_cell_temperature(params, poa, air_temperature);
+    let dc = calc_dc_power(params, rated_kw, poa, cell_temperature) * (1.0 - params.losses);
+    let inverter_kw = params.inverter_kw.unwrap_or(rated_kw);
+    let (ac, clipped) = calc_ac_power(params, inverter_kw, dc);
+
+    PowerEstimate {
+        cell_temperature,
+        dc: dc.max(0.0),
+        ac,
+        clipped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(model: CellTemperatureModel, wind_speed: f64) -> PvSystemParams {
+        PvSystemParams {
+            cell_temperature_model: model,
+            wind_speed,
+            ..PvSystemParams::default()
+        }
+    }
+
+    #[test]
+    fn sandia_matches_pvlib() {
+        // pvlibのtest_temperature.pyのtest_sapm_cellの値 (ガラス/セル/ガラスの架台設置)
+        let params = params(CellTemperatureModel::Sandia, 5.0);
+        assert!((calc_cell_temperature(&params, 900.0, 20.0) - 43.509).abs() < 1e-3);
+    }
+
+    #[test]
+    fn faiman_matches_pvlib() {
+        // pvlibのtest_temperature.pyのtest_faiman_defaultの値
+        let params = params(CellTemperatureModel::Faiman, 5.0);
+        assert!((calc_cell_temperature(&params, 900.0, 20.0) - 35.203).abs() < 1e-3);
+    }
+
+    #[test]
+    fn noct_rises_by_noct_minus_20_at_800() {
+        // 公称動作セル温度の定義。800W/m^2で気温20℃のときのセル温度が公称動作セル温度になる
+        let params = params(CellTemperatureModel::Noct, 1.0);
+        assert!((calc_cell_temperature(&params, 800.0, 20.0) - params.noct).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pvwatts_dc_matches_pvlib() {
+        // pvlibのtest_pvsystem.pyのtest_pvwatts_dc_scalarsの値 (定格100、温度係数-0.003)
+        let params = PvSystemParams {
+            gamma_pdc: -0.003,
+            ..PvSystemParams::default()
+        };
+        assert!((calc_dc_power(&params, 100.0, 900.0, 30.0) - 88.65).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pvwatts_ac_matches_hand_calculation() {
+        // PVWatts v5 (Dobos 2014) の式を手計算した値。公称効率0.96、定格の交流出力4.8kW
+        let params = PvSystemParams {
+            inverter_efficiency: 0.96,
+            ..PvSystemParams::default()
+        };
+        let (ac, clipped) = calc_ac_power(&params, 4.8, 2.5);
+        assert!((ac - 2.405479).abs() < 1e-6);
+        assert!(!clipped);
+        // 定格の直流入力では公称効率になる
+        let (ac, _) = calc_ac_power(&params, 4.8, 5.0);
+        assert!((ac - 4.8).abs() < 1e-9);
+        assert_eq!(calc_ac_power(&params, 4.8, 6.0), (4.8, true));
+    }
+}
@@ -1,7 +1,28 @@
-use chrono::{DateTime, Datelike, FixedOffset, Local, TimeZone, Timelike};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike};
 use std::f64::consts::PI;
 
+// 日本標準時の基準となる子午線の経度
+pub const JST_MERIDIAN: f64 = 135.0;
+
 pub fn calc_q(dt: &DateTime<Local>, lat_deg: f64, lng_deg: f64) -> f64 {
+    calc_q_with_meridian(dt, lat_deg, lng_deg, JST_MERIDIAN)
+}
+
+// 太陽定数(W/m^2)
+pub const SOLAR_CONSTANT: f64 = 1367.0;
+
+// 日付だけで決まる太陽の赤緯・地心太陽距離・均時差
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DayTerms {
+    // 太陽赤緯(単位はラジアン)
+    pub delta: f64,
+    // 地心太陽距離のルートの中身
+    pub geocentri_distance_like: f64,
+    // 均時差(単位はラジアン)
+    pub eq: f64,
+}
+
+pub fn calc_day_terms(dt: &DateTime<Local>) -> DayTerms {
     let dt_new_year = Local.with_ymd_and_hms(dt.year(), 1, 1, 0, 0, 0).unwrap();
     let dt_delta = *dt - dt_new_year;
     let dn = (dt_delta.num_days() + 1) as f64;
@@ -30,37 +51,178 @@ pub fn calc_q(dt: &DateTime<Local>, lat_deg: f64, lng_deg: f64) -> f64 {
         - 0.014615 * theta_2x.cos()
         - 0.040849 * theta_2x.sin();
 
-    let phi = lat_deg * PI / 180.0;
+    DayTerms {
+        delta,
+        geocentri_distance_like,
+        eq,
+    }
+}
+
+// 同じ日付のDayTermsを使い回す
+// 時刻の昇順に並んだ系列を順に計算するときは1日に1回だけ計算すればよい
+#[derive(Debug, Clone, Default)]
+pub struct DayTermsCache {
+    last: Option<(NaiveDate, DayTerms)>,
+}
 
+impl DayTermsCache {
+    pub fn new() -> Self {
+        DayTermsCache::default()
+    }
+
+    pub fn get(&mut self, dt: &DateTime<Local>) -> DayTerms {
+        let date = dt.date_naive();
+        match self.last {
+            Some((last_date, day_terms)) if last_date == date => day_terms,
+            _ => {
+                let day_terms = calc_day_terms(dt);
+                self.last = Some((date, day_terms));
+                day_terms
+            }
+        }
+    }
+}
+
+// 大気外の法線面日射量(W/m^2)
+pub fn calc_dni_extra(dt: &DateTime<Local>) -> f64 {
+    SOLAR_CONSTANT * calc_day_terms(dt).geocentri_distance_like
+}
+
+// 標準気圧(Pa)
+pub const STANDARD_PRESSURE: f64 = 101325.0;
+
+// Kasten & Young (1989) の相対エアマス。zenith_degは大気差を補正した天頂角
+// 太陽が地平線より下にあるときはNaN
+pub fn calc_relative_airmass(zenith_deg: f64) -> f64 {
+    if zenith_deg > 90.0 {
+        return f64::NAN;
+    }
+    1.0 / (zenith_deg.to_radians().cos() + 0.50572 * (96.07995 - zenith_deg).powf(-1.6364))
+}
+
+// 気圧で補正した絶対エアマス。pressureの単位はPa
+pub fn calc_absolute_airmass(relative_airmass: f64, pressure: f64) -> f64 {
+    relative_airmass * pressure / STANDARD_PRESSURE
+}
+
+// 標高(m)から推定した気圧(Pa)
+pub fn calc_pressure_from_altitude(altitude: f64) -> f64 {
+    STANDARD_PRESSURE * (1.0 - 2.25577e-5 * altitude).powf(5.25588)
+}
+
+// 時角(単位はラジアン、南中で0、午後が正)
+pub fn calc_hour_angle(dt: &DateTime<Local>, lng_deg: f64, std_meridian_deg: f64, eq: f64) -> f64 {
     // 経度差
-    let lng_diff = (lng_deg - 135.0) / 180.0 * PI;
-
-    let calc_h = |dt: &DateTime<Local>, lng_diff: f64, eq: f64| {
-        let dt = FixedOffset::east_opt(0)
-            .unwrap()
-            .from_local_datetime(&dt.naive_local())
-            .unwrap();
-        (dt.hour() as f64 + ((dt.minute() as f64) / 60.0) + ((dt.second() as f64) / (60.0 * 60.0))
-            - 12.0)
-            / 12.0
-            * PI
-            + lng_diff
-            + eq
-    };
-
-    let calc_sun_altitude_like =
-        |h: f64, delta: f64, phi: f64| phi.sin() * delta.sin() + phi.cos() * delta.cos() * h.cos();
-
-    let h = calc_h(dt, lng_diff, eq);
+    let lng_diff = (lng_deg - std_meridian_deg) / 180.0 * PI;
+
+    // 計測データの時刻(地方時)の0時からの経過時間
+    let hours = dt.naive_local().num_seconds_from_midnight() as f64 / 3600.0;
+    (hours - 12.0) / 12.0 * PI + lng_diff + eq
+}
+
+// 太陽高度のsin
+pub fn calc_sun_altitude_like(h: f64, delta: f64, phi: f64) -> f64 {
+    phi.sin() * delta.sin() + phi.cos() * delta.cos() * h.cos()
+}
+
+// std_meridian_deg: dtの時刻が基準にしている標準時の子午線の経度
+pub fn calc_q_with_meridian(
+    dt: &DateTime<Local>,
+    lat_deg: f64,
+    lng_deg: f64,
+    std_meridian_deg: f64,
+) -> f64 {
+    calc_q_with_day_terms(dt, lat_deg, lng_deg, std_meridian_deg, &calc_day_terms(dt))
+}
+
+// 日付で決まる項を計算済みのときに使う
+pub fn calc_q_with_day_terms(
+    dt: &DateTime<Local>,
+    lat_deg: f64,
+    lng_deg: f64,
+    std_meridian_deg: f64,
+    day_terms: &DayTerms,
+) -> f64 {
+    let DayTerms {
+        delta,
+        geocentri_distance_like,
+        eq,
+    } = *day_terms;
+
+    let phi = lat_deg * PI / 180.0;
+
+    let h = calc_hour_angle(dt, lng_deg, std_meridian_deg, eq);
     let sin_alpha = calc_sun_altitude_like(h, delta, phi);
 
-    1367.0 * geocentri_distance_like * sin_alpha
+    SOLAR_CONSTANT * geocentri_distance_like * sin_alpha
 }
 
-pub fn calc_q_kw(dt: &DateTime<Local>, lng: f64, lat: f64) -> f64 {
-    let calc_q = calc_q(dt, lng, lat);
-    let positive_calc_q = vec![0.0, calc_q]
-        .iter()
-        .fold(0.0 / 0.0, |m, v: &f64| v.max(m));
+pub fn calc_q_kw(dt: &DateTime<Local>, lat: f64, lng: f64) -> f64 {
+    calc_q_kw_with_meridian(dt, lat, lng, JST_MERIDIAN)
+}
+
+pub fn calc_q_kw_with_meridian(
+    dt: &DateTime<Local>,
+    lat: f64,
+    lng: f64,
+    std_meridian_deg: f64,
+) -> f64 {
+    q_to_kw(calc_q_with_meridian(dt, lat, lng, std_meridian_deg))
+}
+
+// 時刻の系列の大気外日射量(kw/m^2)。日付で決まる項は日ごとに1回だけ計算する
+pub fn calc_q_kw_series(
+    dts: &[DateTime<Local>],
+    lat: f64,
+    lng: f64,
+    std_meridian_deg: f64,
+) -> Vec<f64> {
+    let mut cache = DayTermsCache::new();
+    dts.iter()
+        .map(|dt| {
+            let day_terms = cache.get(dt);
+            q_to_kw(calc_q_with_day_terms(
+                dt,
+                lat,
+                lng,
+                std_meridian_deg,
+                &day_terms,
+            ))
+        })
+        .collect()
+}
+
+// 時刻の系列の大気外の法線面日射量(W/m^2)
+pub fn calc_dni_extra_series(dts: &[DateTime<Local>]) -> Vec<f64> {
+    let mut cache = DayTermsCache::new();
+    dts.iter()
+        .map(|dt| SOLAR_CONSTANT * cache.get(dt).geocentri_distance_like)
+        .collect()
+}
+
+// 負の値(太陽が地平線より下)を0にしてkw/m^2にする
+fn q_to_kw(calc_q: f64) -> f64 {
+    let positive_calc_q = [0.0, calc_q].iter().fold(f64::NAN, |m, v: &f64| v.max(m));
     positive_calc_q / 1000.0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn series_matches_each_sample() {
+        // 大晦日の正午から元日の正午まで。経度を標準時の子午線から180度ずらして0時の前後に太陽が出ているようにする
+        let start = Local.with_ymd_and_hms(2022, 12, 31, 12, 0, 0).unwrap();
+        let dts = (0..=24 * 60)
+            .map(|i| start + Duration::minutes(i))
+            .collect::<Vec<_>>();
+        let (lat, lng) = (-35.0, JST_MERIDIAN - 180.0);
+        let series = calc_q_kw_series(&dts, lat, lng, JST_MERIDIAN);
+        assert!(series[12 * 60] > 0.0);
+        for (dt, q) in dts.iter().zip(series.iter()) {
+            assert_eq!(*q, calc_q_kw(dt, lat, lng), "{}", dt);
+        }
+    }
+}
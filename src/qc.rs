@@ -0,0 +1,195 @@
+// 計測値の品質管理
+// 日射量はBSRNの基準(物理的に可能な範囲・極めてまれな範囲)、電圧・電流・周波数などは値の範囲で判定し、
+// 時刻ごとにビットの組み合わせで記録する
+use serde::Deserialize;
+
+use crate::config::Site;
+use crate::error::Result;
+use crate::es::Field;
+use crate::model;
+use crate::timeseries::TimeSeries;
+
+// 時刻ごとの判定結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QcFlags(u8);
+
+impl QcFlags {
+    pub const NONE: QcFlags = QcFlags(0);
+    // 物理的に可能な範囲を超えた・下回った
+    pub const ABOVE_PHYSICAL: QcFlags = QcFlags(1);
+    pub const BELOW_PHYSICAL: QcFlags = QcFlags(1 << 1);
+    // 極めてまれな範囲を超えた・下回った
+    pub const ABOVE_RARE: QcFlags = QcFlags(1 << 2);
+    pub const BELOW_RARE: QcFlags = QcFlags(1 << 3);
+    // 設定した値の範囲の外
+    pub const OUT_OF_RANGE: QcFlags = QcFlags(1 << 4);
+
+    // 名前の一覧の表示順
+    pub const ALL: [QcFlags; 5] = [
+        QcFlags::ABOVE_PHYSICAL,
+        QcFlags::BELOW_PHYSICAL,
+        QcFlags::ABOVE_RARE,
+        QcFlags::BELOW_RARE,
+        QcFlags::OUT_OF_RANGE,
+    ];
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn contains(self, other: QcFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: QcFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    // 分析から除く値か。極めてまれな範囲の外の値は疑わしいだけなので除かない
+    pub fn is_excluded(self) -> bool {
+        self.0 & (QcFlags::ABOVE_PHYSICAL.0 | QcFlags::BELOW_PHYSICAL.0 | QcFlags::OUT_OF_RANGE.0)
+            != 0
+    }
+
+    // CSVなどで使う名前。1つのビットだけのときに使う
+    pub fn name(self) -> &'static str {
+        match self {
+            QcFlags::ABOVE_PHYSICAL => "above_physical",
+            QcFlags::BELOW_PHYSICAL => "below_physical",
+            QcFlags::ABOVE_RARE => "above_rare",
+            QcFlags::BELOW_RARE => "below_rare",
+            QcFlags::OUT_OF_RANGE => "out_of_range",
+            _ => "",
+        }
+    }
+}
+
+// 値の範囲の判定に使う [下限, 上限]。省略した項目は判定しない
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct QcParams {
+    pub ac_v: Option<[f64; 2]>,
+    pub ac_i: Option<[f64; 2]>,
+    pub dc_v: Option<[f64; 2]>,
+    pub dc_i: Option<[f64; 2]>,
+    pub frequency: Option<[f64; 2]>,
+    pub air_temperature: Option<[f64; 2]>,
+}
+
+impl Default for QcParams {
+    fn default() -> Self {
+        QcParams {
+            ac_v: Some([150.0, 260.0]),
+            ac_i: None,
+            dc_v: Some([0.0, 1000.0]),
+            dc_i: None,
+            frequency: Some([55.0, 65.0]),
+            air_temperature: Some([-30.0, 50.0]),
+        }
+    }
+}
+
+impl QcParams {
+    // 項目の値の範囲。範囲を判定しない項目はNone
+    pub fn range(&self, field: Field) -> Option<[f64; 2]> {
+        match field {
+            Field::AcV => self.ac_v,
+            Field::AcI => self.ac_i,
+            Field::DcV => self.dc_v,
+            Field::DcI => self.dc_i,
+            Field::Frequency => self.frequency,
+            Field::AirTemperature => self.air_temperature,
+            _ => None,
+        }
+    }
+}
+
+// 全天日射量(W/m^2)の判定。zenithは天頂角(度)、dni_extraは大気外の法線面日射量(W/m^2)
+pub fn check_ghi(ghi: f64, zenith: f64, dni_extra: f64) -> QcFlags {
+    let mu = zenith.to_radians().cos().max(0.0).powf(1.2);
+    let mut flags = QcFlags::NONE;
+    if ghi > dni_extra * 1.5 * mu + 100.0 {
+        flags.insert(QcFlags::ABOVE_PHYSICAL);
+    }
+    if ghi > dni_extra * 1.2 * mu + 50.0 {
+        flags.insert(QcFlags::ABOVE_RARE);
+    }
+    if ghi < -4.0 {
+        flags.insert(QcFlags::BELOW_PHYSICAL);
+    }
+    if ghi < -2.0 {
+        flags.insert(QcFlags::BELOW_RARE);
+    }
+    flags
+}
+
+// 値の範囲の判定
+pub fn check_range(value: f64, range: [f64; 2]) -> QcFlags {
+    if value < range[0] || value > range[1] {
+        QcFlags::OUT_OF_RANGE
+    } else {
+        QcFlags::NONE
+    }
+}
+
+// seriesの項目のうち判定できるものに、siteの設定で品質管理の判定結果を付ける
+// 欠損を補完した時刻は判定しない
+pub fn apply_qc(site: &Site, series: &mut TimeSeries) -> Result<()> {
+    let fields = series.fields().collect::<Vec<Field>>();
+    for field in fields {
+        let values = series.valid(field)?;
+        let flags = if field == Field::SolarIrradiance {
+            let geometry = model::solar_geometry(site, series.dts());
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    // 計測値の単位はkw/m^2
+                    value.map_or(QcFlags::NONE, |value| {
+                        check_ghi(
+                            value * 1000.0,
+                            geometry.apparent_zenith[i],
+                            geometry.dni_extra[i],
+                        )
+                    })
+                })
+                .collect::<Vec<QcFlags>>()
+        } else if let Some(range) = site.qc.range(field) {
+            values
+                .iter()
+                .map(|value| value.map_or(QcFlags::NONE, |value| check_range(value, range)))
+                .collect::<Vec<QcFlags>>()
+        } else {
+            continue;
+        };
+        series.set_flags(field, flags)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ghi_limits_match_bsrn() {
+        // Long & Dutton (2002) のBSRNの基準。天頂角60度、大気外日射量1361W/m^2のとき
+        // 物理的に可能な上限は 1.5 * 1361 * 0.5^1.2 + 100 = 988.61W/m^2
+        // 極めてまれな上限は 1.2 * 1361 * 0.5^1.2 + 50 = 760.89W/m^2
+        assert_eq!(check_ghi(760.0, 60.0, 1361.0), QcFlags::NONE);
+        assert_eq!(check_ghi(761.0, 60.0, 1361.0), QcFlags::ABOVE_RARE);
+        let mut above = QcFlags::ABOVE_RARE;
+        above.insert(QcFlags::ABOVE_PHYSICAL);
+        assert_eq!(check_ghi(989.0, 60.0, 1361.0), above);
+
+        // 下限は -4W/m^2 と -2W/m^2。太陽が地平線より下でも上限は100W/m^2
+        assert_eq!(check_ghi(-3.0, 95.0, 1361.0), QcFlags::BELOW_RARE);
+        assert!(check_ghi(-5.0, 95.0, 1361.0).is_excluded());
+        assert!(check_ghi(101.0, 95.0, 1361.0).contains(QcFlags::ABOVE_PHYSICAL));
+    }
+}
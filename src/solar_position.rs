@@ -0,0 +1,287 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+use crate::q::{self, DayTerms, DayTermsCache};
+use crate::spa::{self, SpaParams};
+
+// 時刻の系列の太陽の位置と、日射量のモデルで共通に使う値
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct SolarGeometry {
+    pub positions: Vec<SolarPosition>,
+    // 大気差を補正した天頂角
+    pub apparent_zenith: Vec<f64>,
+    // 大気外の法線面日射量(W/m^2)
+    pub dni_extra: Vec<f64>,
+}
+
+// 日の出・日の入りとみなす太陽高度(度)。大気差と太陽の視半径の分だけ地平線より下にある
+pub const SUNRISE_ELEVATION: f64 = -0.833;
+
+// 太陽の位置の計算方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SolarPositionModel {
+    // q::calc_qと同じフーリエ級数による近似(日ごとの値を使うので、天頂角の誤差は0.5度程度まで)
+    #[default]
+    Spencer,
+    // NREL Solar Position Algorithm(誤差は0.0003度程度、大気差の補正あり)
+    Spa,
+}
+
+impl std::str::FromStr for SolarPositionModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "spencer" => Ok(SolarPositionModel::Spencer),
+            "spa" => Ok(SolarPositionModel::Spa),
+            _ => Err(format!(
+                "不明な計算方法 \"{}\" です (指定可能な値: spencer, spa)",
+                s
+            )),
+        }
+    }
+}
+
+// ある時刻・地点での太陽の位置(角度の単位は全て度)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct SolarPosition {
+    // 天頂角
+    pub zenith: f64,
+    // 太陽高度
+    pub elevation: f64,
+    // 方位角(北が0度、東が90度、南が180度)
+    pub azimuth: f64,
+    // 時角(南中で0度、午後が正)
+    pub hour_angle: f64,
+    // 赤緯
+    pub declination: f64,
+}
+
+impl SolarPosition {
+    pub fn is_daytime(&self) -> bool {
+        self.elevation > SUNRISE_ELEVATION
+    }
+}
+
+// ある日の日の出・南中・日の入りの時刻
+// 白夜や極夜で日の出・日の入りが無い日はNone
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct SunTimes {
+    pub sunrise: Option<DateTime<Local>>,
+    pub solar_noon: DateTime<Local>,
+    pub sunset: Option<DateTime<Local>>,
+}
+
+// std_meridian_deg: dtの時刻が基準にしている標準時の子午線の経度
+pub fn calc_solar_position(
+    dt: &DateTime<Local>,
+    lat_deg: f64,
+    lng_deg: f64,
+    std_meridian_deg: f64,
+) -> SolarPosition {
+    let day_terms = q::calc_day_terms(dt);
+    calc_solar_position_with_day_terms(dt, lat_deg, lng_deg, std_meridian_deg, &day_terms)
+}
+
+// modelで指定した方法で計算する。paramsはSPAのときだけ使う
+pub fn calc_solar_position_by_model(
+    model: SolarPositionModel,
+    dt: &DateTime<Local>,
+    lat_deg: f64,
+    lng_deg: f64,
+    std_meridian_deg: f64,
+    params: &SpaParams,
+) -> SolarPosition {
+    match model {
+        SolarPositionModel::Spencer => calc_solar_position(dt, lat_deg, lng_deg, std_meridian_deg),
+        SolarPositionModel::Spa => {
+            let result = spa::calc_spa(dt, lat_deg, lng_deg, std_meridian_deg / 15.0, params);
+            SolarPosition {
+                zenith: result.zenith,
+                elevation: result.elevation,
+                azimuth: result.azimuth,
+                hour_angle: normalize_hour_angle(result.h_prime.to_radians()).to_degrees(),
+                declination: result.delta_prime,
+            }
+        }
+    }
+}
+
+// 時刻の系列の太陽の位置。日付で決まる項は日ごとに1回だけ計算する
+pub fn calc_solar_positions(
+    dts: &[DateTime<Local>],
+    lat_deg: f64,
+    lng_deg: f64,
+    std_meridian_deg: f64,
+) -> Vec<SolarPosition> {
+    let mut cache = DayTermsCache::new();
+    dts.iter()
+        .map(|dt| {
+            let day_terms = cache.get(dt);
+            calc_solar_position_with_day_terms(dt, lat_deg, lng_deg, std_meridian_deg, &day_terms)
+        })
+        .collect()
+}
+
+// SPAは時刻ごとに全て計算し直すので、フーリエ級数よりかなり遅い
+pub fn calc_solar_positions_by_model(
+    model: SolarPositionModel,
+    dts: &[DateTime<Local>],
+    lat_deg: f64,
+    lng_deg: f64,
+    std_meridian_deg: f64,
+    params: &SpaParams,
+) -> Vec<SolarPosition> {
+    match model {
+        SolarPositionModel::Spencer => {
+            calc_solar_positions(dts, lat_deg, lng_deg, std_meridian_deg)
+        }
+        SolarPositionModel::Spa => dts
+            .iter()
+            .map(|dt| {
+                calc_solar_position_by_model(model, dt, lat_deg, lng_deg, std_meridian_deg, params)
+            })
+            .collect(),
+    }
+}
+
+// 日付で決まる項を計算済みのときに使う
+pub fn calc_solar_position_with_day_terms(
+    dt: &DateTime<Local>,
+    lat_deg: f64,
+    lng_deg: f64,
+    std_meridian_deg: f64,
+    day_terms: &DayTerms,
+) -> SolarPosition {
+    let phi = lat_deg.to_radians();
+    let delta = day_terms.delta;
+    let h = q::calc_hour_angle(dt, lng_deg, std_meridian_deg, day_terms.eq);
+
+    let sin_alpha = q::calc_sun_altitude_like(h, delta, phi).clamp(-1.0, 1.0);
+    let alpha = sin_alpha.asin();
+
+    // 方位角は北から時計回り。午前は東側(180度未満)、午後は西側になる
+    let cos_azimuth =
+        ((delta.sin() - sin_alpha * phi.sin()) / (alpha.cos() * phi.cos())).clamp(-1.0, 1.0);
+    let azimuth = if h.sin() > 0.0 {
+        2.0 * PI - cos_azimuth.acos()
+    } else {
+        cos_azimuth.acos()
+    };
+
+    SolarPosition {
+        zenith: 90.0 - alpha.to_degrees(),
+        elevation: alpha.to_degrees(),
+        azimuth: azimuth.to_degrees(),
+        hour_angle: normalize_hour_angle(h).to_degrees(),
+        declination: delta.to_degrees(),
+    }
+}
+
+// -π ~ π の範囲に収める
+fn normalize_hour_angle(h: f64) -> f64 {
+    (h + PI).rem_euclid(2.0 * PI) - PI
+}
+
+pub fn calc_sun_times(
+    date: NaiveDate,
+    lat_deg: f64,
+    lng_deg: f64,
+    std_meridian_deg: f64,
+) -> SunTimes {
+    let start_of_day = date.and_time(NaiveTime::default());
+    let DayTerms { delta, eq, .. } = q::calc_day_terms(&local_datetime(start_of_day));
+    let phi = lat_deg.to_radians();
+
+    // 時角が0になる時刻が南中時刻
+    let lng_diff = (lng_deg - std_meridian_deg).to_radians();
+    let noon_hours = 12.0 - (lng_diff + eq) / PI * 12.0;
+
+    // 日の出・日の入りの時角
+    let cos_h0 = (SUNRISE_ELEVATION.to_radians().sin() - phi.sin() * delta.sin())
+        / (phi.cos() * delta.cos());
+
+    let at_hours = |hours: f64| {
+        local_datetime(start_of_day + Duration::milliseconds((hours * 3_600_000.0) as i64))
+    };
+    let (sunrise, sunset) = if (-1.0..=1.0).contains(&cos_h0) {
+        let h0_hours = cos_h0.acos() / PI * 12.0;
+        (
+            Some(at_hours(noon_hours - h0_hours)),
+            Some(at_hours(noon_hours + h0_hours)),
+        )
+    } else {
+        (None, None)
+    };
+
+    SunTimes {
+        sunrise,
+        solar_noon: at_hours(noon_hours),
+        sunset,
+    }
+}
+
+// 地方時の日時。夏時間の切り替えで存在しない時刻は、切り替えた後の時刻にずらす
+fn local_datetime(naive: NaiveDateTime) -> DateTime<Local> {
+    (0..=2)
+        .find_map(|hours| {
+            Local
+                .from_local_datetime(&(naive + Duration::hours(hours)))
+                .earliest()
+        })
+        .unwrap_or_else(|| Local.from_utc_datetime(&naive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reda & Andreas (2004) の付録A.5の例と同じ地点・時刻 (Golden, Colorado、UTC-7)
+    const LATITUDE: f64 = 39.742476;
+    const LONGITUDE: f64 = -105.1786;
+    const STD_MERIDIAN: f64 = -105.0;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2003, 10, 17).unwrap()
+    }
+
+    fn at(hour: u32, min: u32, sec: u32) -> DateTime<Local> {
+        local_datetime(date().and_hms_opt(hour, min, sec).unwrap())
+    }
+
+    fn seconds_between(a: DateTime<Local>, b: DateTime<Local>) -> i64 {
+        (a - b).num_seconds().abs()
+    }
+
+    #[test]
+    fn position_matches_spa_example() {
+        // 論文の結果は時角11.105902度、天頂角50.111622度、方位角194.340241度
+        // 日付で決まる項を日ごとに計算するので、赤緯の1日の変化の分(0.4度程度)まで天頂角がずれる
+        let position = calc_solar_position(&at(12, 30, 30), LATITUDE, LONGITUDE, STD_MERIDIAN);
+        assert!((position.hour_angle - 11.105902).abs() < 0.1);
+        assert!((position.azimuth - 194.340241).abs() < 0.2);
+        assert!((position.zenith - 50.111622).abs() < 0.5);
+    }
+
+    #[test]
+    fn sun_times_match_spa_example() {
+        // 論文の結果は日の出06:12:43、日の入り17:20:19
+        // 南中時刻は12:30:30の時角11.105902度から求めた11:46:05
+        let times = calc_sun_times(date(), LATITUDE, LONGITUDE, STD_MERIDIAN);
+        assert!(seconds_between(times.sunrise.unwrap(), at(6, 12, 43)) < 120);
+        assert!(seconds_between(times.sunset.unwrap(), at(17, 20, 19)) < 120);
+        assert!(seconds_between(times.solar_noon, at(11, 46, 5)) < 30);
+    }
+
+    #[test]
+    fn no_sunrise_in_polar_night() {
+        let date = NaiveDate::from_ymd_opt(2022, 12, 21).unwrap();
+        let times = calc_sun_times(date, 80.0, 15.0, 15.0);
+        assert_eq!((times.sunrise, times.sunset), (None, None));
+    }
+}
@@ -0,0 +1,662 @@
+// NREL Solar Position Algorithm (Reda & Andreas, 2004)
+// 論文の付録の例(2003-10-17 12:30:30 UTC-7, 緯度39.742476, 経度-105.1786)で
+// 天頂角50.11162度・方位角194.34024度になることを確認している
+use chrono::{DateTime, Datelike, Local, Timelike};
+
+// 計算条件
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct SpaParams {
+    // 標高(m)
+    pub elevation: f64,
+    // 年平均の気圧(hPa)
+    pub pressure: f64,
+    // 年平均の気温(℃)
+    pub temperature: f64,
+    // 地球時と世界時の差ΔT(秒)
+    pub delta_t: f64,
+    // 日の出・日の入りでの大気差(度)
+    pub atmos_refract: f64,
+}
+
+impl Default for SpaParams {
+    fn default() -> Self {
+        SpaParams {
+            elevation: 0.0,
+            pressure: 1013.25,
+            temperature: 12.0,
+            delta_t: 69.0,
+            atmos_refract: 0.5667,
+        }
+    }
+}
+
+impl SpaParams {
+    pub fn new(elevation: f64, pressure: f64, temperature: f64) -> Self {
+        SpaParams {
+            elevation,
+            pressure,
+            temperature,
+            ..Default::default()
+        }
+    }
+}
+
+// 計算結果(角度の単位は全て度)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct SpaResult {
+    // ユリウス日
+    pub jd: f64,
+    // 日心黄経・日心黄緯・地心距離(AU)
+    pub l: f64,
+    pub b: f64,
+    pub r: f64,
+    // 章動
+    pub delta_psi: f64,
+    pub delta_epsilon: f64,
+    // 真の黄道傾斜角
+    pub epsilon: f64,
+    // 観測地点から見た赤経・赤緯・時角
+    pub alpha_prime: f64,
+    pub delta_prime: f64,
+    pub h_prime: f64,
+    // 大気差を補正する前の太陽高度
+    pub e0: f64,
+    // 大気差を補正した太陽高度と天頂角
+    pub elevation: f64,
+    pub zenith: f64,
+    // 方位角(北が0度、東が90度)
+    pub azimuth: f64,
+}
+
+// 地球の日心黄経・日心黄緯・地心距離の周期項 [A, B, C]
+// 3.14などは論文の表の値そのもので、円周率の近似として使っているわけではない
+#[allow(clippy::approx_constant)]
+const L_TERMS: [&[[f64; 3]]; 6] = [
+    &[
+        [175347046.0, 0.0, 0.0],
+        [3341656.0, 4.6692568, 6283.07585],
+        [34894.0, 4.6261, 12566.1517],
+        [3497.0, 2.7441, 5753.3849],
+        [3418.0, 2.8289, 3.5231],
+        [3136.0, 3.6277, 77713.7715],
+        [2676.0, 4.4181, 7860.4194],
+        [2343.0, 6.1352, 3930.2097],
+        [1324.0, 0.7425, 11506.7698],
+        [1273.0, 2.0371, 529.691],
+        [1199.0, 1.1096, 1577.3435],
+        [990.0, 5.233, 5884.927],
+        [902.0, 2.045, 26.298],
+        [857.0, 3.508, 398.149],
+        [780.0, 1.179, 5223.694],
+        [753.0, 2.533, 5507.553],
+        [505.0, 4.583, 18849.228],
+        [492.0, 4.205, 775.523],
+        [357.0, 2.92, 0.067],
+        [317.0, 5.849, 11790.629],
+        [284.0, 1.899, 796.298],
+        [271.0, 0.315, 10977.079],
+        [243.0, 0.345, 5486.778],
+        [206.0, 4.806, 2544.314],
+        [205.0, 1.869, 5573.143],
+        [202.0, 2.458, 6069.777],
+        [156.0, 0.833, 213.299],
+        [132.0, 3.411, 2942.463],
+        [126.0, 1.083, 20.775],
+        [115.0, 0.645, 0.98],
+        [103.0, 0.636, 4694.003],
+        [102.0, 0.976, 15720.839],
+        [102.0, 4.267, 7.114],
+        [99.0, 6.21, 2146.17],
+        [98.0, 0.68, 155.42],
+        [86.0, 5.98, 161000.69],
+        [85.0, 1.3, 6275.96],
+        [85.0, 3.67, 71430.7],
+        [80.0, 1.81, 17260.15],
+        [79.0, 3.04, 12036.46],
+        [75.0, 1.76, 5088.63],
+        [74.0, 3.5, 3154.69],
+        [74.0, 4.68, 801.82],
+        [70.0, 0.83, 9437.76],
+        [62.0, 3.98, 8827.39],
+        [61.0, 1.82, 7084.9],
+        [57.0, 2.78, 6286.6],
+        [56.0, 4.39, 14143.5],
+        [56.0, 3.47, 6279.55],
+        [52.0, 0.19, 12139.55],
+        [52.0, 1.33, 1748.02],
+        [51.0, 0.28, 5856.48],
+        [49.0, 0.49, 1194.45],
+        [41.0, 5.37, 8429.24],
+        [41.0, 2.4, 19651.05],
+        [39.0, 6.17, 10447.39],
+        [37.0, 6.04, 10213.29],
+        [37.0, 2.57, 1059.38],
+        [36.0, 1.71, 2352.87],
+        [36.0, 1.78, 6812.77],
+        [33.0, 0.59, 17789.85],
+        [30.0, 0.44, 83996.85],
+        [30.0, 2.74, 1349.87],
+        [25.0, 3.16, 4690.48],
+    ],
+    &[
+        [628331966747.0, 0.0, 0.0],
+        [206059.0, 2.678235, 6283.07585],
+        [4303.0, 2.6351, 12566.1517],
+        [425.0, 1.59, 3.523],
+        [119.0, 5.796, 26.298],
+        [109.0, 2.966, 1577.344],
+        [93.0, 2.59, 18849.23],
+        [72.0, 1.14, 529.69],
+        [68.0, 1.87, 398.15],
+        [67.0, 4.41, 5507.55],
+        [59.0, 2.89, 5223.69],
+        [56.0, 2.17, 155.42],
+        [45.0, 0.4, 796.3],
+        [36.0, 0.47, 775.52],
+        [29.0, 2.65, 7.11],
+        [21.0, 5.34, 0.98],
+        [19.0, 1.85, 5486.78],
+        [19.0, 4.97, 213.3],
+        [17.0, 2.99, 6275.96],
+        [16.0, 0.03, 2544.31],
+        [16.0, 1.43, 2146.17],
+        [15.0, 1.21, 10977.08],
+        [12.0, 2.83, 1748.02],
+        [12.0, 3.26, 5088.63],
+        [12.0, 5.27, 1194.45],
+        [12.0, 2.08, 4694.0],
+        [11.0, 0.77, 553.57],
+        [10.0, 1.3, 6286.6],
+        [10.0, 4.24, 1349.87],
+        [9.0, 2.7, 242.73],
+        [9.0, 5.64, 951.72],
+        [8.0, 5.3, 2352.87],
+        [6.0, 2.65, 9437.76],
+        [6.0, 4.67, 4690.48],
+    ],
+    &[
+        [52919.0, 0.0, 0.0],
+        [8720.0, 1.0721, 6283.0758],
+        [309.0, 0.867, 12566.152],
+        [27.0, 0.05, 3.52],
+        [16.0, 5.19, 26.3],
+        [16.0, 3.68, 155.42],
+        [10.0, 0.76, 18849.23],
+        [9.0, 2.06, 77713.77],
+        [7.0, 0.83, 775.52],
+        [5.0, 4.66, 1577.34],
+        [4.0, 1.03, 7.11],
+        [4.0, 3.44, 5573.14],
+        [3.0, 5.14, 796.3],
+        [3.0, 6.05, 5507.55],
+        [3.0, 1.19, 242.73],
+        [3.0, 6.12, 529.69],
+        [3.0, 0.31, 398.15],
+        [3.0, 2.28, 553.57],
+        [2.0, 4.38, 5223.69],
+        [2.0, 3.75, 0.98],
+    ],
+    &[
+        [289.0, 5.844, 6283.076],
+        [35.0, 0.0, 0.0],
+        [17.0, 5.49, 12566.15],
+        [3.0, 5.2, 155.42],
+        [1.0, 4.72, 3.52],
+        [1.0, 5.3, 18849.23],
+        [1.0, 5.97, 242.73],
+    ],
+    &[
+        [114.0, 3.142, 0.0],
+        [8.0, 4.13, 6283.08],
+        [1.0, 3.84, 12566.15],
+    ],
+    &[[1.0, 3.14, 0.0]],
+];
+
+const B_TERMS: [&[[f64; 3]]; 2] = [
+    &[
+        [280.0, 3.199, 84334.662],
+        [102.0, 5.422, 5507.553],
+        [80.0, 3.88, 5223.69],
+        [44.0, 3.7, 2352.87],
+        [32.0, 4.0, 1577.34],
+    ],
+    &[[9.0, 3.9, 5507.55], [6.0, 1.73, 5223.69]],
+];
+
+#[allow(clippy::approx_constant)]
+const R_TERMS: [&[[f64; 3]]; 5] = [
+    &[
+        [100013989.0, 0.0, 0.0],
+        [1670700.0, 3.0984635, 6283.07585],
+        [13956.0, 3.05525, 12566.1517],
+        [3084.0, 5.1985, 77713.7715],
+        [1628.0, 1.1739, 5753.3849],
+        [1576.0, 2.8469, 7860.4194],
+        [925.0, 5.453, 11506.77],
+        [542.0, 4.564, 3930.21],
+        [472.0, 3.661, 5884.927],
+        [346.0, 0.964, 5507.553],
+        [329.0, 5.9, 5223.694],
+        [307.0, 0.299, 5573.143],
+        [243.0, 4.273, 11790.629],
+        [212.0, 5.847, 1577.344],
+        [186.0, 5.022, 10977.079],
+        [175.0, 3.012, 18849.228],
+        [110.0, 5.055, 5486.778],
+        [98.0, 0.89, 6069.78],
+        [86.0, 5.69, 15720.84],
+        [86.0, 1.27, 161000.69],
+        [65.0, 0.27, 17260.15],
+        [63.0, 0.92, 529.69],
+        [57.0, 2.01, 83996.85],
+        [56.0, 5.24, 71430.7],
+        [49.0, 3.25, 2544.31],
+        [47.0, 2.58, 775.52],
+        [45.0, 5.54, 9437.76],
+        [43.0, 6.01, 6275.96],
+        [39.0, 5.36, 4694.0],
+        [38.0, 2.39, 8827.39],
+        [37.0, 0.83, 19651.05],
+        [37.0, 4.9, 12139.55],
+        [36.0, 1.67, 12036.46],
+        [35.0, 1.84, 2942.46],
+        [33.0, 0.24, 7084.9],
+        [32.0, 0.18, 5088.63],
+        [32.0, 1.78, 398.15],
+        [28.0, 1.21, 6286.6],
+        [28.0, 1.9, 6279.55],
+        [26.0, 4.59, 10447.39],
+    ],
+    &[
+        [103019.0, 1.10749, 6283.07585],
+        [1721.0, 1.0644, 12566.1517],
+        [702.0, 3.142, 0.0],
+        [32.0, 1.02, 18849.23],
+        [31.0, 2.84, 5507.55],
+        [25.0, 1.32, 5223.69],
+        [18.0, 1.42, 1577.34],
+        [10.0, 5.91, 10977.08],
+        [9.0, 1.42, 6275.96],
+        [9.0, 0.27, 5486.78],
+    ],
+    &[
+        [4359.0, 5.7846, 6283.0758],
+        [124.0, 5.579, 12566.152],
+        [12.0, 3.14, 0.0],
+        [9.0, 3.63, 77713.77],
+        [6.0, 1.87, 5573.14],
+        [3.0, 5.47, 18849.23],
+    ],
+    &[[145.0, 4.273, 6283.076], [7.0, 3.92, 12566.15]],
+    &[[4.0, 2.56, 6283.08]],
+];
+
+// 章動の周期項。X0~X4に掛ける係数と、Δψ・Δεの係数 [a, b, c, d]
+const NUTATION_Y_TERMS: [[f64; 5]; 63] = [
+    [0.0, 0.0, 0.0, 0.0, 1.0],
+    [-2.0, 0.0, 0.0, 2.0, 2.0],
+    [0.0, 0.0, 0.0, 2.0, 2.0],
+    [0.0, 0.0, 0.0, 0.0, 2.0],
+    [0.0, 1.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0, 0.0],
+    [-2.0, 1.0, 0.0, 2.0, 2.0],
+    [0.0, 0.0, 0.0, 2.0, 1.0],
+    [0.0, 0.0, 1.0, 2.0, 2.0],
+    [-2.0, -1.0, 0.0, 2.0, 2.0],
+    [-2.0, 0.0, 1.0, 0.0, 0.0],
+    [-2.0, 0.0, 0.0, 2.0, 1.0],
+    [0.0, 0.0, -1.0, 2.0, 2.0],
+    [2.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0, 1.0],
+    [2.0, 0.0, -1.0, 2.0, 2.0],
+    [0.0, 0.0, -1.0, 0.0, 1.0],
+    [0.0, 0.0, 1.0, 2.0, 1.0],
+    [-2.0, 0.0, 2.0, 0.0, 0.0],
+    [0.0, 0.0, -2.0, 2.0, 1.0],
+    [2.0, 0.0, 0.0, 2.0, 2.0],
+    [0.0, 0.0, 2.0, 2.0, 2.0],
+    [0.0, 0.0, 2.0, 0.0, 0.0],
+    [-2.0, 0.0, 1.0, 2.0, 2.0],
+    [0.0, 0.0, 0.0, 2.0, 0.0],
+    [-2.0, 0.0, 0.0, 2.0, 0.0],
+    [0.0, 0.0, -1.0, 2.0, 1.0],
+    [0.0, 2.0, 0.0, 0.0, 0.0],
+    [2.0, 0.0, -1.0, 0.0, 1.0],
+    [-2.0, 2.0, 0.0, 2.0, 2.0],
+    [0.0, 1.0, 0.0, 0.0, 1.0],
+    [-2.0, 0.0, 1.0, 0.0, 1.0],
+    [0.0, -1.0, 0.0, 0.0, 1.0],
+    [0.0, 0.0, 2.0, -2.0, 0.0],
+    [2.0, 0.0, -1.0, 2.0, 1.0],
+    [2.0, 0.0, 1.0, 2.0, 2.0],
+    [0.0, 1.0, 0.0, 2.0, 2.0],
+    [-2.0, 1.0, 1.0, 0.0, 0.0],
+    [0.0, -1.0, 0.0, 2.0, 2.0],
+    [2.0, 0.0, 0.0, 2.0, 1.0],
+    [2.0, 0.0, 1.0, 0.0, 0.0],
+    [-2.0, 0.0, 2.0, 2.0, 2.0],
+    [-2.0, 0.0, 1.0, 2.0, 1.0],
+    [2.0, 0.0, -2.0, 0.0, 1.0],
+    [2.0, 0.0, 0.0, 0.0, 1.0],
+    [0.0, -1.0, 1.0, 0.0, 0.0],
+    [-2.0, -1.0, 0.0, 2.0, 1.0],
+    [-2.0, 0.0, 0.0, 0.0, 1.0],
+    [0.0, 0.0, 2.0, 2.0, 1.0],
+    [-2.0, 0.0, 2.0, 0.0, 1.0],
+    [-2.0, 1.0, 0.0, 2.0, 1.0],
+    [0.0, 0.0, 1.0, -2.0, 0.0],
+    [-1.0, 0.0, 1.0, 0.0, 0.0],
+    [-2.0, 1.0, 0.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 2.0, 0.0],
+    [0.0, 0.0, -2.0, 2.0, 2.0],
+    [-1.0, -1.0, 1.0, 0.0, 0.0],
+    [0.0, 1.0, 1.0, 0.0, 0.0],
+    [0.0, -1.0, 1.0, 2.0, 2.0],
+    [2.0, -1.0, -1.0, 2.0, 2.0],
+    [0.0, 0.0, 3.0, 2.0, 2.0],
+    [2.0, -1.0, 0.0, 2.0, 2.0],
+];
+
+const NUTATION_PE_TERMS: [[f64; 4]; 63] = [
+    [-171996.0, -174.2, 92025.0, 8.9],
+    [-13187.0, -1.6, 5736.0, -3.1],
+    [-2274.0, -0.2, 977.0, -0.5],
+    [2062.0, 0.2, -895.0, 0.5],
+    [1426.0, -3.4, 54.0, -0.1],
+    [712.0, 0.1, -7.0, 0.0],
+    [-517.0, 1.2, 224.0, -0.6],
+    [-386.0, -0.4, 200.0, 0.0],
+    [-301.0, 0.0, 129.0, -0.1],
+    [217.0, -0.5, -95.0, 0.3],
+    [-158.0, 0.0, 0.0, 0.0],
+    [129.0, 0.1, -70.0, 0.0],
+    [123.0, 0.0, -53.0, 0.0],
+    [63.0, 0.0, 0.0, 0.0],
+    [63.0, 0.1, -33.0, 0.0],
+    [-59.0, 0.0, 26.0, 0.0],
+    [-58.0, -0.1, 32.0, 0.0],
+    [-51.0, 0.0, 27.0, 0.0],
+    [48.0, 0.0, 0.0, 0.0],
+    [46.0, 0.0, -24.0, 0.0],
+    [-38.0, 0.0, 16.0, 0.0],
+    [-31.0, 0.0, 13.0, 0.0],
+    [29.0, 0.0, 0.0, 0.0],
+    [29.0, 0.0, -12.0, 0.0],
+    [26.0, 0.0, 0.0, 0.0],
+    [-22.0, 0.0, 0.0, 0.0],
+    [21.0, 0.0, -10.0, 0.0],
+    [17.0, -0.1, 0.0, 0.0],
+    [16.0, 0.0, -8.0, 0.0],
+    [-16.0, 0.1, 7.0, 0.0],
+    [-15.0, 0.0, 9.0, 0.0],
+    [-13.0, 0.0, 7.0, 0.0],
+    [-12.0, 0.0, 6.0, 0.0],
+    [11.0, 0.0, 0.0, 0.0],
+    [-10.0, 0.0, 5.0, 0.0],
+    [-8.0, 0.0, 3.0, 0.0],
+    [7.0, 0.0, -3.0, 0.0],
+    [-7.0, 0.0, 0.0, 0.0],
+    [-7.0, 0.0, 3.0, 0.0],
+    [-7.0, 0.0, 3.0, 0.0],
+    [6.0, 0.0, 0.0, 0.0],
+    [6.0, 0.0, -3.0, 0.0],
+    [6.0, 0.0, -3.0, 0.0],
+    [-6.0, 0.0, 3.0, 0.0],
+    [-6.0, 0.0, 3.0, 0.0],
+    [5.0, 0.0, 0.0, 0.0],
+    [-5.0, 0.0, 3.0, 0.0],
+    [-5.0, 0.0, 3.0, 0.0],
+    [-5.0, 0.0, 3.0, 0.0],
+    [4.0, 0.0, 0.0, 0.0],
+    [4.0, 0.0, 0.0, 0.0],
+    [4.0, 0.0, 0.0, 0.0],
+    [-4.0, 0.0, 0.0, 0.0],
+    [-4.0, 0.0, 0.0, 0.0],
+    [-4.0, 0.0, 0.0, 0.0],
+    [3.0, 0.0, 0.0, 0.0],
+    [-3.0, 0.0, 0.0, 0.0],
+    [-3.0, 0.0, 0.0, 0.0],
+    [-3.0, 0.0, 0.0, 0.0],
+    [-3.0, 0.0, 0.0, 0.0],
+    [-3.0, 0.0, 0.0, 0.0],
+    [-3.0, 0.0, 0.0, 0.0],
+    [-3.0, 0.0, 0.0, 0.0],
+];
+
+// 0 ~ 360度の範囲に収める
+fn limit_degrees(degrees: f64) -> f64 {
+    degrees.rem_euclid(360.0)
+}
+
+// dtの時刻を、協定世界時からtimezone時間ずれた地方時とみなしたときのユリウス日
+pub fn julian_day(dt: &DateTime<Local>, timezone: f64) -> f64 {
+    let naive = dt.naive_local();
+    let mut year = naive.year() as f64;
+    let mut month = naive.month() as f64;
+    let day = naive.day() as f64
+        + (naive.hour() as f64 - timezone
+            + (naive.minute() as f64
+                + (naive.second() as f64 + naive.nanosecond() as f64 / 1e9) / 60.0)
+                / 60.0)
+            / 24.0;
+
+    if month < 3.0 {
+        month += 12.0;
+        year -= 1.0;
+    }
+
+    let mut jd =
+        (365.25 * (year + 4716.0)).floor() + (30.6001 * (month + 1.0)).floor() + day - 1524.5;
+    // グレゴリオ暦への補正
+    if jd > 2299160.0 {
+        let a = (year / 100.0).floor();
+        jd += 2.0 - a + (a / 4.0).floor();
+    }
+    jd
+}
+
+// 周期項の和を儒略千年の多項式として評価する
+fn earth_periodic_sum(terms: &[&[[f64; 3]]], jme: f64) -> f64 {
+    let sum = terms
+        .iter()
+        .enumerate()
+        .map(|(i, rows)| {
+            rows.iter()
+                .map(|[a, b, c]| a * (b + c * jme).cos())
+                .sum::<f64>()
+                * jme.powi(i as i32)
+        })
+        .sum::<f64>();
+    sum / 1e8
+}
+
+// 黄経の章動Δψと黄道傾斜角の章動Δε(度)
+fn nutation(jce: f64) -> (f64, f64) {
+    let x = [
+        297.85036 + 445267.111480 * jce - 0.0019142 * jce.powi(2) + jce.powi(3) / 189474.0,
+        357.52772 + 35999.050340 * jce - 0.0001603 * jce.powi(2) - jce.powi(3) / 300000.0,
+        134.96298 + 477198.867398 * jce + 0.0086972 * jce.powi(2) + jce.powi(3) / 56250.0,
+        93.27191 + 483202.017538 * jce - 0.0036825 * jce.powi(2) + jce.powi(3) / 327270.0,
+        125.04452 - 1934.136261 * jce + 0.0020708 * jce.powi(2) + jce.powi(3) / 450000.0,
+    ];
+
+    let (mut delta_psi, mut delta_epsilon) = (0.0, 0.0);
+    for (y, [a, b, c, d]) in NUTATION_Y_TERMS.iter().zip(NUTATION_PE_TERMS.iter()) {
+        let arg = y
+            .iter()
+            .zip(x.iter())
+            .map(|(y, x)| y * x)
+            .sum::<f64>()
+            .to_radians();
+        delta_psi += (a + b * jce) * arg.sin();
+        delta_epsilon += (c + d * jce) * arg.cos();
+    }
+
+    (delta_psi / 36000000.0, delta_epsilon / 36000000.0)
+}
+
+// 大気差による太陽高度の補正量(度)
+// e0: 補正前の太陽高度(度)、pressure: 気圧(hPa)、temperature: 気温(℃)
+pub fn refraction_correction(e0: f64, pressure: f64, temperature: f64, atmos_refract: f64) -> f64 {
+    if e0 < -(0.26667 + atmos_refract) {
+        // 太陽が地平線より十分下にあるときは補正しない
+        return 0.0;
+    }
+    (pressure / 1010.0) * (283.0 / (273.0 + temperature)) * 1.02
+        / (60.0 * (e0 + 10.3 / (e0 + 5.11)).to_radians().tan())
+}
+
+// timezone: dtの時刻の協定世界時からの時差(時間)
+pub fn calc_spa(
+    dt: &DateTime<Local>,
+    lat_deg: f64,
+    lng_deg: f64,
+    timezone: f64,
+    params: &SpaParams,
+) -> SpaResult {
+    let jd = julian_day(dt, timezone);
+    let jc = (jd - 2451545.0) / 36525.0;
+    let jde = jd + params.delta_t / 86400.0;
+    let jce = (jde - 2451545.0) / 36525.0;
+    let jme = jce / 10.0;
+
+    // 地球の日心黄経・日心黄緯・地心距離
+    let l = limit_degrees(earth_periodic_sum(&L_TERMS, jme).to_degrees());
+    let b = earth_periodic_sum(&B_TERMS, jme).to_degrees();
+    let r = earth_periodic_sum(&R_TERMS, jme);
+
+    // 太陽の地心黄経・地心黄緯
+    let theta = limit_degrees(l + 180.0);
+    let beta = -b;
+
+    let (delta_psi, delta_epsilon) = nutation(jce);
+
+    // 黄道傾斜角
+    let u = jme / 10.0;
+    let epsilon0 = 84381.448 - 4680.93 * u - 1.55 * u.powi(2) + 1999.25 * u.powi(3)
+        - 51.38 * u.powi(4)
+        - 249.67 * u.powi(5)
+        - 39.05 * u.powi(6)
+        + 7.12 * u.powi(7)
+        + 27.87 * u.powi(8)
+        + 5.79 * u.powi(9)
+        + 2.45 * u.powi(10);
+    let epsilon = epsilon0 / 3600.0 + delta_epsilon;
+
+    // 光行差を補正した太陽の視黄経
+    let delta_tau = -20.4898 / (3600.0 * r);
+    let lambda = theta + delta_psi + delta_tau;
+
+    // グリニッジ視恒星時
+    let nu0 = limit_degrees(
+        280.46061837 + 360.98564736629 * (jd - 2451545.0) + 0.000387933 * jc.powi(2)
+            - jc.powi(3) / 38710000.0,
+    );
+    let nu = nu0 + delta_psi * epsilon.to_radians().cos();
+
+    // 地心赤経・地心赤緯
+    let (lambda_rad, epsilon_rad, beta_rad) =
+        (lambda.to_radians(), epsilon.to_radians(), beta.to_radians());
+    let alpha = limit_degrees(
+        (lambda_rad.sin() * epsilon_rad.cos() - beta_rad.tan() * epsilon_rad.sin())
+            .atan2(lambda_rad.cos())
+            .to_degrees(),
+    );
+    let delta = (beta_rad.sin() * epsilon_rad.cos()
+        + beta_rad.cos() * epsilon_rad.sin() * lambda_rad.sin())
+    .asin()
+    .to_degrees();
+
+    // 地心での時角
+    let h = limit_degrees(nu + lng_deg - alpha);
+
+    // 観測地点の位置による視差の補正
+    let xi = (8.794 / (3600.0 * r)).to_radians();
+    let phi = lat_deg.to_radians();
+    let u = (0.99664719 * phi.tan()).atan();
+    let x = u.cos() + params.elevation / 6378140.0 * phi.cos();
+    let y = 0.99664719 * u.sin() + params.elevation / 6378140.0 * phi.sin();
+
+    let (h_rad, delta_rad) = (h.to_radians(), delta.to_radians());
+    let delta_alpha = (-x * xi.sin() * h_rad.sin())
+        .atan2(delta_rad.cos() - x * xi.sin() * h_rad.cos())
+        .to_degrees();
+    let alpha_prime = alpha + delta_alpha;
+    let delta_prime = ((delta_rad.sin() - y * xi.sin()) * delta_alpha.to_radians().cos())
+        .atan2(delta_rad.cos() - x * xi.sin() * h_rad.cos())
+        .to_degrees();
+    let h_prime = h - delta_alpha;
+
+    // 太陽高度と天頂角
+    let (h_prime_rad, delta_prime_rad) = (h_prime.to_radians(), delta_prime.to_radians());
+    let e0 = (phi.sin() * delta_prime_rad.sin()
+        + phi.cos() * delta_prime_rad.cos() * h_prime_rad.cos())
+    .asin()
+    .to_degrees();
+    let elevation = e0
+        + refraction_correction(
+            e0,
+            params.pressure,
+            params.temperature,
+            params.atmos_refract,
+        );
+    let zenith = 90.0 - elevation;
+
+    // 方位角(南から西回りの値を北基準に直す)
+    let gamma = h_prime_rad
+        .sin()
+        .atan2(h_prime_rad.cos() * phi.sin() - delta_prime_rad.tan() * phi.cos())
+        .to_degrees();
+    let azimuth = limit_degrees(gamma + 180.0);
+
+    SpaResult {
+        jd,
+        l,
+        b,
+        r,
+        delta_psi,
+        delta_epsilon,
+        epsilon,
+        alpha_prime,
+        delta_prime,
+        h_prime,
+        e0,
+        elevation,
+        zenith,
+        azimuth,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    // Reda & Andreas (2004) の付録A.5の例
+    #[test]
+    fn matches_reference_example() {
+        let naive = NaiveDate::from_ymd_opt(2003, 10, 17)
+            .unwrap()
+            .and_hms_opt(12, 30, 30)
+            .unwrap();
+        let dt = Local.from_local_datetime(&naive).unwrap();
+        let params = SpaParams {
+            delta_t: 67.0,
+            ..SpaParams::new(1830.14, 820.0, 11.0)
+        };
+        let result = calc_spa(&dt, 39.742476, -105.1786, -7.0, &params);
+
+        assert!((result.jd - 2452930.312847).abs() < 1e-6);
+        assert!((result.l - 24.0182616917).abs() < 1e-6);
+        assert!((result.r - 0.9965422974).abs() < 1e-8);
+        assert!((result.delta_psi - -0.00399840).abs() < 1e-7);
+        assert!((result.delta_epsilon - 0.00166657).abs() < 1e-7);
+        assert!((result.epsilon - 23.440465).abs() < 1e-6);
+        assert!((result.zenith - 50.11162).abs() < 1e-4);
+        assert!((result.azimuth - 194.34024).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,208 @@
+use std::ops::Range;
+
+use chrono::{DateTime, Local, NaiveDate};
+
+use crate::error::{Error, Result};
+use crate::es::Field;
+use crate::qc::QcFlags;
+
+// 共通の時刻の列と、項目ごとの値の列
+#[derive(Debug, Clone, Default)]
+pub struct TimeSeries {
+    dts: Vec<DateTime<Local>>,
+    columns: Vec<(Field, Vec<f64>)>,
+    // 品質管理の判定結果。判定した項目だけ持つ
+    flags: Vec<(Field, Vec<QcFlags>)>,
+    // 計測した時刻か。読み込むときに欠損を補完した時刻はfalse
+    measured: Vec<bool>,
+    // 品質管理で除くと判定した値をvalidでNoneにするか
+    exclude_flagged: bool,
+}
+
+impl TimeSeries {
+    pub fn new(dts: Vec<DateTime<Local>>) -> Self {
+        TimeSeries {
+            measured: vec![true; dts.len()],
+            dts,
+            columns: Vec::new(),
+            flags: Vec::new(),
+            exclude_flagged: false,
+        }
+    }
+
+    // 欠損を補完した時刻をfalseにした列を設定する
+    pub fn set_measured(&mut self, measured: Vec<bool>) -> Result<()> {
+        if measured.len() != self.dts.len() {
+            return Err(Error::MissingData(format!(
+                "計測したかどうかの件数({})が時刻の件数({})と一致しない",
+                measured.len(),
+                self.dts.len()
+            )));
+        }
+        self.measured = measured;
+        Ok(())
+    }
+
+    pub fn measured(&self) -> &[bool] {
+        &self.measured
+    }
+
+    // 同じ項目がすでにあるときは置き換える
+    pub fn with_column(mut self, field: Field, values: Vec<f64>) -> Result<Self> {
+        self.insert_column(field, values)?;
+        Ok(self)
+    }
+
+    pub fn insert_column(&mut self, field: Field, values: Vec<f64>) -> Result<()> {
+        if values.len() != self.dts.len() {
+            return Err(Error::MissingData(format!(
+                "{} の件数({})が時刻の件数({})と一致しない",
+                field,
+                values.len(),
+                self.dts.len()
+            )));
+        }
+        match self.columns.iter_mut().find(|(f, _)| *f == field) {
+            Some((_, column)) => *column = values,
+            None => self.columns.push((field, values)),
+        }
+        Ok(())
+    }
+
+    pub fn set_flags(&mut self, field: Field, flags: Vec<QcFlags>) -> Result<()> {
+        if flags.len() != self.dts.len() {
+            return Err(Error::MissingData(format!(
+                "{} の判定結果の件数({})が時刻の件数({})と一致しない",
+                field,
+                flags.len(),
+                self.dts.len()
+            )));
+        }
+        match self.flags.iter_mut().find(|(f, _)| *f == field) {
+            Some((_, column)) => *column = flags,
+            None => self.flags.push((field, flags)),
+        }
+        Ok(())
+    }
+
+    // 品質管理の判定をしていない項目はNone
+    pub fn flags(&self, field: Field) -> Option<&[QcFlags]> {
+        self.flags
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, flags)| flags.as_slice())
+    }
+
+    // 以後、品質管理で除くと判定した値をvalidでNoneにする。値そのものは変えない
+    // 除く値の件数を返す
+    pub fn exclude_flagged(&mut self) -> usize {
+        self.exclude_flagged = true;
+        self.flags
+            .iter()
+            .map(|(_, flags)| flags.iter().filter(|flags| flags.is_excluded()).count())
+            .sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.dts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dts.is_empty()
+    }
+
+    pub fn dts(&self) -> &[DateTime<Local>] {
+        &self.dts
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = Field> + '_ {
+        self.columns.iter().map(|(field, _)| *field)
+    }
+
+    pub fn column(&self, field: Field) -> Option<&[f64]> {
+        self.columns
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    // 読み込んでいない項目を指定したときはエラーにする
+    pub fn require(&self, field: Field) -> Result<&[f64]> {
+        self.column(field)
+            .ok_or_else(|| Error::MissingData(format!("項目 {}", field)))
+    }
+
+    // 欠損を補完した時刻だけをNoneにした値の列。品質管理の判定に関わらず計測した値をすべて使う
+    pub fn recorded(&self, field: Field) -> Result<Vec<Option<f64>>> {
+        Ok(self
+            .require(field)?
+            .iter()
+            .zip(self.measured.iter())
+            .map(|(value, measured)| measured.then_some(*value))
+            .collect())
+    }
+
+    // 欠損を補完した時刻と、exclude_flaggedの後なら品質管理で除くと判定した値をNoneにした値の列
+    pub fn valid(&self, field: Field) -> Result<Vec<Option<f64>>> {
+        let flags = self.flags(field).filter(|_| self.exclude_flagged);
+        Ok(self
+            .require(field)?
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let excluded = flags.is_some_and(|flags| flags[i].is_excluded());
+                (self.measured[i] && !excluded).then_some(*value)
+            })
+            .collect())
+    }
+
+    // 日付ごとのインデックスの範囲(時刻の昇順に並んでいることが前提)
+    pub fn day_ranges(&self) -> Vec<(NaiveDate, Range<usize>)> {
+        day_ranges(&self.dts)
+    }
+
+    // rangeの範囲を切り出した新しい系列
+    pub fn slice(&self, range: Range<usize>) -> TimeSeries {
+        TimeSeries {
+            dts: self.dts[range.clone()].to_vec(),
+            columns: self
+                .columns
+                .iter()
+                .map(|(field, values)| (*field, values[range.clone()].to_vec()))
+                .collect(),
+            flags: self
+                .flags
+                .iter()
+                .map(|(field, flags)| (*field, flags[range.clone()].to_vec()))
+                .collect(),
+            measured: self.measured[range].to_vec(),
+            exclude_flagged: self.exclude_flagged,
+        }
+    }
+}
+
+// 時刻の列の日付ごとのインデックスの範囲(時刻の昇順に並んでいることが前提)
+pub fn day_ranges(dts: &[DateTime<Local>]) -> Vec<(NaiveDate, Range<usize>)> {
+    let mut ranges: Vec<(NaiveDate, Range<usize>)> = Vec::new();
+    for (i, dt) in dts.iter().enumerate() {
+        let date = dt.date_naive();
+        match ranges.last_mut() {
+            Some((last_date, range)) if *last_date == date => range.end = i + 1,
+            _ => ranges.push((date, i..i + 1)),
+        }
+    }
+    ranges
+}
+
+// 時刻の列の1分ごとのインデックスの範囲(時刻の昇順に並んでいることが前提)
+pub fn minute_ranges(dts: &[DateTime<Local>]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<(i64, Range<usize>)> = Vec::new();
+    for (i, dt) in dts.iter().enumerate() {
+        let minute = dt.timestamp().div_euclid(60);
+        match ranges.last_mut() {
+            Some((last_minute, range)) if *last_minute == minute => range.end = i + 1,
+            _ => ranges.push((minute, i..i + 1)),
+        }
+    }
+    ranges.into_iter().map(|(_, range)| range).collect()
+}
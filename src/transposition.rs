@@ -0,0 +1,227 @@
+// 水平面の日射量から傾斜したパネル面(POA)の日射量を求める
+// 日射量の単位は全てW/m^2、角度の単位は度
+use serde::Deserialize;
+
+// 天空からの散乱日射の計算方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkyDiffuseModel {
+    // 天空全体が一様に明るいとみなす
+    Isotropic,
+    // 太陽周辺の明るさを考慮する
+    HayDavies,
+    // 太陽周辺と地平線付近の明るさを考慮する
+    #[default]
+    Perez,
+}
+
+impl std::str::FromStr for SkyDiffuseModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "isotropic" => Ok(SkyDiffuseModel::Isotropic),
+            "hay-davies" => Ok(SkyDiffuseModel::HayDavies),
+            "perez" => Ok(SkyDiffuseModel::Perez),
+            _ => Err(format!(
+                "不明な散乱日射モデル \"{}\" です (指定可能な値: isotropic, hay-davies, perez)",
+                s
+            )),
+        }
+    }
+}
+
+// パネル面の日射量
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct PoaIrradiance {
+    pub global: f64,
+    // 直達成分
+    pub direct: f64,
+    // 天空からの散乱成分
+    pub sky_diffuse: f64,
+    // 地面からの反射成分
+    pub ground_diffuse: f64,
+}
+
+// 計算に使う太陽の位置と水平面の日射量
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TranspositionInputs {
+    // 大気差を補正した天頂角
+    pub solar_zenith: f64,
+    // 太陽の方位角(北が0度、南が180度)
+    pub solar_azimuth: f64,
+    pub ghi: f64,
+    pub dni: f64,
+    pub dhi: f64,
+    // 大気外の法線面日射量
+    pub dni_extra: f64,
+    // 相対エアマス(Perezモデルだけで使う)
+    pub airmass: f64,
+}
+
+// Perez (1990) の全地点の係数 (f11, f12, f13, f21, f22, f23)
+const PEREZ_COEFFICIENTS: [[f64; 6]; 8] = [
+    [-0.0080, 0.5880, -0.0620, -0.0600, 0.0720, -0.0220],
+    [0.1300, 0.6830, -0.1510, -0.0190, 0.0660, -0.0290],
+    [0.3300, 0.4870, -0.2210, 0.0550, -0.0640, -0.0260],
+    [0.5680, 0.1870, -0.2950, 0.1090, -0.1520, -0.0140],
+    [0.8730, -0.3920, -0.3620, 0.2260, -0.4620, 0.0010],
+    [1.1320, -1.2370, -0.4120, 0.2880, -0.8230, 0.0560],
+    [1.0600, -1.6000, -0.3590, 0.2640, -1.1270, 0.1310],
+    [0.6780, -0.3270, -0.2500, 0.1560, -1.3770, 0.2510],
+];
+
+// 晴天指数εの区分の上限(最後の区分は上限なし)
+const PEREZ_EPSILON_BINS: [f64; 7] = [1.065, 1.23, 1.5, 1.95, 2.8, 4.5, 6.2];
+
+// 太陽光のパネル面への入射角のcos
+// tilt: パネルの傾斜角(水平が0度)、surface_azimuth: パネルの方位角(北が0度、南が180度)
+pub fn calc_cos_aoi(tilt: f64, surface_azimuth: f64, solar_zenith: f64, solar_azimuth: f64) -> f64 {
+    let tilt = tilt.to_radians();
+    let zenith = solar_zenith.to_radians();
+    let cos_aoi = tilt.cos() * zenith.cos()
+        + tilt.sin() * zenith.sin() * (solar_azimuth - surface_azimuth).to_radians().cos();
+    cos_aoi.clamp(-1.0, 1.0)
+}
+
+// 入射角(度)
+pub fn calc_aoi(tilt: f64, surface_azimuth: f64, solar_zenith: f64, solar_azimuth: f64) -> f64 {
+    calc_cos_aoi(tilt, surface_azimuth, solar_zenith, solar_azimuth)
+        .acos()
+        .to_degrees()
+}
+
+pub fn calc_poa(
+    model: SkyDiffuseModel,
+    tilt: f64,
+    surface_azimuth: f64,
+    albedo: f64,
+    inputs: &TranspositionInputs,
+) -> PoaIrradiance {
+    let cos_aoi = calc_cos_aoi(
+        tilt,
+        surface_azimuth,
+        inputs.solar_zenith,
+        inputs.solar_azimuth,
+    );
+    let direct = (inputs.dni * cos_aoi).max(0.0);
+    let sky_diffuse = match model {
+        SkyDiffuseModel::Isotropic => isotropic(tilt, inputs.dhi),
+        SkyDiffuseModel::HayDavies => hay_davies(tilt, cos_aoi, inputs),
+        SkyDiffuseModel::Perez => perez(tilt, cos_aoi, inputs),
+    };
+    let ground_diffuse = ground_diffuse(tilt, inputs.ghi, albedo);
+
+    PoaIrradiance {
+        global: direct + sky_diffuse + ground_diffuse,
+        direct,
+        sky_diffuse,
+        ground_diffuse,
+    }
+}
+
+pub fn isotropic(tilt: f64, dhi: f64) -> f64 {
+    dhi * (1.0 + tilt.to_radians().cos()) / 2.0
+}
+
+// Hay & Davies (1980)
+pub fn hay_davies(tilt: f64, cos_aoi: f64, inputs: &TranspositionInputs) -> f64 {
+    // 天頂角が89度を超えるときは89度として扱う
+    let cos_zenith = inputs.solar_zenith.to_radians().cos().max(0.01745);
+    let anisotropy = (inputs.dni / inputs.dni_extra).clamp(0.0, 1.0);
+    let rb = cos_aoi.max(0.0) / cos_zenith;
+    let sky_diffuse =
+        inputs.dhi * (anisotropy * rb + (1.0 - anisotropy) * (1.0 + tilt.to_radians().cos()) / 2.0);
+    sky_diffuse.max(0.0)
+}
+
+// Perez et al. (1990)
+pub fn perez(tilt: f64, cos_aoi: f64, inputs: &TranspositionInputs) -> f64 {
+    if inputs.dhi <= 0.0 || inputs.solar_zenith > 90.0 {
+        return 0.0;
+    }
+    let kappa = 1.041;
+    let z = inputs.solar_zenith.to_radians();
+
+    // 天空の明るさΔと晴天指数ε
+    let brightness = inputs.dhi * inputs.airmass / inputs.dni_extra;
+    let epsilon =
+        ((inputs.dhi + inputs.dni) / inputs.dhi + kappa * z.powi(3)) / (1.0 + kappa * z.powi(3));
+    let bin = PEREZ_EPSILON_BINS
+        .iter()
+        .take_while(|upper| epsilon >= **upper)
+        .count();
+    let [f11, f12, f13, f21, f22, f23] = PEREZ_COEFFICIENTS[bin];
+
+    // 太陽周辺と地平線付近の明るさの係数
+    let f1 = (f11 + f12 * brightness + f13 * z).max(0.0);
+    let f2 = f21 + f22 * brightness + f23 * z;
+
+    let tilt = tilt.to_radians();
+    let a = cos_aoi.max(0.0);
+    let b = z.cos().max(85.0_f64.to_radians().cos());
+    let sky_diffuse =
+        inputs.dhi * ((1.0 - f1) * (1.0 + tilt.cos()) / 2.0 + f1 * a / b + f2 * tilt.sin());
+    sky_diffuse.max(0.0)
+}
+
+// 地面で反射してパネル面に届く日射量
+pub fn ground_diffuse(tilt: f64, ghi: f64, albedo: f64) -> f64 {
+    ghi * albedo * (1.0 - tilt.to_radians().cos()) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::q;
+
+    // 傾斜角30度・真南向きのパネル、天頂角40度・方位角150度の太陽
+    // 法線面直達日射量800W/m^2、散乱日射量150W/m^2、大気外日射量1367W/m^2
+    fn inputs() -> TranspositionInputs {
+        let zenith: f64 = 40.0;
+        TranspositionInputs {
+            solar_zenith: zenith,
+            solar_azimuth: 150.0,
+            ghi: 800.0 * zenith.to_radians().cos() + 150.0,
+            dni: 800.0,
+            dhi: 150.0,
+            dni_extra: 1367.0,
+            airmass: q::calc_relative_airmass(zenith),
+        }
+    }
+
+    fn cos_aoi(tilt: f64) -> f64 {
+        let inputs = inputs();
+        calc_cos_aoi(tilt, 180.0, inputs.solar_zenith, inputs.solar_azimuth)
+    }
+
+    #[test]
+    fn hay_davies_matches_hand_calculation() {
+        // Hay & Davies (1980) の式を手計算した値
+        let inputs = inputs();
+        assert!((cos_aoi(30.0) - 0.941749).abs() < 1e-6);
+        assert!((hay_davies(30.0, cos_aoi(30.0), &inputs) - 165.9668).abs() < 1e-3);
+        // 水平面では散乱日射量そのもの
+        assert!((hay_davies(0.0, cos_aoi(0.0), &inputs) - inputs.dhi).abs() < 1e-9);
+    }
+
+    #[test]
+    fn perez_matches_hand_calculation() {
+        // Perez et al. (1990) の式と全地点の係数で手計算した値
+        // 晴天指数ε=4.938で7番目の区分、F1=0.5804、F2=0.1942
+        let inputs = inputs();
+        assert!((perez(30.0, cos_aoi(30.0), &inputs) - 180.3147).abs() < 1e-3);
+        // 水平面では散乱日射量そのもの
+        assert!((perez(0.0, cos_aoi(0.0), &inputs) - inputs.dhi).abs() < 1e-9);
+    }
+
+    #[test]
+    fn poa_adds_ground_reflection() {
+        let inputs = inputs();
+        let poa = calc_poa(SkyDiffuseModel::Perez, 30.0, 180.0, 0.2, &inputs);
+        assert!((poa.direct - 800.0 * 0.941749).abs() < 1e-3);
+        assert!((poa.ground_diffuse - 10.2201).abs() < 1e-3);
+        assert!((poa.global - (poa.direct + poa.sky_diffuse + poa.ground_diffuse)).abs() < 1e-9);
+    }
+}